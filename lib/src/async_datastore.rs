@@ -0,0 +1,453 @@
+//! An async wrapper around a datastore, for use on tokio runtimes.
+
+use std::sync::Arc;
+
+use crate::errors::{Error, Result};
+use crate::models;
+use crate::models::{EdgeQueryExt, VertexQueryExt};
+use crate::traits::Datastore;
+
+use uuid::Uuid;
+
+/// Wraps a datastore so that its blocking calls run on tokio's blocking
+/// thread pool, rather than stalling the async executor. Each method returns
+/// a future that resolves once the underlying call finishes.
+///
+/// If a call panics, the panic is returned as an `Error::Datastore`.
+#[derive(Debug)]
+pub struct AsyncDatastore<D: Datastore + Send + Sync + 'static> {
+    datastore: Arc<D>,
+}
+
+impl<D: Datastore + Send + Sync + 'static> Clone for AsyncDatastore<D> {
+    fn clone(&self) -> Self {
+        Self {
+            datastore: self.datastore.clone(),
+        }
+    }
+}
+
+impl<D: Datastore + Send + Sync + 'static> AsyncDatastore<D> {
+    /// Creates a new async datastore.
+    ///
+    /// # Arguments
+    /// * `datastore`: The underlying datastore.
+    pub fn new(datastore: Arc<D>) -> Self {
+        Self { datastore }
+    }
+
+    /// Gets the underlying datastore.
+    pub fn inner(&self) -> &Arc<D> {
+        &self.datastore
+    }
+
+    /// Runs an arbitrary operation against the underlying datastore on the
+    /// blocking thread pool.
+    ///
+    /// # Arguments
+    /// * `f`: The operation to run.
+    pub async fn run<F, T>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&D) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let datastore = self.datastore.clone();
+        match tokio::task::spawn_blocking(move || f(&datastore)).await {
+            Ok(res) => res,
+            Err(err) => Err(Error::Datastore(Box::new(err))),
+        }
+    }
+
+    /// Syncs persisted content. Depending on the datastore implementation,
+    /// this has different meanings - including potentially being a no-op.
+    pub async fn sync(&self) -> Result<()> {
+        self.run(|datastore| datastore.sync()).await
+    }
+
+    /// Gets a vertex by its ID.
+    ///
+    /// # Arguments
+    /// * `id`: The ID of the vertex.
+    pub async fn get_vertex(&self, id: Uuid) -> Result<Option<models::Vertex>> {
+        self.run(move |datastore| {
+            let vertices = datastore.get_vertices(models::SpecificVertexQuery::single(id).into())?;
+            Ok(vertices.into_iter().next())
+        })
+        .await
+    }
+
+    /// Creates a new vertex. Returns whether the vertex was successfully
+    /// created - if this is false, it's because a vertex with the same UUID
+    /// already exists.
+    ///
+    /// # Arguments
+    /// * `vertex`: The vertex to create.
+    pub async fn create_vertex(&self, vertex: models::Vertex) -> Result<bool> {
+        self.run(move |datastore| datastore.create_vertex(&vertex)).await
+    }
+
+    /// Gets a range of vertices specified by a query.
+    ///
+    /// # Arguments
+    /// * `q`: The query to run.
+    pub async fn get_vertices(&self, q: models::VertexQuery) -> Result<Vec<models::Vertex>> {
+        self.run(move |datastore| datastore.get_vertices(q)).await
+    }
+
+    /// Deletes existing vertices specified by a query.
+    ///
+    /// # Arguments
+    /// * `q`: The query to run.
+    pub async fn delete_vertices(&self, q: models::VertexQuery) -> Result<()> {
+        self.run(move |datastore| datastore.delete_vertices(q)).await
+    }
+
+    /// Gets the number of vertices in the datastore.
+    pub async fn get_vertex_count(&self) -> Result<u64> {
+        self.run(|datastore| datastore.get_vertex_count()).await
+    }
+
+    /// Creates a new edge. If the edge already exists, this will update it
+    /// with a new update datetime. Returns whether the edge was successfully
+    /// created - if this is false, it's because one of the specified vertices
+    /// is missing.
+    ///
+    /// # Arguments
+    /// * `key`: The edge to create.
+    pub async fn create_edge(&self, key: models::EdgeKey) -> Result<bool> {
+        self.run(move |datastore| datastore.create_edge(&key)).await
+    }
+
+    /// Gets a range of edges specified by a query.
+    ///
+    /// # Arguments
+    /// * `q`: The query to run.
+    pub async fn get_edges(&self, q: models::EdgeQuery) -> Result<Vec<models::Edge>> {
+        self.run(move |datastore| datastore.get_edges(q)).await
+    }
+
+    /// Deletes a set of edges specified by a query.
+    ///
+    /// # Arguments
+    /// * `q`: The query to run.
+    pub async fn delete_edges(&self, q: models::EdgeQuery) -> Result<()> {
+        self.run(move |datastore| datastore.delete_edges(q)).await
+    }
+
+    /// Gets the number of edges associated with a vertex.
+    ///
+    /// # Arguments
+    /// * `id`: The id of the vertex.
+    /// * `t`: Only get the count for a specified edge type.
+    /// * `direction`: The direction of edges to get.
+    pub async fn get_edge_count(
+        &self,
+        id: Uuid,
+        t: Option<models::Identifier>,
+        direction: models::EdgeDirection,
+    ) -> Result<u64> {
+        self.run(move |datastore| datastore.get_edge_count(id, t.as_ref(), direction))
+            .await
+    }
+
+    /// Gets a vertex property.
+    ///
+    /// # Arguments
+    /// * `id`: The ID of the vertex.
+    /// * `name`: The property name.
+    pub async fn get_vertex_property(&self, id: Uuid, name: models::Identifier) -> Result<Option<serde_json::Value>> {
+        self.run(move |datastore| {
+            let q = models::SpecificVertexQuery::single(id).property(name);
+            let properties = datastore.get_vertex_properties(q)?;
+            Ok(properties.into_iter().next().map(|property| property.value))
+        })
+        .await
+    }
+
+    /// Sets a vertex property.
+    ///
+    /// # Arguments
+    /// * `id`: The ID of the vertex.
+    /// * `name`: The property name.
+    /// * `value`: The property value.
+    pub async fn set_vertex_property(
+        &self,
+        id: Uuid,
+        name: models::Identifier,
+        value: serde_json::Value,
+    ) -> Result<()> {
+        self.run(move |datastore| {
+            let q = models::SpecificVertexQuery::single(id).property(name);
+            datastore.set_vertex_properties(q, value)
+        })
+        .await
+    }
+
+    /// Gets all vertex properties.
+    ///
+    /// # Arguments
+    /// * `q`: The query to run.
+    pub async fn get_all_vertex_properties(&self, q: models::VertexQuery) -> Result<Vec<models::VertexProperties>> {
+        self.run(move |datastore| datastore.get_all_vertex_properties(q)).await
+    }
+
+    /// Deletes vertex properties.
+    ///
+    /// # Arguments
+    /// * `q`: The query to run.
+    pub async fn delete_vertex_properties(&self, q: models::VertexPropertyQuery) -> Result<()> {
+        self.run(move |datastore| datastore.delete_vertex_properties(q)).await
+    }
+
+    /// Gets an edge property.
+    ///
+    /// # Arguments
+    /// * `key`: The edge.
+    /// * `name`: The property name.
+    pub async fn get_edge_property(
+        &self,
+        key: models::EdgeKey,
+        name: models::Identifier,
+    ) -> Result<Option<serde_json::Value>> {
+        self.run(move |datastore| {
+            let q = models::SpecificEdgeQuery::single(key).property(name);
+            let properties = datastore.get_edge_properties(q)?;
+            Ok(properties.into_iter().next().map(|property| property.value))
+        })
+        .await
+    }
+
+    /// Sets an edge property.
+    ///
+    /// # Arguments
+    /// * `key`: The edge.
+    /// * `name`: The property name.
+    /// * `value`: The property value.
+    pub async fn set_edge_property(
+        &self,
+        key: models::EdgeKey,
+        name: models::Identifier,
+        value: serde_json::Value,
+    ) -> Result<()> {
+        self.run(move |datastore| {
+            let q = models::SpecificEdgeQuery::single(key).property(name);
+            datastore.set_edge_properties(q, value)
+        })
+        .await
+    }
+
+    /// Gets all edge properties.
+    ///
+    /// # Arguments
+    /// * `q`: The query to run.
+    pub async fn get_all_edge_properties(&self, q: models::EdgeQuery) -> Result<Vec<models::EdgeProperties>> {
+        self.run(move |datastore| datastore.get_all_edge_properties(q)).await
+    }
+
+    /// Deletes edge properties.
+    ///
+    /// # Arguments
+    /// * `q`: The query to run.
+    pub async fn delete_edge_properties(&self, q: models::EdgePropertyQuery) -> Result<()> {
+        self.run(move |datastore| datastore.delete_edge_properties(q)).await
+    }
+
+    /// Bulk inserts many vertices, edges, and/or properties.
+    ///
+    /// # Arguments
+    /// * `items`: The items to insert.
+    pub async fn bulk_insert(&self, items: Vec<models::BulkInsertItem>) -> Result<()> {
+        self.run(move |datastore| datastore.bulk_insert(items)).await
+    }
+
+    /// Enables indexing on a specified property. When indexing is enabled on
+    /// a property, it's possible to query on its presence and values.
+    ///
+    /// # Arguments
+    /// * `name`: The name of the property to index.
+    pub async fn index_property(&self, name: models::Identifier) -> Result<()> {
+        self.run(move |datastore| datastore.index_property(name)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::AsyncDatastore;
+    use crate::{
+        EdgeDirection, EdgeKey, EdgeQueryExt, Error, Identifier, MemoryDatastore, PropertyValueVertexQuery,
+        SpecificEdgeQuery, SpecificVertexQuery, Vertex, VertexQueryExt,
+    };
+
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn should_create_and_get_through_async_wrappers() {
+        let datastore = AsyncDatastore::new(Arc::new(MemoryDatastore::default()));
+        let t = Identifier::new("test_type").unwrap();
+        let name = Identifier::new("test_property").unwrap();
+
+        let outbound_v = Vertex::new(t.clone());
+        let inbound_v = Vertex::new(t.clone());
+        assert!(datastore.create_vertex(outbound_v.clone()).await.unwrap());
+        assert!(datastore.create_vertex(inbound_v.clone()).await.unwrap());
+        assert_eq!(
+            datastore.get_vertex(outbound_v.id).await.unwrap(),
+            Some(outbound_v.clone())
+        );
+        assert_eq!(
+            datastore.get_vertex(crate::util::generate_uuid_v1()).await.unwrap(),
+            None
+        );
+
+        datastore
+            .set_vertex_property(outbound_v.id, name.clone(), json!(true))
+            .await
+            .unwrap();
+        assert_eq!(
+            datastore
+                .get_vertex_property(outbound_v.id, name.clone())
+                .await
+                .unwrap(),
+            Some(json!(true))
+        );
+
+        let key = EdgeKey::new(outbound_v.id, t, inbound_v.id);
+        assert!(datastore.create_edge(key.clone()).await.unwrap());
+        assert_eq!(
+            datastore.get_edge_property(key.clone(), name.clone()).await.unwrap(),
+            None
+        );
+        datastore
+            .set_edge_property(key.clone(), name.clone(), json!(1))
+            .await
+            .unwrap();
+        assert_eq!(datastore.get_edge_property(key, name).await.unwrap(), Some(json!(1)));
+    }
+
+    #[tokio::test]
+    async fn should_run_concurrent_reads_and_writes() {
+        let datastore = AsyncDatastore::new(Arc::new(MemoryDatastore::default()));
+        let t = Identifier::new("test_type").unwrap();
+        let hub = Vertex::new(t.clone());
+        assert!(datastore.create_vertex(hub.clone()).await.unwrap());
+
+        let handles: Vec<_> = (0..10)
+            .map(|_| {
+                let datastore = datastore.clone();
+                let t = t.clone();
+                tokio::spawn(async move {
+                    let vertex = Vertex::new(t.clone());
+                    assert!(datastore.create_vertex(vertex.clone()).await.unwrap());
+                    let key = EdgeKey::new(hub.id, t, vertex.id);
+                    assert!(datastore.create_edge(key.clone()).await.unwrap());
+                    let q = SpecificVertexQuery::single(vertex.id).into();
+                    assert_eq!(datastore.get_vertices(q).await.unwrap(), vec![vertex]);
+                    key
+                })
+            })
+            .collect();
+
+        let mut keys = Vec::new();
+        for handle in handles {
+            keys.push(handle.await.unwrap());
+        }
+
+        assert_eq!(datastore.get_vertex_count().await.unwrap(), 11);
+        let q = SpecificVertexQuery::single(hub.id).outbound().limit(100).into();
+        let mut edge_keys: Vec<_> = datastore
+            .get_edges(q)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|edge| edge.key)
+            .collect();
+        edge_keys.sort_by_key(|key| key.inbound_id);
+        keys.sort_by_key(|key| key.inbound_id);
+        assert_eq!(edge_keys, keys);
+
+        datastore
+            .delete_edges(SpecificEdgeQuery::new(keys).into())
+            .await
+            .unwrap();
+        let q = SpecificVertexQuery::single(hub.id).outbound().limit(100).into();
+        assert!(datastore.get_edges(q).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_index_and_delete_through_async_wrappers() {
+        let datastore = AsyncDatastore::new(Arc::new(MemoryDatastore::default()));
+        let t = Identifier::new("test_type").unwrap();
+        let name = Identifier::new("test_property").unwrap();
+        let outbound_v = Vertex::new(t.clone());
+        let inbound_v = Vertex::new(t.clone());
+        assert!(datastore.create_vertex(outbound_v.clone()).await.unwrap());
+        assert!(datastore.create_vertex(inbound_v.clone()).await.unwrap());
+        let key = EdgeKey::new(outbound_v.id, t.clone(), inbound_v.id);
+        assert!(datastore.create_edge(key.clone()).await.unwrap());
+        assert_eq!(
+            datastore
+                .get_edge_count(outbound_v.id, Some(t), EdgeDirection::Outbound)
+                .await
+                .unwrap(),
+            1
+        );
+
+        datastore
+            .set_vertex_property(outbound_v.id, name.clone(), json!(1))
+            .await
+            .unwrap();
+        datastore
+            .set_edge_property(key.clone(), name.clone(), json!(2))
+            .await
+            .unwrap();
+
+        let q = PropertyValueVertexQuery::new(name.clone(), json!(1));
+        assert!(matches!(
+            datastore.get_vertices(q.clone().into()).await,
+            Err(Error::NotIndexed)
+        ));
+        datastore.index_property(name.clone()).await.unwrap();
+        assert_eq!(
+            datastore.get_vertices(q.clone().into()).await.unwrap(),
+            vec![outbound_v.clone()]
+        );
+
+        let vertex_q = SpecificVertexQuery::single(outbound_v.id);
+        let properties = datastore
+            .get_all_vertex_properties(vertex_q.clone().into())
+            .await
+            .unwrap();
+        assert_eq!(properties[0].props.len(), 1);
+        datastore
+            .delete_vertex_properties(vertex_q.clone().property(name.clone()))
+            .await
+            .unwrap();
+        let properties = datastore.get_all_vertex_properties(vertex_q.into()).await.unwrap();
+        assert!(properties[0].props.is_empty());
+        assert!(datastore.get_vertices(q.into()).await.unwrap().is_empty());
+
+        let edge_q = SpecificEdgeQuery::single(key);
+        let properties = datastore.get_all_edge_properties(edge_q.clone().into()).await.unwrap();
+        assert_eq!(properties[0].props.len(), 1);
+        datastore
+            .delete_edge_properties(edge_q.clone().property(name))
+            .await
+            .unwrap();
+        let properties = datastore.get_all_edge_properties(edge_q.into()).await.unwrap();
+        assert!(properties[0].props.is_empty());
+
+        datastore.sync().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn should_return_panics_as_errors() {
+        let datastore = AsyncDatastore::new(Arc::new(MemoryDatastore::default()));
+        let res: Result<(), Error> = datastore.run(|_| panic!("test panic")).await;
+        match res {
+            Err(Error::Datastore(_)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
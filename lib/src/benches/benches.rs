@@ -28,6 +28,15 @@ pub fn bench_get_vertices<D: Datastore>(b: &mut Bencher, datastore: &mut D) {
     });
 }
 
+pub fn bench_create_existing_vertex<D: Datastore>(b: &mut Bencher, datastore: &mut D) {
+    let v = Vertex::new(Identifier::new("bench_create_existing_vertex").unwrap());
+    datastore.create_vertex(&v).unwrap();
+
+    b.iter(|| {
+        datastore.create_vertex(&v).unwrap();
+    });
+}
+
 pub fn bench_create_edge<D: Datastore>(b: &mut Bencher, datastore: &mut D) {
     let t = Identifier::new("bench_create_edge").unwrap();
 
@@ -18,6 +18,7 @@ macro_rules! full_bench_impl {
     ($code:expr) => {
         define_bench!(bench_create_vertex, $code);
         define_bench!(bench_get_vertices, $code);
+        define_bench!(bench_create_existing_vertex, $code);
         define_bench!(bench_create_edge, $code);
         define_bench!(bench_get_edges, $code);
         define_bench!(bench_get_edge_count, $code);
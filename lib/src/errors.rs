@@ -11,22 +11,60 @@ use serde_json::Error as JsonError;
 #[non_exhaustive]
 #[derive(Debug)]
 pub enum Error {
+    /// A vertex was created with the UUID of an existing vertex
     UuidTaken,
 
     /// An error occurred in the underlying datastore
     Datastore(Box<dyn StdError + Send + Sync>),
 
+    /// A value could not be serialized or deserialized
+    Serialization(JsonError),
+
+    /// An error occurred in rocksdb
+    #[cfg(feature = "rocksdb-datastore")]
+    Storage(RocksDbError),
+
+    /// A value read from the underlying datastore is malformed
+    Corrupt {
+        /// Where the value was read from.
+        location: &'static str,
+        /// A description of the key the value was stored under.
+        key: String,
+        /// The underlying deserialization error.
+        source: JsonError,
+    },
+
+    /// A key read from the underlying datastore is malformed
+    CorruptKey {
+        /// Where the key was read from.
+        location: &'static str,
+        /// The underlying decoding error.
+        source: Box<dyn StdError + Send + Sync>,
+    },
+
     /// A query occurred on a property that isn't indexed
     NotIndexed,
 
     /// For functionality that isn't supported
     Unsupported,
+
+    /// A property value is larger than the datastore allows
+    PropertyTooLarge,
+
+    /// A value has the wrong type for an operation, e.g. a non-numeric
+    /// bound for a numeric range
+    TypeMismatch,
 }
 
 impl StdError for Error {
     fn source(&self) -> Option<&(dyn StdError + 'static)> {
         match *self {
             Error::Datastore(ref err) => Some(&**err),
+            Error::Serialization(ref err) => Some(err),
+            Error::Corrupt { ref source, .. } => Some(source),
+            Error::CorruptKey { ref source, .. } => Some(&**source),
+            #[cfg(feature = "rocksdb-datastore")]
+            Error::Storage(ref err) => Some(err),
             _ => None,
         }
     }
@@ -37,15 +75,26 @@ impl fmt::Display for Error {
         match *self {
             Error::UuidTaken => write!(f, "UUID already taken"),
             Error::Datastore(ref err) => write!(f, "error in the underlying datastore: {}", err),
+            Error::Serialization(ref err) => write!(f, "serialization error: {}", err),
+            #[cfg(feature = "rocksdb-datastore")]
+            Error::Storage(ref err) => write!(f, "rocksdb error: {}", err),
+            Error::Corrupt {
+                location,
+                ref key,
+                ref source,
+            } => write!(f, "malformed value in {} for {}: {}", location, key, source),
+            Error::CorruptKey { location, ref source } => write!(f, "malformed key in {}: {}", location, source),
             Error::NotIndexed => write!(f, "query attempted on a property that isn't indexed"),
             Error::Unsupported => write!(f, "functionality not supported"),
+            Error::PropertyTooLarge => write!(f, "property value too large"),
+            Error::TypeMismatch => write!(f, "value has the wrong type"),
         }
     }
 }
 
 impl From<JsonError> for Error {
     fn from(err: JsonError) -> Self {
-        Error::Datastore(Box::new(err))
+        Error::Serialization(err)
     }
 }
 
@@ -58,7 +107,7 @@ impl From<BincodeError> for Error {
 #[cfg(feature = "rocksdb-datastore")]
 impl From<RocksDbError> for Error {
     fn from(err: RocksDbError) -> Self {
-        Error::Datastore(Box::new(err))
+        Error::Storage(err)
     }
 }
 
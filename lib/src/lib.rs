@@ -34,4 +34,13 @@ pub use crate::traits::*;
 mod rdb;
 
 #[cfg(feature = "rocksdb-datastore")]
-pub use crate::rdb::RocksdbDatastore;
+pub use crate::rdb::{
+    ChangeEvent, DeleteCounts, DeletePlan, IntegrityReport, MaintenanceReport, RecompressStats, RocksdbBatch,
+    RocksdbConfig, RocksdbDatastore,
+};
+
+#[cfg(feature = "tokio-datastore")]
+mod async_datastore;
+
+#[cfg(feature = "tokio-datastore")]
+pub use crate::async_datastore::AsyncDatastore;
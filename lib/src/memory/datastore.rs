@@ -1,6 +1,7 @@
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::File;
 use std::io::{BufReader, BufWriter};
+use std::ops::Bound;
 use std::path::PathBuf;
 use std::result::Result as StdResult;
 use std::sync::{Arc, RwLock};
@@ -11,6 +12,8 @@ use crate::{
     Datastore, Edge, EdgeDirection, EdgeKey, EdgeProperties, EdgeProperty, EdgePropertyQuery, EdgeQuery, Identifier,
     Json, NamedProperty, Vertex, VertexProperties, VertexProperty, VertexPropertyQuery, VertexQuery,
 };
+#[cfg(feature = "rocksdb-datastore")]
+use crate::{RangeVertexQuery, SpecificVertexQuery, VertexQueryExt};
 
 use bincode::Error as BincodeError;
 use chrono::offset::Utc;
@@ -19,6 +22,9 @@ use serde::{Deserialize, Serialize};
 use tempfile::NamedTempFile;
 use uuid::Uuid;
 
+#[cfg(feature = "rocksdb-datastore")]
+const FROM_ROCKSDB_BATCH_SIZE: u32 = 10_000;
+
 macro_rules! iter_vertex_values {
     ($self:expr, $iter:expr) => {
         Box::new($iter.filter_map(move |id| $self.vertices.get(&id).map(|value| (id, value.clone()))))
@@ -98,11 +104,15 @@ impl InternalMemoryDatastore {
         match q {
             VertexQuery::Range(range) => {
                 let mut iter: QueryIter<(&Uuid, &Identifier)> = if let Some(start_id) = range.start_id {
-                    Box::new(self.vertices.range(start_id..))
+                    Box::new(self.vertices.range((Bound::Excluded(start_id), Bound::Unbounded)))
                 } else {
                     Box::new(self.vertices.iter())
                 };
 
+                if let Some(high_id) = range.high_id {
+                    iter = Box::new(iter.take_while(move |(k, _)| **k < high_id));
+                }
+
                 if let Some(t) = range.t {
                     iter = Box::new(iter.filter(move |(_, v)| v == &&t));
                 }
@@ -426,6 +436,78 @@ impl MemoryDatastore {
             path: Some(path.into()),
         })
     }
+
+    /// Creates a datastore holding a copy of everything in a rocksdb
+    /// datastore, including edge update datetimes and property indexes. This
+    /// makes it cheap to keep a fixture on disk and load it into memory for
+    /// each test. The copy is not persisted anywhere.
+    ///
+    /// # Arguments
+    /// * `source`: The rocksdb datastore to copy from.
+    #[cfg(feature = "rocksdb-datastore")]
+    pub fn from_rocksdb(source: &crate::RocksdbDatastore) -> Result<MemoryDatastore> {
+        Self::from_rocksdb_in_batches(source, FROM_ROCKSDB_BATCH_SIZE)
+    }
+
+    #[cfg(feature = "rocksdb-datastore")]
+    pub(crate) fn from_rocksdb_in_batches(
+        source: &crate::RocksdbDatastore,
+        batch_size: u32,
+    ) -> Result<MemoryDatastore> {
+        let datastore = MemoryDatastore::default();
+
+        {
+            let mut internal = datastore.datastore.write().unwrap();
+            let mut last_id: Option<Uuid> = None;
+
+            loop {
+                let mut q = RangeVertexQuery::new().limit(batch_size);
+                if let Some(last_id) = last_id {
+                    q = q.start_id(last_id);
+                }
+
+                let vertex_properties = source.get_all_vertex_properties(q.into())?;
+                if vertex_properties.is_empty() {
+                    break;
+                }
+
+                let ids: Vec<Uuid> = vertex_properties.iter().map(|item| item.vertex.id).collect();
+                last_id = ids.last().copied();
+                for item in vertex_properties {
+                    let id = item.vertex.id;
+                    internal.vertices.insert(id, item.vertex.t);
+                    for prop in item.props {
+                        internal
+                            .vertex_properties
+                            .insert((id, prop.name), Json::new(prop.value));
+                    }
+                }
+
+                // Every edge is copied exactly once, along with the batch
+                // holding its outbound vertex
+                let edge_properties =
+                    source.get_all_edge_properties(SpecificVertexQuery::new(ids).outbound().into())?;
+                for item in edge_properties {
+                    let key = item.edge.key;
+                    internal.edges.insert(key.clone(), item.edge.created_datetime);
+                    internal
+                        .reversed_edges
+                        .insert(key.reversed(), item.edge.created_datetime);
+                    for prop in item.props {
+                        internal
+                            .edge_properties
+                            .insert((key.clone(), prop.name), Json::new(prop.value));
+                    }
+                }
+            }
+        }
+
+        for name in source.indexed_properties() {
+            datastore.index_property(name)?;
+        }
+
+        Ok(datastore)
+    }
 }
 
 impl Datastore for MemoryDatastore {
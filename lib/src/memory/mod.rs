@@ -38,3 +38,133 @@ fn should_serialize() {
     assert_eq!(vertices[0].id, id);
     assert_eq!(vertices[0].t, Identifier::default());
 }
+
+#[cfg(all(feature = "test-suite", feature = "rocksdb-datastore"))]
+#[test]
+fn should_copy_from_rocksdb() {
+    use super::MemoryDatastore;
+    use crate::{
+        Datastore, EdgeKey, EdgeQueryExt, Identifier, PropertyValueVertexQuery, RocksdbConfig, SpecificEdgeQuery,
+        SpecificVertexQuery, Vertex, VertexQueryExt,
+    };
+    use serde_json::json;
+
+    let source = RocksdbConfig::default().temporary().open("ignored").unwrap();
+    let t = Identifier::new("test_type").unwrap();
+    let name = Identifier::new("test_property").unwrap();
+    let outbound_v = Vertex::new(t.clone());
+    let inbound_v = Vertex::new(t.clone());
+    let key = EdgeKey::new(outbound_v.id, t.clone(), inbound_v.id);
+    source.create_vertex(&outbound_v).unwrap();
+    source.create_vertex(&inbound_v).unwrap();
+    source.create_edge(&key).unwrap();
+    source.index_property(name.clone()).unwrap();
+    source
+        .set_vertex_properties(
+            SpecificVertexQuery::single(outbound_v.id).property(name.clone()),
+            json!(1),
+        )
+        .unwrap();
+    source
+        .set_edge_properties(SpecificEdgeQuery::single(key.clone()).property(name.clone()), json!(2))
+        .unwrap();
+
+    let datastore = MemoryDatastore::from_rocksdb(&source).unwrap();
+    assert_eq!(datastore.get_vertex_count().unwrap(), 2);
+    let vertices = datastore
+        .get_vertices(SpecificVertexQuery::single(inbound_v.id).into())
+        .unwrap();
+    assert_eq!(vertices, vec![inbound_v.clone()]);
+
+    let q = SpecificEdgeQuery::single(key.clone());
+    assert_eq!(
+        datastore.get_edges(q.clone().into()).unwrap(),
+        source.get_edges(q.clone().into()).unwrap()
+    );
+    assert_eq!(
+        datastore
+            .get_edge_count(inbound_v.id, None, crate::EdgeDirection::Inbound)
+            .unwrap(),
+        1
+    );
+    let properties = datastore.get_edge_properties(q.property(name.clone())).unwrap();
+    assert_eq!(properties[0].value, json!(2));
+
+    let ids: Vec<_> = datastore
+        .get_vertices(PropertyValueVertexQuery::new(name, json!(1)).into())
+        .unwrap()
+        .into_iter()
+        .map(|vertex| vertex.id)
+        .collect();
+    assert_eq!(ids, vec![outbound_v.id]);
+}
+
+#[cfg(all(feature = "test-suite", feature = "rocksdb-datastore"))]
+#[test]
+fn should_copy_from_rocksdb_in_batches() {
+    use super::MemoryDatastore;
+    use crate::{Datastore, EdgeDirection, EdgeKey, Identifier, RangeVertexQuery, RocksdbConfig};
+
+    let source = RocksdbConfig::default().temporary().open("ignored").unwrap();
+    let t = Identifier::new("test_type").unwrap();
+    let ids: Vec<_> = (0..5)
+        .map(|_| source.create_vertex_from_type(t.clone()).unwrap())
+        .collect();
+    for pair in ids.windows(2) {
+        source.create_edge(&EdgeKey::new(pair[0], t.clone(), pair[1])).unwrap();
+    }
+
+    // Each page starts after the last vertex of the one before, so no
+    // vertex is skipped or copied twice
+    for batch_size in [1, 2, 5, 6] {
+        let datastore = MemoryDatastore::from_rocksdb_in_batches(&source, batch_size).unwrap();
+        let q = RangeVertexQuery::new();
+        assert_eq!(
+            datastore.get_vertices(q.clone().into()).unwrap(),
+            source.get_vertices(q.into()).unwrap()
+        );
+        for id in &ids {
+            for direction in [EdgeDirection::Outbound, EdgeDirection::Inbound] {
+                assert_eq!(
+                    datastore.get_edge_count(*id, None, direction).unwrap(),
+                    source.get_edge_count(*id, None, direction).unwrap()
+                );
+            }
+        }
+    }
+}
+
+#[cfg(all(feature = "test-suite", feature = "rocksdb-datastore"))]
+#[test]
+fn should_order_vertices_like_rocksdb() {
+    use super::MemoryDatastore;
+    use crate::{Datastore, Identifier, RangeVertexQuery, RocksdbConfig, SpecificVertexQuery, Vertex, VertexQuery};
+
+    let memory = MemoryDatastore::default();
+    let rocksdb = RocksdbConfig::default().temporary().open("ignored").unwrap();
+    let t = Identifier::new("test_type").unwrap();
+    let ids: Vec<_> = (0..50).map(|_| crate::util::generate_uuid_v1()).rev().collect();
+    for id in &ids {
+        let vertex = Vertex::with_id(*id, t.clone());
+        memory.create_vertex(&vertex).unwrap();
+        rocksdb.create_vertex(&vertex).unwrap();
+    }
+
+    let queries: Vec<VertexQuery> = vec![
+        RangeVertexQuery::new().into(),
+        RangeVertexQuery::new().high_id(ids[20]).limit(10).into(),
+        RangeVertexQuery::new().start_id(ids[30]).into(),
+        RangeVertexQuery::new()
+            .start_id(ids[40])
+            .high_id(ids[10])
+            .limit(5)
+            .into(),
+        SpecificVertexQuery::new(ids.clone()).into(),
+    ];
+    for q in queries {
+        assert_eq!(
+            memory.get_vertices(q.clone()).unwrap(),
+            rocksdb.get_vertices(q).unwrap()
+        );
+    }
+}
@@ -14,3 +14,17 @@ pub enum BulkInsertItem {
     /// An edge property to insert.
     EdgeProperty(EdgeKey, Identifier, serde_json::Value),
 }
+
+/// A problem found when validating a bulk insert request.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BulkValidationError {
+    /// An identifier in the item at `index` is invalid, e.g. because it's too
+    /// long.
+    InvalidIdentifier { index: usize, identifier: Identifier },
+    /// The item at `index` references a vertex that neither exists nor is
+    /// inserted as part of the same request.
+    MissingVertex { index: usize, id: Uuid },
+    /// The item at `index` references an edge that neither exists nor is
+    /// inserted as part of the same request.
+    MissingEdge { index: usize, key: EdgeKey },
+}
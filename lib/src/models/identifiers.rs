@@ -93,6 +93,17 @@ mod tests {
         let long_t = (0..256).map(|_| "X").collect::<String>();
         assert!(Identifier::new(long_t).is_err());
         assert!(Identifier::new("$").is_err());
+        assert!(Identifier::new("meta.created_by").is_err());
+    }
+
+    #[test]
+    fn should_round_trip_valid_identifiers() {
+        let long_t = (0..255).map(|_| "X").collect::<String>();
+        for s in ["a", "created_by", "meta-created_by", long_t.as_str()] {
+            let identifier = Identifier::new(s).unwrap();
+            assert_eq!(identifier.as_str(), s);
+            assert_eq!(identifier.into_string(), s);
+        }
     }
 
     #[test]
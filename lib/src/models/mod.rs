@@ -6,7 +6,7 @@ mod properties;
 mod queries;
 mod vertices;
 
-pub use self::bulk_insert::BulkInsertItem;
+pub use self::bulk_insert::{BulkInsertItem, BulkValidationError};
 pub use self::edges::{Edge, EdgeKey};
 pub use self::identifiers::Identifier;
 pub use self::json::Json;
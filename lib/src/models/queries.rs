@@ -259,7 +259,7 @@ impl PipePropertyValueVertexQuery {
     }
 }
 
-/// Gets a range of vertices.
+/// Gets a range of vertices, in ascending order of ID.
 #[derive(Eq, PartialEq, Clone, Debug)]
 pub struct RangeVertexQuery {
     /// Limits the number of vertices to get.
@@ -268,8 +268,14 @@ pub struct RangeVertexQuery {
     /// Filters the type of vertices returned.
     pub t: Option<Identifier>,
 
-    /// Sets the lowest vertex ID to return.
+    /// Sets the vertex ID to start after. Only vertices with IDs higher than
+    /// this are returned, so the ID of the last vertex of one page can be used
+    /// to get the next page.
     pub start_id: Option<Uuid>,
+
+    /// Sets the vertex ID to stop at. Only vertices with IDs lower than this
+    /// are returned.
+    pub high_id: Option<Uuid>,
 }
 
 vertex_query_type!(RangeVertexQuery, Range);
@@ -287,6 +293,7 @@ impl RangeVertexQuery {
             limit: u32::max_value(),
             t: None,
             start_id: None,
+            high_id: None,
         }
     }
 
@@ -299,6 +306,7 @@ impl RangeVertexQuery {
             limit,
             t: self.t,
             start_id: self.start_id,
+            high_id: self.high_id,
         }
     }
 
@@ -311,18 +319,33 @@ impl RangeVertexQuery {
             limit: self.limit,
             t: Some(t),
             start_id: self.start_id,
+            high_id: self.high_id,
         }
     }
 
-    /// Sets the lowest vertex ID to return.
+    /// Sets the vertex ID to start after.
     ///
     /// # Arguments
-    /// * `start_id`: The lowest vertex ID to return.
+    /// * `start_id`: Only vertices with IDs higher than this are returned.
     pub fn start_id(self, start_id: Uuid) -> Self {
         Self {
             limit: self.limit,
             t: self.t,
             start_id: Some(start_id),
+            high_id: self.high_id,
+        }
+    }
+
+    /// Sets the vertex ID to stop at.
+    ///
+    /// # Arguments
+    /// * `high_id`: Only vertices with IDs lower than this are returned.
+    pub fn high_id(self, high_id: Uuid) -> Self {
+        Self {
+            limit: self.limit,
+            t: self.t,
+            start_id: self.start_id,
+            high_id: Some(high_id),
         }
     }
 }
@@ -0,0 +1,250 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::Mutex;
+
+use crate::models;
+
+use uuid::Uuid;
+
+type CacheKey = (Uuid, models::Identifier);
+
+// Entries keyed by owner and property name, ordered by when they were last
+// used.
+struct Lru<T> {
+    by_key: HashMap<CacheKey, (T, u64)>,
+    by_tick: BTreeMap<u64, CacheKey>,
+    tick: u64,
+    hits: u64,
+}
+
+impl<T> Default for Lru<T> {
+    fn default() -> Self {
+        Lru {
+            by_key: HashMap::new(),
+            by_tick: BTreeMap::new(),
+            tick: 0,
+            hits: 0,
+        }
+    }
+}
+
+impl<T> Lru<T> {
+    // Gets an entry if `is_valid` accepts it, marking it as the most
+    // recently used.
+    fn get<F: FnOnce(&T) -> bool>(&mut self, key: CacheKey, is_valid: F) -> Option<&T> {
+        let (value, tick) = self.by_key.get_mut(&key)?;
+        if !is_valid(value) {
+            return None;
+        }
+
+        self.tick += 1;
+        self.by_tick.remove(tick);
+        self.by_tick.insert(self.tick, key);
+        *tick = self.tick;
+        self.hits += 1;
+        Some(value)
+    }
+
+    fn insert(&mut self, key: CacheKey, value: T, capacity: usize) {
+        self.tick += 1;
+        if let Some((_, old_tick)) = self.by_key.insert(key.clone(), (value, self.tick)) {
+            self.by_tick.remove(&old_tick);
+        }
+        self.by_tick.insert(self.tick, key);
+
+        while self.by_key.len() > capacity {
+            let (_, oldest_key) = self.by_tick.pop_first().unwrap();
+            self.by_key.remove(&oldest_key);
+        }
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some((_, tick)) = self.by_key.remove(key) {
+            self.by_tick.remove(&tick);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.by_key.clear();
+        self.by_tick.clear();
+    }
+}
+
+/// A least-recently-used cache of decoded vertex property values.
+///
+/// Each entry keeps the serialized bytes it was decoded from, and is only
+/// used if they still match what's stored. Entries can therefore never be
+/// stale, even if a write races with a read; invalidating an entry just frees
+/// it early.
+pub(crate) struct PropertyCache {
+    capacity: usize,
+    entries: Mutex<Lru<(Vec<u8>, models::Json)>>,
+}
+
+impl PropertyCache {
+    pub fn new(capacity: usize) -> Self {
+        PropertyCache {
+            capacity,
+            entries: Mutex::new(Lru::default()),
+        }
+    }
+
+    pub fn get(&self, id: Uuid, name: &models::Identifier, bytes: &[u8]) -> Option<models::Json> {
+        if self.capacity == 0 {
+            return None;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        entries
+            .get((id, name.clone()), |(entry_bytes, _)| entry_bytes == bytes)
+            .map(|(_, value)| value.clone())
+    }
+
+    pub fn insert(&self, id: Uuid, name: &models::Identifier, bytes: &[u8], value: models::Json) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert((id, name.clone()), (bytes.to_vec(), value), self.capacity);
+    }
+
+    pub fn invalidate(&self, id: Uuid, name: &models::Identifier) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.entries.lock().unwrap().remove(&(id, name.clone()));
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    #[cfg(test)]
+    pub fn hits(&self) -> u64 {
+        self.entries.lock().unwrap().hits
+    }
+}
+
+/// A least-recently-used cache of vertex property values read within a
+/// single batch, including properties that weren't set.
+///
+/// Unlike `PropertyCache`, entries aren't checked against what's stored, so
+/// cached reads skip the database entirely. Entries only change when they're
+/// invalidated, so this is only suitable for short-lived sessions.
+pub(crate) struct SessionPropertyCache {
+    capacity: usize,
+    entries: Lru<Option<models::Json>>,
+}
+
+impl SessionPropertyCache {
+    pub fn new(capacity: usize) -> Self {
+        SessionPropertyCache {
+            capacity,
+            entries: Lru::default(),
+        }
+    }
+
+    pub fn get(&mut self, id: Uuid, name: &models::Identifier) -> Option<Option<models::Json>> {
+        self.entries.get((id, name.clone()), |_| true).cloned()
+    }
+
+    pub fn insert(&mut self, id: Uuid, name: &models::Identifier, value: Option<models::Json>) {
+        if self.capacity > 0 {
+            self.entries.insert((id, name.clone()), value, self.capacity);
+        }
+    }
+
+    pub fn invalidate(&mut self, id: Uuid, name: &models::Identifier) {
+        self.entries.remove(&(id, name.clone()));
+    }
+
+    #[cfg(test)]
+    pub fn hits(&self) -> u64 {
+        self.entries.hits
+    }
+}
+
+impl fmt::Debug for PropertyCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PropertyCache")
+            .field("capacity", &self.capacity)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PropertyCache, SessionPropertyCache};
+    use crate::models;
+    use crate::util;
+
+    #[test]
+    fn should_evict_least_recently_used() {
+        let cache = PropertyCache::new(2);
+        let name = models::Identifier::new("test_property").unwrap();
+        let ids = [
+            util::generate_uuid_v1(),
+            util::generate_uuid_v1(),
+            util::generate_uuid_v1(),
+        ];
+        let value = models::Json::new(serde_json::json!(true));
+
+        cache.insert(ids[0], &name, b"true", value.clone());
+        cache.insert(ids[1], &name, b"true", value.clone());
+        assert_eq!(cache.get(ids[0], &name, b"true"), Some(value.clone()));
+        cache.insert(ids[2], &name, b"true", value.clone());
+
+        assert_eq!(cache.get(ids[0], &name, b"true"), Some(value.clone()));
+        assert_eq!(cache.get(ids[1], &name, b"true"), None);
+        assert_eq!(cache.get(ids[2], &name, b"true"), Some(value));
+        assert_eq!(cache.hits(), 3);
+    }
+
+    #[test]
+    fn should_not_return_entries_for_changed_bytes() {
+        let cache = PropertyCache::new(2);
+        let name = models::Identifier::new("test_property").unwrap();
+        let id = util::generate_uuid_v1();
+
+        cache.insert(id, &name, b"true", models::Json::new(serde_json::json!(true)));
+        assert_eq!(cache.get(id, &name, b"false"), None);
+        cache.invalidate(id, &name);
+        assert_eq!(cache.get(id, &name, b"true"), None);
+    }
+
+    #[test]
+    fn should_not_cache_without_capacity() {
+        let cache = PropertyCache::new(0);
+        let name = models::Identifier::new("test_property").unwrap();
+        let id = util::generate_uuid_v1();
+
+        cache.insert(id, &name, b"true", models::Json::new(serde_json::json!(true)));
+        assert_eq!(cache.get(id, &name, b"true"), None);
+    }
+
+    #[test]
+    fn should_cache_session_reads_until_invalidated() {
+        let mut cache = SessionPropertyCache::new(2);
+        let name = models::Identifier::new("test_property").unwrap();
+        let ids = [
+            util::generate_uuid_v1(),
+            util::generate_uuid_v1(),
+            util::generate_uuid_v1(),
+        ];
+        let value = models::Json::new(serde_json::json!(true));
+
+        cache.insert(ids[0], &name, Some(value.clone()));
+        cache.insert(ids[1], &name, None);
+        assert_eq!(cache.get(ids[0], &name), Some(Some(value.clone())));
+        assert_eq!(cache.get(ids[1], &name), Some(None));
+        cache.insert(ids[2], &name, Some(value.clone()));
+        assert_eq!(cache.get(ids[0], &name), None);
+
+        cache.invalidate(ids[2], &name);
+        assert_eq!(cache.get(ids[2], &name), None);
+        assert_eq!(cache.get(ids[1], &name), Some(None));
+        assert_eq!(cache.hits(), 3);
+    }
+}
@@ -0,0 +1,142 @@
+// A small LZ77 codec for property values. The output is a sequence of
+// tokens: a control byte below 0x80 is followed by that many plus one literal
+// bytes, and a control byte of 0x80 or above is a match of its low seven bits
+// plus `MIN_MATCH` bytes, followed by a little-endian u16 offset back into
+// the output.
+
+const MIN_MATCH: usize = 4;
+const MAX_MATCH: usize = MIN_MATCH + 0x7f;
+const MAX_LITERALS: usize = 0x80;
+const MAX_OFFSET: usize = u16::MAX as usize;
+const HASH_BITS: u32 = 12;
+
+pub(crate) fn compress(input: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(input.len() / 2);
+    // The position after the last occurrence of each hashed sequence, or zero
+    let mut table = vec![0usize; 1 << HASH_BITS];
+    let mut literal_start = 0;
+    let mut i = 0;
+
+    while i + MIN_MATCH <= input.len() {
+        let h = hash(&input[i..i + MIN_MATCH]);
+        let candidate = table[h];
+        table[h] = i + 1;
+
+        if candidate > 0 {
+            let candidate = candidate - 1;
+            if i - candidate <= MAX_OFFSET && input[candidate..candidate + MIN_MATCH] == input[i..i + MIN_MATCH] {
+                let mut len = MIN_MATCH;
+                while len < MAX_MATCH && i + len < input.len() && input[candidate + len] == input[i + len] {
+                    len += 1;
+                }
+
+                write_literals(&mut output, &input[literal_start..i]);
+                output.push(0x80 | (len - MIN_MATCH) as u8);
+                output.extend_from_slice(&((i - candidate) as u16).to_le_bytes());
+                i += len;
+                literal_start = i;
+                continue;
+            }
+        }
+
+        i += 1;
+    }
+
+    write_literals(&mut output, &input[literal_start..]);
+    output
+}
+
+/// Decompresses `input`, which should decompress to exactly `len` bytes.
+/// Returns `None` if the input is malformed.
+pub(crate) fn decompress(input: &[u8], len: usize) -> Option<Vec<u8>> {
+    // `len` comes from the stored value, so it isn't trusted to size the
+    // allocation beyond what `input` could possibly expand to
+    let mut output = Vec::with_capacity(len.min(input.len().saturating_mul(MAX_MATCH)));
+    let mut i = 0;
+
+    while i < input.len() {
+        let control = input[i] as usize;
+        i += 1;
+
+        if control < 0x80 {
+            let end = i + control + 1;
+            output.extend_from_slice(input.get(i..end)?);
+            i = end;
+        } else {
+            let offset_bytes = input.get(i..i + 2)?;
+            let offset = u16::from_le_bytes([offset_bytes[0], offset_bytes[1]]) as usize;
+            i += 2;
+            if offset == 0 || offset > output.len() {
+                return None;
+            }
+            // Matches may overlap the bytes they produce, so they're copied
+            // one byte at a time
+            let start = output.len() - offset;
+            for j in 0..(control & 0x7f) + MIN_MATCH {
+                output.push(output[start + j]);
+            }
+        }
+
+        if output.len() > len {
+            return None;
+        }
+    }
+
+    if output.len() == len {
+        Some(output)
+    } else {
+        None
+    }
+}
+
+fn write_literals(output: &mut Vec<u8>, literals: &[u8]) {
+    for chunk in literals.chunks(MAX_LITERALS) {
+        output.push((chunk.len() - 1) as u8);
+        output.extend_from_slice(chunk);
+    }
+}
+
+fn hash(bytes: &[u8]) -> usize {
+    let value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    (value.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compress, decompress};
+
+    #[test]
+    fn should_round_trip() {
+        let inputs: Vec<Vec<u8>> = vec![
+            Vec::new(),
+            b"abc".to_vec(),
+            b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa".to_vec(),
+            serde_json::to_vec(&vec![serde_json::json!({"name": "test", "value": 1}); 100]).unwrap(),
+            (0..1000u32)
+                .flat_map(|i| i.wrapping_mul(2_654_435_761).to_le_bytes())
+                .collect(),
+        ];
+
+        for input in inputs {
+            let compressed = compress(&input);
+            assert_eq!(decompress(&compressed, input.len()), Some(input));
+        }
+    }
+
+    #[test]
+    fn should_shrink_repetitive_input() {
+        let input = serde_json::to_vec(&vec![serde_json::json!({"name": "test", "value": 1}); 100]).unwrap();
+        assert!(compress(&input).len() < input.len() / 4);
+    }
+
+    #[test]
+    fn should_reject_malformed_input() {
+        let compressed = compress(b"abcabcabcabcabcabc");
+        assert_eq!(decompress(&compressed, 17), None);
+        assert_eq!(decompress(&compressed[..compressed.len() - 1], 18), None);
+        assert_eq!(decompress(&[0x80, 0x01, 0x00], 4), None);
+        // A corrupt length shouldn't be trusted for the allocation
+        assert_eq!(decompress(&compressed, u32::MAX as usize), None);
+        assert_eq!(decompress(&compressed, usize::MAX), None);
+    }
+}
@@ -1,13 +1,17 @@
 use std::collections::{HashMap, HashSet};
 use std::i32;
+use std::mem;
 use std::path::Path;
-use std::sync::{Arc, RwLock};
+use std::sync::{mpsc, Arc, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
 use std::u64;
 use std::usize;
 
+use super::cache::{PropertyCache, SessionPropertyCache};
 use super::managers::*;
 use crate::errors::{Error, Result};
-use crate::util::next_uuid;
+use crate::util::MAX_DATETIME;
 use crate::{
     BulkInsertItem, Datastore, Edge, EdgeDirection, EdgeKey, EdgeProperties, EdgeProperty, EdgePropertyQuery,
     EdgeQuery, Identifier, Json, NamedProperty, PropertyPresenceEdgeQuery, PropertyPresenceVertexQuery,
@@ -17,10 +21,14 @@ use crate::{
 
 use chrono::offset::Utc;
 use chrono::DateTime;
-use rocksdb::{DBCompactionStyle, Options, WriteBatch, DB};
+use rocksdb::{
+    BottommostLevelCompaction, ColumnFamilyDescriptor, CompactOptions, DBCompactionStyle, DBCompressionType,
+    IteratorMode, Options, WriteBatch, DB,
+};
+use tempfile::TempDir;
 use uuid::Uuid;
 
-const CF_NAMES: [&str; 9] = [
+pub(crate) const CF_NAMES: [&str; 12] = [
     "vertices:v1",
     "edges:v1",
     "edge_ranges:v1",
@@ -30,14 +38,65 @@ const CF_NAMES: [&str; 9] = [
     "vertex_property_values:v1",
     "edge_property_values:v1",
     "metadata:v1",
+    "vertex_degrees:v1",
+    "edges_by_type:v1",
+    "vertex_property_numbers:v1",
 ];
 
-fn get_options(max_open_files: Option<i32>) -> Options {
+const MIGRATE_BATCH_SIZE: usize = 10_000;
+const REBUILD_SPACE_AMPLIFICATION: f64 = 2.0;
+
+// Every column family of a database, including the edge range shards beyond
+// the first.
+pub(crate) fn cf_names(config: &RocksdbConfig) -> Vec<String> {
+    let mut cf_names: Vec<String> = CF_NAMES.iter().map(|cf_name| cf_name.to_string()).collect();
+    for shard in 1..config.edge_range_shards {
+        cf_names.push(shard_cf_name("edge_ranges:v1", shard));
+        cf_names.push(shard_cf_name("reversed_edge_ranges:v1", shard));
+    }
+    cf_names
+}
+
+fn open_db(opts: &Options, path: &Path, cf_names: &[String]) -> Result<DB> {
+    // Column families are opened with the same options as the database, so
+    // that they share the merge operator used by the degree counters
+    let cf_descriptors = cf_names
+        .iter()
+        .map(|cf_name| ColumnFamilyDescriptor::new(cf_name, opts.clone()));
+
+    match DB::open_cf_descriptors(opts, path, cf_descriptors) {
+        Ok(db) => Ok(db),
+        Err(_) => {
+            let mut db = DB::open(opts, path)?;
+
+            for cf_name in cf_names {
+                db.create_cf(cf_name, opts)?;
+            }
+
+            Ok(db)
+        }
+    }
+}
+
+// Rocksdb doesn't expose error kinds, so a held lock is recognized by its
+// message, e.g. "While lock file: ...: Resource temporarily unavailable"
+fn is_lock_error(err: &rocksdb::Error) -> bool {
+    err.as_ref().contains("lock")
+}
+
+fn has_entries(db: &DB, cf_name: &str) -> bool {
+    let cf = db.cf_handle(cf_name).unwrap();
+    db.iterator_cf(cf, IteratorMode::Start).next().is_some()
+}
+
+fn get_options(config: &RocksdbConfig) -> Options {
     // Current tuning based off of the total ordered example, flash
     // storage example on
     // https://github.com/facebook/rocksdb/wiki/RocksDB-Tuning-Guide
     let mut opts = Options::default();
     opts.create_if_missing(true);
+    opts.create_missing_column_families(true);
+    opts.set_merge_operator_associative("vertex_degrees", merge_degrees);
     opts.set_compaction_style(DBCompactionStyle::Level);
     opts.set_write_buffer_size(67_108_864); // 64mb
     opts.set_max_write_buffer_number(3);
@@ -49,13 +108,48 @@ fn get_options(max_open_files: Option<i32>) -> Options {
     opts.set_max_bytes_for_level_base(536_870_912); // 512mb
     opts.set_max_bytes_for_level_multiplier(8.0);
 
-    if let Some(max_open_files) = max_open_files {
+    if let Some(max_open_files) = config.max_open_files {
         opts.set_max_open_files(max_open_files);
     }
 
+    if !config.compression {
+        opts.set_compression_type(DBCompressionType::None);
+    }
+
     opts
 }
 
+// Flushes every column family, rather than just the default one, so that
+// nothing is left only in memtables if the write-ahead log is disabled.
+pub(crate) fn flush(db: &DB, config: &RocksdbConfig) -> Result<()> {
+    for cf_name in cf_names(config) {
+        db.flush_cf(db.cf_handle(&cf_name).unwrap())?;
+    }
+    Ok(())
+}
+
+// Rewrites the value index entries of every indexed vertex property.
+fn reindex_vertex_property_values(db_ref: DBRef<'_>) -> Result<()> {
+    let vertex_manager = VertexManager::new(db_ref);
+    let vertex_property_manager = VertexPropertyManager::new(db_ref);
+    let vertex_property_value_manager = VertexPropertyValueManager::new(db_ref);
+    let mut batch = WriteBatch::default();
+
+    for item in vertex_manager.iterate_for_range(Uuid::default()) {
+        let (vertex_id, _) = item?;
+        for name in db_ref.indexed_properties {
+            if let Some(value) = vertex_property_manager.get(vertex_id, name)? {
+                vertex_property_value_manager.set(&mut batch, vertex_id, name, &value);
+            }
+        }
+        if batch.len() >= MIGRATE_BATCH_SIZE {
+            db_ref.write(mem::take(&mut batch))?;
+        }
+    }
+
+    db_ref.write(batch)
+}
+
 fn guard_indexed_property(db_ref: DBRef<'_>, property: &Identifier) -> Result<()> {
     if !db_ref.indexed_properties.contains(property) {
         Err(Error::NotIndexed)
@@ -171,23 +265,17 @@ fn execute_vertex_query(db_ref: DBRef<'_>, q: VertexQuery) -> Result<Vec<VertexI
         VertexQuery::Range(q) => {
             let vertex_manager = VertexManager::new(db_ref);
 
-            let next_uuid = match q.start_id {
-                Some(start_id) => {
-                    match next_uuid(start_id) {
-                        Ok(next_uuid) => next_uuid,
-                        // If we get an error back, it's because
-                        // `start_id` is the maximum possible value. We
-                        // know that no vertices exist whose ID is greater
-                        // than the maximum possible value, so just return
-                        // an empty list.
-                        Err(_) => return Ok(vec![]),
-                    }
-                }
-                None => Uuid::default(),
+            let mut iter: Box<dyn Iterator<Item = Result<VertexItem>>> = match q.start_id {
+                Some(start_id) => Box::new(vertex_manager.iterate_for_range_after(start_id)),
+                None => Box::new(vertex_manager.iterate_for_range(Uuid::default())),
             };
 
-            let mut iter: Box<dyn Iterator<Item = Result<VertexItem>>> =
-                Box::new(vertex_manager.iterate_for_range(next_uuid));
+            if let Some(high_id) = q.high_id {
+                iter = Box::new(iter.take_while(move |item| match item {
+                    Ok((id, _)) => *id < high_id,
+                    Err(_) => true,
+                }));
+            }
 
             if let Some(ref t) = q.t {
                 iter = Box::new(iter.filter(move |item| match item {
@@ -315,17 +403,24 @@ fn execute_edge_query(db_ref: DBRef<'_>, q: EdgeQuery) -> Result<Vec<EdgeRangeIt
             let mut edges: Vec<EdgeRangeItem> = Vec::new();
 
             for (id, _) in vertices.into_iter() {
-                let edge_iterator = edge_range_manager.iterate_for_range(id, q.t.as_ref(), q.high)?;
+                if edges.len() >= q.limit as usize {
+                    break;
+                }
 
-                for item in edge_iterator {
-                    let (edge_range_first_id, edge_range_t, edge_range_update_datetime, edge_range_second_id) = item?;
+                let edge_iterator = match q.low {
+                    Some(low) => {
+                        edge_range_manager.iterate_for_window(id, low, q.high.unwrap_or(*MAX_DATETIME), q.t.as_ref())?
+                    }
+                    None => edge_range_manager.iterate_for_range(id, q.t.as_ref(), q.high)?,
+                };
 
-                    if let Some(low) = q.low {
-                        if edge_range_update_datetime < low {
-                            break;
-                        }
+                for item in edge_iterator {
+                    if edges.len() >= q.limit as usize {
+                        break;
                     }
 
+                    let (edge_range_first_id, edge_range_t, edge_range_update_datetime, edge_range_second_id) = item?;
+
                     edges.push(match q.direction {
                         EdgeDirection::Outbound => (
                             edge_range_first_id,
@@ -340,10 +435,6 @@ fn execute_edge_query(db_ref: DBRef<'_>, q: EdgeQuery) -> Result<Vec<EdgeRangeIt
                             edge_range_first_id,
                         ),
                     });
-
-                    if edges.len() == q.limit as usize {
-                        break;
-                    }
                 }
             }
 
@@ -374,220 +465,1784 @@ fn execute_edge_query(db_ref: DBRef<'_>, q: EdgeQuery) -> Result<Vec<EdgeRangeIt
     }
 }
 
-/// A datastore that is backed by rocksdb.
-#[derive(Debug)]
-pub struct RocksdbDatastore {
-    db: Arc<DB>,
-    indexed_properties: Arc<RwLock<HashSet<Identifier>>>,
+/// Configuration options for the rocksdb datastore.
+#[derive(Clone, Debug)]
+pub struct RocksdbConfig {
+    pub(crate) max_open_files: Option<i32>,
+    pub(crate) manual_flush: bool,
+    pub(crate) flush_on_drop: bool,
+    pub(crate) max_property_bytes: Option<usize>,
+    pub(crate) compression: bool,
+    pub(crate) property_compress_threshold: Option<usize>,
+    pub(crate) degree_counters: bool,
+    pub(crate) edge_type_index: bool,
+    pub(crate) edge_sequence_numbers: bool,
+    pub(crate) edge_time_resolution: Option<Duration>,
+    pub(crate) edge_range_shards: usize,
+    pub(crate) property_cache_capacity: usize,
+    pub(crate) temporary: bool,
+    pub(crate) open_retry_attempts: usize,
+    pub(crate) open_retry_delay: Duration,
 }
 
-impl RocksdbDatastore {
-    /// Creates a new rocksdb datastore.
+impl Default for RocksdbConfig {
+    fn default() -> Self {
+        Self {
+            max_open_files: None,
+            manual_flush: false,
+            flush_on_drop: true,
+            max_property_bytes: None,
+            compression: true,
+            property_compress_threshold: None,
+            degree_counters: false,
+            edge_type_index: false,
+            edge_sequence_numbers: false,
+            edge_time_resolution: None,
+            edge_range_shards: 1,
+            property_cache_capacity: 0,
+            temporary: false,
+            open_retry_attempts: 0,
+            open_retry_delay: Duration::from_millis(0),
+        }
+    }
+}
+
+impl RocksdbConfig {
+    /// Sets the maximum number of open files to have.
     ///
     /// # Arguments
-    /// * `path`: The file path to the rocksdb database.
-    /// * `max_open_files`: The maximum number of open files to have. If
-    ///   `None`, the default will be used.
-    pub fn new<P: AsRef<Path>>(path: P, max_open_files: Option<i32>) -> Result<RocksdbDatastore> {
-        let opts = get_options(max_open_files);
-        let path = path.as_ref();
-
-        let db = match DB::open_cf(&opts, path, &CF_NAMES) {
-            Ok(db) => db,
-            Err(_) => {
-                let mut db = DB::open(&opts, path)?;
+    /// * `max_open_files`: The maximum number of open files. If `None`, the
+    ///   default will be used.
+    pub fn max_open_files(self, max_open_files: Option<i32>) -> Self {
+        Self { max_open_files, ..self }
+    }
 
-                for cf_name in &CF_NAMES {
-                    db.create_cf(cf_name, &opts)?;
-                }
+    /// Skips the write-ahead log on writes, so that data is only persisted
+    /// when rocksdb flushes its memtables. This reduces write overhead for
+    /// bulk loads, but writes that happen after the last call to `sync` may
+    /// be lost if the process exits; callers must call `sync` to persist.
+    pub fn manual_flush(self) -> Self {
+        Self {
+            manual_flush: true,
+            ..self
+        }
+    }
 
-                db
-            }
-        };
+    /// Sets whether to flush the datastore when it's dropped, so that writes
+    /// made since the last call to `sync` are persisted even if `sync` is
+    /// never called. This is enabled by default; disable it for crash-only
+    /// designs that don't want to pay for the flush on shutdown.
+    ///
+    /// # Arguments
+    /// * `flush_on_drop`: Whether to flush on drop.
+    pub fn flush_on_drop(self, flush_on_drop: bool) -> Self {
+        Self { flush_on_drop, ..self }
+    }
 
-        let metadata_manager = MetadataManager::new(&db);
-        let indexed_properties = metadata_manager.get_indexed_properties()?;
+    /// Sets the maximum size of a property value, in bytes of serialized
+    /// JSON. Setting a larger property value fails with
+    /// `Error::PropertyTooLarge`.
+    ///
+    /// # Arguments
+    /// * `max_property_bytes`: The maximum size. If `None`, property values
+    ///   can be of any size.
+    pub fn max_property_bytes(self, max_property_bytes: Option<usize>) -> Self {
+        Self {
+            max_property_bytes,
+            ..self
+        }
+    }
 
-        Ok(RocksdbDatastore {
-            db: Arc::new(db),
-            indexed_properties: Arc::new(RwLock::new(indexed_properties)),
-        })
+    /// Sets whether to compress data on disk. This is enabled by default.
+    /// Only newly written data is affected, so to change the setting for an
+    /// existing database, use `RocksdbDatastore::recompress` or
+    /// `RocksdbDatastore::migrate_compression`.
+    ///
+    /// # Arguments
+    /// * `compression`: Whether to compress data.
+    pub fn compression(self, compression: bool) -> Self {
+        Self { compression, ..self }
     }
 
-    /// Runs a repair operation on the rocksdb database.
+    /// Compresses individual property values whose serialized JSON is
+    /// larger than a threshold. Small values rarely shrink enough to be
+    /// worth the CPU, while large ones often do. Values are only stored
+    /// compressed if that makes them smaller, and values written without
+    /// compression remain readable, so this can be changed at any time.
     ///
     /// # Arguments
-    /// * `path`: The file path to the rocksdb database.
-    /// * `max_open_files`: The maximum number of open files to have. If
-    ///   `None`, the default will be used.
-    pub fn repair<P: AsRef<Path>>(path: P, max_open_files: Option<i32>) -> Result<()> {
-        let opts = get_options(max_open_files);
-        DB::repair(&opts, path)?;
-        Ok(())
+    /// * `property_compress_threshold`: The threshold, in bytes. If `None`,
+    ///   property values are never compressed.
+    pub fn property_compress_threshold(self, property_compress_threshold: Option<usize>) -> Self {
+        Self {
+            property_compress_threshold,
+            ..self
+        }
     }
-}
 
-impl Datastore for RocksdbDatastore {
-    fn sync(&self) -> Result<()> {
-        let db = self.db.clone();
-        let indexed_properties = self.indexed_properties.read().unwrap();
-        let db_ref = DBRef::new(&db, &indexed_properties);
-        VertexManager::new(db_ref).compact();
-        EdgeManager::new(db_ref).compact();
-        EdgeRangeManager::new(db_ref).compact();
-        EdgeRangeManager::new_reversed(db_ref).compact();
-        VertexPropertyManager::new(db_ref).compact();
-        EdgePropertyManager::new(db_ref).compact();
-        VertexPropertyValueManager::new(db_ref).compact();
-        EdgePropertyValueManager::new(db_ref).compact();
-        MetadataManager::new(&db).compact();
-        db.flush()?;
-        Ok(())
+    /// Maintains counters of each vertex's outbound and inbound edges as
+    /// edges are written, so that untyped edge counts are read in constant
+    /// time rather than by scanning the vertex's edges. This adds overhead to
+    /// every edge write. The counters aren't backfilled, so a database must
+    /// always be opened with the same setting it was created with; opening
+    /// it with a different setting errors.
+    pub fn degree_counters(self) -> Self {
+        Self {
+            degree_counters: true,
+            ..self
+        }
     }
 
-    fn create_vertex(&self, vertex: &Vertex) -> Result<bool> {
-        let db = self.db.clone();
-        let indexed_properties = self.indexed_properties.read().unwrap();
-        let db_ref = DBRef::new(&db, &indexed_properties);
-        let vertex_manager = VertexManager::new(db_ref);
+    /// Maintains an index of edges by type and update datetime, so that all
+    /// edges of a type can be listed from newest to oldest regardless of
+    /// their vertices, via `RocksdbDatastore::get_edges_by_type`. This
+    /// stores an extra key per edge, and adds a write to every edge create,
+    /// delete, touch and retype. The index isn't backfilled, so a database
+    /// must always be opened with the same setting it was created with;
+    /// opening it with a different setting errors.
+    pub fn edge_type_index(self) -> Self {
+        Self {
+            edge_type_index: true,
+            ..self
+        }
+    }
 
-        if vertex_manager.exists(vertex.id)? {
-            Ok(false)
-        } else {
-            let mut batch = WriteBatch::default();
-            vertex_manager.create(&mut batch, vertex)?;
-            db.write(batch)?;
-            Ok(true)
+    /// Tags every edge with a sequence number from a persisted counter as
+    /// it's written, which breaks ties between edges with the same update
+    /// datetime in edge ranges. Edges then list newest first in the order
+    /// they were written, rather than by the IDs of the vertices they
+    /// point to. This adds a write to every edge create and touch, and
+    /// changes the format of edge range keys, so a database must always be
+    /// opened with the same setting it was created with; opening it with a
+    /// different setting errors.
+    pub fn edge_sequence_numbers(self) -> Self {
+        Self {
+            edge_sequence_numbers: true,
+            ..self
         }
     }
 
-    fn get_vertices(&self, q: VertexQuery) -> Result<Vec<Vertex>> {
-        let db = self.db.clone();
-        let indexed_properties = self.indexed_properties.read().unwrap();
-        let db_ref = DBRef::new(&db, &indexed_properties);
-        let iter = execute_vertex_query(db_ref, q)?.into_iter();
+    /// Rounds edge update datetimes down to a multiple of a resolution
+    /// before storing them, so that writing an edge that was already
+    /// written within the same interval doesn't rewrite its range entries.
+    /// This cuts write churn for frequently touched edges, at the cost of
+    /// ordering: edges updated within the same interval are no longer
+    /// ordered by when they were updated, and their update datetimes are
+    /// reported rounded. With `edge_sequence_numbers`, rewriting an edge
+    /// still assigns it a new sequence number, so only touches are skipped.
+    ///
+    /// # Arguments
+    /// * `edge_time_resolution`: The resolution. A zero duration keeps full
+    ///   resolution, which is the default.
+    pub fn edge_time_resolution(self, edge_time_resolution: Duration) -> Self {
+        Self {
+            edge_time_resolution: Some(edge_time_resolution).filter(|resolution| !resolution.is_zero()),
+            ..self
+        }
+    }
 
-        let iter = iter.map(move |(id, t)| {
-            let vertex = Vertex::with_id(id, t);
-            Ok(vertex)
-        });
+    /// Splits edge ranges across multiple column families, chosen by the
+    /// vertex that owns each range, to spread the write load of vertices
+    /// with many edges. A database must always be opened with the same
+    /// number of shards it was created with; opening it with a different
+    /// number fails.
+    ///
+    /// # Arguments
+    /// * `edge_range_shards`: The number of shards. Values less than 1 are
+    ///   treated as 1, which is the default.
+    pub fn edge_range_shards(self, edge_range_shards: usize) -> Self {
+        Self {
+            edge_range_shards: edge_range_shards.max(1),
+            ..self
+        }
+    }
 
-        iter.collect()
+    /// Caches decoded vertex property values, so that repeatedly reading the
+    /// same properties doesn't decode their JSON every time. Cached values
+    /// are checked against the stored bytes on every read, so they're never
+    /// stale. This is disabled by default.
+    ///
+    /// # Arguments
+    /// * `property_cache_capacity`: The maximum number of values to cache,
+    ///   after which the least recently used are evicted. If 0, nothing is
+    ///   cached.
+    pub fn property_cache_capacity(self, property_cache_capacity: usize) -> Self {
+        Self {
+            property_cache_capacity,
+            ..self
+        }
     }
 
-    fn delete_vertices(&self, q: VertexQuery) -> Result<()> {
-        let db = self.db.clone();
-        let indexed_properties = self.indexed_properties.read().unwrap();
-        let db_ref = DBRef::new(&db, &indexed_properties);
-        let iter = execute_vertex_query(db_ref, q)?.into_iter();
-        let vertex_manager = VertexManager::new(db_ref);
-        let mut batch = WriteBatch::default();
+    /// Opens the datastore in a new temporary directory rather than at the
+    /// given path, and deletes the directory when the datastore is dropped.
+    /// This is useful for tests that want rocksdb's behavior without leaving
+    /// anything behind. Since nothing outlives the datastore, it isn't
+    /// flushed on drop.
+    pub fn temporary(self) -> Self {
+        Self {
+            temporary: true,
+            flush_on_drop: false,
+            ..self
+        }
+    }
 
-        for (id, _) in iter {
-            vertex_manager.delete(&mut batch, id)?;
+    /// Retries opening the database while another handle holds its lock,
+    /// e.g. while a previous process is still shutting down during a
+    /// restart. Other errors aren't retried. By default, opening a locked
+    /// database fails immediately.
+    ///
+    /// # Arguments
+    /// * `attempts`: The maximum number of retries. If every retry fails,
+    ///   the last error is returned.
+    /// * `delay`: How long to wait before the first retry. The delay doubles
+    ///   after each retry.
+    pub fn open_retry(self, attempts: usize, delay: Duration) -> Self {
+        Self {
+            open_retry_attempts: attempts,
+            open_retry_delay: delay,
+            ..self
         }
+    }
 
-        db.write(batch)?;
+    // The number of edge range shards is fixed when the database is created
+    fn check_edge_range_shards(&self, existing_cf_names: &[String]) -> Result<()> {
+        let edge_range_shards = existing_cf_names
+            .iter()
+            .filter(|cf_name| cf_name.as_str() == "edge_ranges:v1" || cf_name.starts_with("edge_ranges:v1:"))
+            .count();
+        if edge_range_shards > 0 && edge_range_shards != self.edge_range_shards {
+            return Err(Error::Datastore(
+                format!(
+                    "database has {} edge range shards, but was opened with {}",
+                    edge_range_shards, self.edge_range_shards
+                )
+                .into(),
+            ));
+        }
         Ok(())
     }
 
-    fn get_vertex_count(&self) -> Result<u64> {
-        let db = self.db.clone();
-        let indexed_properties = self.indexed_properties.read().unwrap();
-        let db_ref = DBRef::new(&db, &indexed_properties);
-        let vertex_manager = VertexManager::new(db_ref);
-        let iterator = vertex_manager.iterate_for_range(Uuid::default());
-        Ok(iterator.count() as u64)
+    // Settings that change what's stored for each edge are fixed when the
+    // database is created, since existing edges aren't migrated. Returns the
+    // settings that aren't recorded yet. Databases created before a setting
+    // was recorded are checked by whether they've stored anything for it,
+    // which they would have for any edge if it was enabled.
+    fn check_edge_settings(&self, db: &DB) -> Result<Vec<(&'static str, bool)>> {
+        let metadata_manager = MetadataManager::new(db);
+        let settings = [
+            (
+                "edge_sequence_numbers",
+                self.edge_sequence_numbers,
+                metadata_manager.get_edge_seq()? > 0,
+            ),
+            (
+                "degree_counters",
+                self.degree_counters,
+                has_entries(db, "vertex_degrees:v1"),
+            ),
+            (
+                "edge_type_index",
+                self.edge_type_index,
+                has_entries(db, "edges_by_type:v1"),
+            ),
+        ];
+
+        let mut unrecorded = Vec::new();
+        for (name, enabled, has_stored) in settings {
+            let recorded = metadata_manager.get_setting(name)?;
+            let created_with = match recorded {
+                Some(created_with) => Some(created_with),
+                None if has_stored => Some(true),
+                None if has_entries(db, "edges:v1") => Some(false),
+                None => None,
+            };
+            if let Some(created_with) = created_with {
+                if created_with != enabled {
+                    return Err(Error::Datastore(
+                        format!(
+                            "database was created with {} set to {}, but was opened with {}",
+                            name, created_with, enabled
+                        )
+                        .into(),
+                    ));
+                }
+            }
+            if recorded.is_none() {
+                unrecorded.push((name, enabled));
+            }
+        }
+        Ok(unrecorded)
     }
 
-    fn create_edge(&self, key: &EdgeKey) -> Result<bool> {
-        let db = self.db.clone();
-        let indexed_properties = self.indexed_properties.read().unwrap();
-        let db_ref = DBRef::new(&db, &indexed_properties);
-        let vertex_manager = VertexManager::new(db_ref);
-
-        if !vertex_manager.exists(key.outbound_id)? || !vertex_manager.exists(key.inbound_id)? {
-            Ok(false)
+    /// Opens a rocksdb datastore with this configuration.
+    ///
+    /// # Arguments
+    /// * `path`: The file path to the rocksdb database. Ignored if the
+    ///   datastore is temporary.
+    pub fn open<P: AsRef<Path>>(self, path: P) -> Result<RocksdbDatastore> {
+        let temp_dir = if self.temporary {
+            Some(tempfile::tempdir().map_err(|err| Error::Datastore(Box::new(err)))?)
         } else {
-            let edge_manager = EdgeManager::new(db_ref);
+            None
+        };
+        let path = temp_dir.as_ref().map_or(path.as_ref(), |temp_dir| temp_dir.path());
+        let opts = get_options(&self);
+
+        let existing_cf_names = DB::list_cf(&opts, path).ok();
+
+        // Check the number of shards before any missing shards would be
+        // created
+        if let Some(ref existing_cf_names) = existing_cf_names {
+            self.check_edge_range_shards(existing_cf_names)?;
+        }
+
+        let mut delay = self.open_retry_delay;
+        let mut attempts = 0;
+        let db = loop {
+            match open_db(&opts, path, &cf_names(&self)) {
+                Err(Error::Storage(err)) if attempts < self.open_retry_attempts && is_lock_error(&err) => {
+                    thread::sleep(delay);
+                    delay *= 2;
+                    attempts += 1;
+                }
+                res => break res?,
+            }
+        };
+
+        let metadata_manager = MetadataManager::new(&db);
+        let indexed_properties = metadata_manager.get_indexed_properties()?;
+        let edge_seq = metadata_manager.get_edge_seq()?;
+
+        let unrecorded_settings = self.check_edge_settings(&db)?;
+        if !unrecorded_settings.is_empty() {
             let mut batch = WriteBatch::default();
-            edge_manager.set(&mut batch, key.outbound_id, &key.t, key.inbound_id, Utc::now())?;
+            for (name, enabled) in unrecorded_settings {
+                metadata_manager.set_setting(&mut batch, name, enabled);
+            }
             db.write(batch)?;
-            Ok(true)
         }
-    }
-
-    fn get_edges(&self, q: EdgeQuery) -> Result<Vec<Edge>> {
-        let db = self.db.clone();
-        let indexed_properties = self.indexed_properties.read().unwrap();
-        let db_ref = DBRef::new(&db, &indexed_properties);
-        let iter = execute_edge_query(db_ref, q)?.into_iter();
 
-        let iter = iter.map(move |(out_id, t, update_datetime, in_id)| {
-            let key = EdgeKey::new(out_id, t, in_id);
-            let edge = Edge::new(key, update_datetime);
-            Ok(edge)
-        });
+        // Databases created before numeric values were indexed in order
+        // don't have them in that index yet
+        let has_numbers_cf = match existing_cf_names {
+            Some(existing_cf_names) => existing_cf_names
+                .iter()
+                .any(|cf_name| cf_name == "vertex_property_numbers:v1"),
+            None => true,
+        };
+        if !has_numbers_cf && !indexed_properties.is_empty() {
+            let edge_seq = EdgeSeq::new(edge_seq);
+            let db_ref = DBRef::new(&db, &indexed_properties, &self, &edge_seq);
+            reindex_vertex_property_values(db_ref)?;
+        }
 
-        iter.collect()
+        Ok(RocksdbDatastore {
+            db: Arc::new(db),
+            indexed_properties: Arc::new(RwLock::new(indexed_properties)),
+            property_cache: PropertyCache::new(self.property_cache_capacity),
+            config: self,
+            edge_seq: EdgeSeq::new(edge_seq),
+            vertex_subscribers: Mutex::new(Vec::new()),
+            temp_dir,
+        })
     }
+}
 
-    fn delete_edges(&self, q: EdgeQuery) -> Result<()> {
-        let db = self.db.clone();
-        let indexed_properties = self.indexed_properties.read().unwrap();
-        let db_ref = DBRef::new(&db, &indexed_properties);
-        let edge_manager = EdgeManager::new(db_ref);
-        let vertex_manager = VertexManager::new(db_ref);
-        let iter = execute_edge_query(db_ref, q)?;
-        let mut batch = WriteBatch::default();
+/// A change to a vertex, as delivered to subscribers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChangeEvent {
+    /// A vertex was created.
+    Insert(Vertex),
+    /// A vertex was deleted.
+    Remove(Uuid),
+}
 
-        for (out_id, t, update_datetime, in_id) in iter {
-            if vertex_manager.get(out_id)?.is_some() {
-                edge_manager.delete(&mut batch, out_id, &t, in_id, update_datetime)?;
-            };
+impl ChangeEvent {
+    fn id(&self) -> Uuid {
+        match self {
+            ChangeEvent::Insert(vertex) => vertex.id,
+            ChangeEvent::Remove(id) => *id,
         }
-
-        db.write(batch)?;
-        Ok(())
     }
+}
 
-    fn get_edge_count(&self, id: Uuid, t: Option<&Identifier>, direction: EdgeDirection) -> Result<u64> {
-        let db = self.db.clone();
-        let indexed_properties = self.indexed_properties.read().unwrap();
-        let db_ref = DBRef::new(&db, &indexed_properties);
-
-        let edge_range_manager = match direction {
-            EdgeDirection::Outbound => EdgeRangeManager::new(db_ref),
-            EdgeDirection::Inbound => EdgeRangeManager::new_reversed(db_ref),
-        };
+/// Everything that would be removed by deleting a vertex.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeletePlan {
+    /// The vertex itself.
+    pub vertex: Vertex,
+    /// The names of the vertex's properties.
+    pub vertex_properties: Vec<Identifier>,
+    /// The vertex's outbound and inbound edges.
+    pub edges: Vec<EdgeKey>,
+    /// The properties of the vertex's edges.
+    pub edge_properties: Vec<(EdgeKey, Identifier)>,
+}
 
-        let count = edge_range_manager.iterate_for_range(id, t, None)?.count();
+/// Writes staged by `RocksdbDatastore::with_batch`, which are applied
+/// atomically once the closure returns successfully.
+///
+/// Checks are made against the datastore as it was before the batch, plus
+/// the vertices and edges created earlier in the batch. Reads through the
+/// datastore won't see staged writes until the batch is applied.
+pub struct RocksdbBatch<'a> {
+    db_ref: DBRef<'a>,
+    property_cache: &'a PropertyCache,
+    session_cache: Option<SessionPropertyCache>,
+    batch: WriteBatch,
+    created_vertices: HashSet<Uuid>,
+    created_edges: HashSet<EdgeKey>,
+    events: Vec<ChangeEvent>,
+}
 
-        Ok(count as u64)
+impl<'a> RocksdbBatch<'a> {
+    fn vertex_exists(&self, id: Uuid) -> Result<bool> {
+        Ok(self.created_vertices.contains(&id) || VertexManager::new(self.db_ref).exists(id)?)
     }
 
-    fn get_vertex_properties(&self, q: VertexPropertyQuery) -> Result<Vec<VertexProperty>> {
-        let db = self.db.clone();
-        let indexed_properties = self.indexed_properties.read().unwrap();
-        let db_ref = DBRef::new(&db, &indexed_properties);
-        let manager = VertexPropertyManager::new(db_ref);
-        let mut properties = Vec::new();
+    /// Stages creating a vertex. Returns whether the vertex will be created;
+    /// if this is false, it's because a vertex with the same UUID already
+    /// exists.
+    ///
+    /// # Arguments
+    /// * `vertex`: The vertex to create.
+    pub fn create_vertex(&mut self, vertex: &Vertex) -> Result<bool> {
+        if self.vertex_exists(vertex.id)? {
+            return Ok(false);
+        }
 
-        for (id, _) in execute_vertex_query(db_ref, q.inner)?.into_iter() {
-            let value = manager.get(id, &q.name)?;
+        // Already checked against vertices staged in this batch, as well as
+        // the database
+        VertexManager::new(self.db_ref).upsert(&mut self.batch, vertex)?;
+        self.created_vertices.insert(vertex.id);
+        self.events.push(ChangeEvent::Insert(vertex.clone()));
+        Ok(true)
+    }
 
-            if let Some(value) = value {
-                properties.push(VertexProperty::new(id, value.0));
-            }
+    /// Stages creating an edge. If the edge already exists, this will update
+    /// it with a new update datetime. Returns whether the edge will be
+    /// created; if this is false, it's because one of the specified vertices
+    /// is missing.
+    ///
+    /// # Arguments
+    /// * `key`: The edge to create.
+    pub fn create_edge(&mut self, key: &EdgeKey) -> Result<bool> {
+        if !self.vertex_exists(key.outbound_id)? || !self.vertex_exists(key.inbound_id)? {
+            return Ok(false);
         }
 
-        Ok(properties)
+        // Edges are only checked for existence against the database, so
+        // duplicates within the batch are skipped
+        if self.created_edges.insert(key.clone()) {
+            EdgeManager::new(self.db_ref).set(&mut self.batch, key.outbound_id, &key.t, key.inbound_id, Utc::now())?;
+        }
+        Ok(true)
     }
 
-    fn get_all_vertex_properties(&self, q: VertexQuery) -> Result<Vec<VertexProperties>> {
-        let db = self.db.clone();
-        let indexed_properties = self.indexed_properties.read().unwrap();
-        let db_ref = DBRef::new(&db, &indexed_properties);
+    /// Stages setting a vertex property. As with `bulk_insert`, the vertex
+    /// isn't checked for existence.
+    ///
+    /// # Arguments
+    /// * `id`: The ID of the vertex.
+    /// * `name`: The property name.
+    /// * `value`: The property value.
+    pub fn set_vertex_property(&mut self, id: Uuid, name: &Identifier, value: serde_json::Value) -> Result<()> {
+        if let Some(ref mut session_cache) = self.session_cache {
+            session_cache.invalidate(id, name);
+        }
+        let manager = CachedVertexPropertyManager::new(self.db_ref, self.property_cache);
+        manager.set(&mut self.batch, id, name, &Json::new(value))
+    }
+
+    /// Caches the vertex property values read through
+    /// `get_vertex_property` for the rest of the batch, so that reading the
+    /// same property again neither reads nor decodes it. Setting a property
+    /// in the batch invalidates its cached value, but writes made outside
+    /// the batch aren't seen once a value is cached.
+    ///
+    /// # Arguments
+    /// * `capacity`: The maximum number of values to cache, after which the
+    ///   least recently used are evicted.
+    pub fn cache_property_reads(&mut self, capacity: usize) {
+        self.session_cache = Some(SessionPropertyCache::new(capacity));
+    }
+
+    /// Gets a vertex property. As with other reads in the batch, staged
+    /// writes aren't seen.
+    ///
+    /// # Arguments
+    /// * `id`: The ID of the vertex.
+    /// * `name`: The property name.
+    pub fn get_vertex_property(&mut self, id: Uuid, name: &Identifier) -> Result<Option<serde_json::Value>> {
+        if let Some(value) = self.session_cache.as_mut().and_then(|cache| cache.get(id, name)) {
+            return Ok(value.map(|value| value.0));
+        }
+
+        let manager = CachedVertexPropertyManager::new(self.db_ref, self.property_cache);
+        let value = manager.get(id, name)?;
+        if let Some(ref mut session_cache) = self.session_cache {
+            session_cache.insert(id, name, value.clone());
+        }
+        Ok(value.map(|value| value.0))
+    }
+
+    /// Stages setting an edge property. As with `bulk_insert`, the edge
+    /// isn't checked for existence.
+    ///
+    /// # Arguments
+    /// * `key`: The edge.
+    /// * `name`: The property name.
+    /// * `value`: The property value.
+    pub fn set_edge_property(&mut self, key: &EdgeKey, name: &Identifier, value: serde_json::Value) -> Result<()> {
+        let manager = EdgePropertyManager::new(self.db_ref);
+        manager.set(
+            &mut self.batch,
+            key.outbound_id,
+            &key.t,
+            key.inbound_id,
+            name,
+            &Json::new(value),
+        )
+    }
+}
+
+/// How much was removed by deleting a vertex.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DeleteCounts {
+    /// Whether the vertex existed.
+    pub vertex_removed: bool,
+    /// The number of the vertex's outbound and inbound edges removed.
+    pub edges_removed: u64,
+    /// The number of properties removed, of both the vertex and its edges.
+    pub properties_removed: u64,
+}
+
+/// Inconsistencies between column families found by an integrity check.
+/// An edge range entry whose datetime doesn't match its edge is reported as
+/// dangling, and the edge as missing a range entry.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IntegrityReport {
+    /// Outbound edge range entries with no matching edge, along with their
+    /// update datetimes.
+    pub dangling_edge_ranges: Vec<(EdgeKey, DateTime<Utc>)>,
+    /// Inbound edge range entries with no matching edge, along with their
+    /// update datetimes.
+    pub dangling_reversed_edge_ranges: Vec<(EdgeKey, DateTime<Utc>)>,
+    /// Edges that are missing an outbound or inbound range entry.
+    pub missing_edge_ranges: Vec<EdgeKey>,
+    /// Edges whose outbound or inbound vertex doesn't exist, e.g. after an
+    /// interrupted `delete_vertex_chunked`.
+    pub dangling_edges: Vec<EdgeKey>,
+    /// Edge type index entries with no matching edge, along with their
+    /// update datetimes. Only checked with `edge_type_index`.
+    pub dangling_type_index_entries: Vec<(EdgeKey, DateTime<Utc>)>,
+    /// Edges that are missing an edge type index entry. Only checked with
+    /// `edge_type_index`.
+    pub missing_type_index_entries: Vec<EdgeKey>,
+    /// Vertices whose degree counters don't match their edges. Only checked
+    /// with `degree_counters`.
+    pub mismatched_degrees: Vec<Uuid>,
+    /// Vertex properties whose vertex doesn't exist.
+    pub orphaned_vertex_properties: Vec<(Uuid, Identifier)>,
+    /// Edge properties whose edge doesn't exist.
+    pub orphaned_edge_properties: Vec<(EdgeKey, Identifier)>,
+}
+
+impl IntegrityReport {
+    /// Whether no inconsistencies were found.
+    pub fn is_empty(&self) -> bool {
+        self.dangling_edge_ranges.is_empty()
+            && self.dangling_reversed_edge_ranges.is_empty()
+            && self.missing_edge_ranges.is_empty()
+            && self.dangling_edges.is_empty()
+            && self.dangling_type_index_entries.is_empty()
+            && self.missing_type_index_entries.is_empty()
+            && self.mismatched_degrees.is_empty()
+            && self.orphaned_vertex_properties.is_empty()
+            && self.orphaned_edge_properties.is_empty()
+    }
+}
+
+/// An estimate of how much of the space a datastore takes on disk is
+/// reclaimable, e.g. from deleted or overwritten entries that haven't been
+/// compacted away yet.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MaintenanceReport {
+    /// The total size of the database's table files, in bytes.
+    pub size_on_disk: u64,
+    /// The total size of the keys and values that are still live, in bytes.
+    pub live_data_size: u64,
+    /// How many times larger the database is on disk than its live data. If
+    /// compression is enabled, this understates the amplification. Small
+    /// databases can report large ratios, since each table file has a fixed
+    /// overhead.
+    pub space_amplification: f64,
+}
+
+impl MaintenanceReport {
+    /// Whether enough space is reclaimable that the database should be
+    /// rebuilt. `Datastore::sync` compacts the database in place, and
+    /// `RocksdbDatastore::migrate_compression` rebuilds it into a fresh
+    /// copy offline.
+    pub fn should_rebuild(&self) -> bool {
+        self.space_amplification > REBUILD_SPACE_AMPLIFICATION
+    }
+}
+
+/// How much space `RocksdbDatastore::recompress` saved.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RecompressStats {
+    /// The total size of the database's table files before recompressing,
+    /// in bytes.
+    pub size_before: u64,
+    /// The total size of the database's table files after recompressing, in
+    /// bytes.
+    pub size_after: u64,
+}
+
+/// A datastore that is backed by rocksdb.
+///
+/// Every mutating method of the `Datastore` trait is applied as a single
+/// atomic write, and is visible to reads on any thread as soon as it returns.
+/// Some of the methods specific to rocksdb, like `delete_vertex_chunked`,
+/// write in several batches instead, and say so in their docs. There is no
+/// buffering of writes on top of rocksdb, so there is no separate
+/// read-your-writes mode to opt into. Writes are not synced to disk when they
+/// return, however; call `sync` for durability.
+#[derive(Debug)]
+pub struct RocksdbDatastore {
+    db: Arc<DB>,
+    indexed_properties: Arc<RwLock<HashSet<Identifier>>>,
+    property_cache: PropertyCache,
+    config: RocksdbConfig,
+    edge_seq: EdgeSeq,
+    vertex_subscribers: Mutex<Vec<(Option<Uuid>, mpsc::Sender<ChangeEvent>)>>,
+    // Declared after `db` so that the database is closed before the
+    // directory is deleted
+    temp_dir: Option<TempDir>,
+}
+
+impl RocksdbDatastore {
+    /// Creates a new rocksdb datastore.
+    ///
+    /// # Arguments
+    /// * `path`: The file path to the rocksdb database.
+    /// * `max_open_files`: The maximum number of open files to have. If
+    ///   `None`, the default will be used.
+    pub fn new<P: AsRef<Path>>(path: P, max_open_files: Option<i32>) -> Result<RocksdbDatastore> {
+        RocksdbConfig::default().max_open_files(max_open_files).open(path)
+    }
+
+    /// Gets the file path of the rocksdb database. For a temporary
+    /// datastore, this is the temporary directory it was opened in.
+    pub fn path(&self) -> &Path {
+        match self.temp_dir {
+            Some(ref temp_dir) => temp_dir.path(),
+            None => self.db.path(),
+        }
+    }
+
+    /// Gets the names of the properties that are indexed.
+    pub fn indexed_properties(&self) -> Vec<Identifier> {
+        self.indexed_properties.read().unwrap().iter().cloned().collect()
+    }
+
+    /// Gets the distinct names of the properties set on any vertex, in
+    /// sorted order. This scans the keys of every vertex property, but
+    /// doesn't decode any values.
+    pub fn vertex_property_names(&self) -> Result<Vec<Identifier>> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        VertexPropertyManager::new(db_ref).distinct_names()
+    }
+
+    /// Gets the distinct names of the properties set on any edge, in sorted
+    /// order. This scans the keys of every edge property, but doesn't decode
+    /// any values.
+    pub fn edge_property_names(&self) -> Result<Vec<Identifier>> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        EdgePropertyManager::new(db_ref).distinct_names()
+    }
+
+    /// Checks whether a vertex has at least `k` edges in a direction. Unless
+    /// degree counters are enabled, this reads up to `k` edges rather than
+    /// counting all of them, so it's much cheaper than `get_edge_count` for
+    /// vertices with many edges.
+    ///
+    /// # Arguments
+    /// * `id`: The id of the vertex.
+    /// * `direction`: The direction of edges to check.
+    /// * `k`: The number of edges to check for.
+    pub fn has_at_least_edges(&self, id: Uuid, direction: EdgeDirection, k: u64) -> Result<bool> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+
+        if self.config.degree_counters {
+            let edge_manager = EdgeManager::new(db_ref);
+            let degree = match direction {
+                EdgeDirection::Outbound => edge_manager.out_degree(id)?,
+                EdgeDirection::Inbound => edge_manager.in_degree(id)?,
+            };
+            return Ok(degree >= k);
+        }
+
+        let edge_range_manager = match direction {
+            EdgeDirection::Outbound => EdgeRangeManager::new(db_ref),
+            EdgeDirection::Inbound => EdgeRangeManager::new_reversed(db_ref),
+        };
+        edge_range_manager.has_at_least_edges(id, k as usize)
+    }
+
+    /// Gets the IDs of vertices whose value for an indexed property is a
+    /// number between `low` and `high`, inclusive. IDs are returned in
+    /// ascending order of the property value. Numbers are compared as
+    /// 64-bit floats, so integers beyond 2^53 may compare as equal to their
+    /// neighbors.
+    ///
+    /// Returns `Error::NotIndexed` if the property isn't indexed, and
+    /// `Error::TypeMismatch` if either bound isn't a number.
+    ///
+    /// # Arguments
+    /// * `name`: The property name.
+    /// * `low`: The lowest property value to include.
+    /// * `high`: The highest property value to include.
+    pub fn find_vertices_by_property_range(
+        &self,
+        name: &Identifier,
+        low: &serde_json::Value,
+        high: &serde_json::Value,
+    ) -> Result<Vec<Uuid>> {
+        let (low, high) = match (low.as_f64(), high.as_f64()) {
+            (Some(low), Some(high)) => (low, high),
+            _ => return Err(Error::TypeMismatch),
+        };
+
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        guard_indexed_property(db_ref, name)?;
+        let manager = VertexPropertyValueManager::new(db_ref);
+        let ids = manager.iterate_for_number_range(name, low, high).collect();
+        Ok(ids)
+    }
+
+    /// Runs a repair operation on the rocksdb database.
+    ///
+    /// # Arguments
+    /// * `path`: The file path to the rocksdb database.
+    /// * `max_open_files`: The maximum number of open files to have. If
+    ///   `None`, the default will be used.
+    pub fn repair<P: AsRef<Path>>(path: P, max_open_files: Option<i32>) -> Result<()> {
+        let config = RocksdbConfig::default().max_open_files(max_open_files);
+        DB::repair(&get_options(&config), path)?;
+        Ok(())
+    }
+
+    /// Copies a rocksdb database into a fresh database with a different
+    /// configuration, e.g. to switch compression on or off for data that's
+    /// already been written. This should be run offline, and `dst_path`
+    /// should not already contain a database. Entry counts of the two
+    /// databases are compared after copying. Both databases have the number
+    /// of edge range shards, and the other settings that are fixed when a
+    /// database is created, in `config`. Nothing is written to the existing database, and it's an
+    /// error if there isn't one at `src_path`.
+    ///
+    /// # Arguments
+    /// * `src_path`: The file path to the existing rocksdb database.
+    /// * `dst_path`: The file path to create the new rocksdb database at.
+    /// * `config`: The configuration of the new rocksdb database.
+    pub fn migrate_compression<P: AsRef<Path>, Q: AsRef<Path>>(
+        src_path: P,
+        dst_path: Q,
+        config: RocksdbConfig,
+    ) -> Result<()> {
+        // The source is opened directly rather than through `open`, so that
+        // neither it nor any of its column families or metadata are created
+        let src_path = src_path.as_ref();
+        let mut opts = get_options(&config);
+        opts.create_if_missing(false);
+        opts.create_missing_column_families(false);
+        let src_cf_names = DB::list_cf(&opts, src_path).map_err(|err| {
+            Error::Datastore(format!("no rocksdb database at {}: {}", src_path.display(), err).into())
+        })?;
+        config.check_edge_range_shards(&src_cf_names)?;
+        let src_cf_descriptors = src_cf_names
+            .iter()
+            .map(|cf_name| ColumnFamilyDescriptor::new(cf_name, opts.clone()));
+        let src_db = DB::open_cf_descriptors(&opts, src_path, src_cf_descriptors)?;
+        config.check_edge_settings(&src_db)?;
+        let dst = config.open(dst_path)?;
+
+        for cf_name in cf_names(&dst.config) {
+            // Databases created by older versions may not have every column
+            // family yet
+            let src_cf = match src_db.cf_handle(&cf_name) {
+                Some(src_cf) => src_cf,
+                None => continue,
+            };
+            let dst_cf = dst.db.cf_handle(&cf_name).unwrap();
+            let mut batch = WriteBatch::default();
+            let mut count = 0;
+
+            for (key, value) in src_db.iterator_cf(src_cf, IteratorMode::Start) {
+                batch.put_cf(dst_cf, key, value);
+                count += 1;
+                if batch.len() >= MIGRATE_BATCH_SIZE {
+                    dst.db.write(mem::take(&mut batch))?;
+                }
+            }
+
+            dst.db.write(batch)?;
+
+            let dst_count = dst.db.iterator_cf(dst_cf, IteratorMode::Start).count();
+            if dst_count != count {
+                return Err(Error::Datastore(
+                    format!(
+                        "migrated {} entries of `{}`, but the destination has {}",
+                        count, cf_name, dst_count
+                    )
+                    .into(),
+                ));
+            }
+        }
+
+        // Opening the source would have built this index if it was missing,
+        // so it's built in the copy instead
+        if !src_cf_names
+            .iter()
+            .any(|cf_name| cf_name == "vertex_property_numbers:v1")
+        {
+            let indexed_properties = MetadataManager::new(&dst.db).get_indexed_properties()?;
+            let db_ref = DBRef::new(&dst.db, &indexed_properties, &dst.config, &dst.edge_seq);
+            reindex_vertex_property_values(db_ref)?;
+        }
+
+        flush(&dst.db, &dst.config)?;
+        Ok(())
+    }
+
+    /// Rewrites every table file in the database in place, so that data
+    /// written under a different compression setting is re-encoded with the
+    /// current one, e.g. after reopening an uncompressed database with
+    /// compression enabled. Unlike `migrate_compression`, this runs online
+    /// and doesn't need a second copy of the database, although it's about
+    /// as expensive as a full compaction.
+    ///
+    /// Column families are rewritten one at a time, so there's never more
+    /// than one column family's worth of extra space in use. Rewriting is
+    /// idempotent, so if it's interrupted, it can just be run again.
+    pub fn recompress(&self) -> Result<RecompressStats> {
+        let mut stats = RecompressStats::default();
+        let mut opts = CompactOptions::default();
+        opts.set_exclusive_manual_compaction(true);
+        opts.set_bottommost_level_compaction(BottommostLevelCompaction::Force);
+
+        for cf_name in cf_names(&self.config) {
+            let cf = self.db.cf_handle(&cf_name).unwrap();
+            // Flush first, so that entries only in the memtable are written
+            // before the size is measured
+            self.db.flush_cf(cf)?;
+            stats.size_before += self.sst_files_size(&cf_name)?;
+            self.db
+                .compact_range_cf_opt(cf, Option::<&[u8]>::None, Option::<&[u8]>::None, &opts);
+            stats.size_after += self.sst_files_size(&cf_name)?;
+        }
+
+        Ok(stats)
+    }
+
+    fn sst_files_size(&self, cf_name: &str) -> Result<u64> {
+        let cf = self.db.cf_handle(cf_name).unwrap();
+        Ok(self
+            .db
+            .property_int_value_cf(cf, "rocksdb.total-sst-files-size")?
+            .unwrap_or(0))
+    }
+
+    /// Estimates how much space could be reclaimed by compacting or
+    /// rebuilding the database. This reads every live entry, so it's about
+    /// as expensive as a full scan.
+    pub fn maintenance_report(&self) -> Result<MaintenanceReport> {
+        let mut size_on_disk = 0;
+        let mut live_data_size = 0;
+
+        for cf_name in cf_names(&self.config) {
+            let cf = self.db.cf_handle(&cf_name).unwrap();
+            size_on_disk += self.sst_files_size(&cf_name)?;
+            for (k, v) in self.db.iterator_cf(cf, IteratorMode::Start) {
+                live_data_size += (k.len() + v.len()) as u64;
+            }
+        }
+
+        Ok(MaintenanceReport {
+            size_on_disk,
+            live_data_size,
+            space_amplification: size_on_disk as f64 / live_data_size.max(1) as f64,
+        })
+    }
+
+    /// Scans the datastore for inconsistencies between column families, e.g.
+    /// after a crash, without modifying anything.
+    pub fn check_integrity(&self) -> Result<IntegrityReport> {
+        self.scan_integrity(false)
+    }
+
+    /// Scans the datastore for inconsistencies between column families, like
+    /// `check_integrity`, and fixes them: dangling range and type index
+    /// entries, dangling edges and orphaned properties are deleted, missing
+    /// range and type index entries are recreated, and degree counters are
+    /// recounted. Returns what was found.
+    pub fn repair_integrity(&self) -> Result<IntegrityReport> {
+        self.scan_integrity(true)
+    }
+
+    fn scan_integrity(&self, repair: bool) -> Result<IntegrityReport> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        let vertex_manager = VertexManager::new(db_ref);
+        let edge_manager = EdgeManager::new(db_ref);
+        let edge_range_manager = EdgeRangeManager::new(db_ref);
+        let reversed_edge_range_manager = EdgeRangeManager::new_reversed(db_ref);
+        let vertex_property_manager = VertexPropertyManager::new(db_ref);
+        let edge_property_manager = EdgePropertyManager::new(db_ref);
+        let mut report = IntegrityReport::default();
+        let mut batch = WriteBatch::default();
+
+        for item in edge_range_manager.iterate_for_all_with_seq() {
+            let ((out_id, t, update_datetime, in_id), seq) = item?;
+            if edge_manager.get_with_seq(out_id, &t, in_id)? != Some((update_datetime, seq)) {
+                if repair {
+                    edge_range_manager.delete(&mut batch, out_id, &t, update_datetime, seq, in_id)?;
+                }
+                report
+                    .dangling_edge_ranges
+                    .push((EdgeKey::new(out_id, t, in_id), update_datetime));
+            }
+        }
+
+        for item in reversed_edge_range_manager.iterate_for_all_with_seq() {
+            let ((in_id, t, update_datetime, out_id), seq) = item?;
+            if edge_manager.get_with_seq(out_id, &t, in_id)? != Some((update_datetime, seq)) {
+                if repair {
+                    reversed_edge_range_manager.delete(&mut batch, in_id, &t, update_datetime, seq, out_id)?;
+                }
+                report
+                    .dangling_reversed_edge_ranges
+                    .push((EdgeKey::new(out_id, t, in_id), update_datetime));
+            }
+        }
+
+        if self.config.edge_type_index {
+            for (out_id, t, update_datetime, in_id) in edge_manager.iterate_type_index_for_all()? {
+                if edge_manager.get(out_id, &t, in_id)? != Some(update_datetime) {
+                    if repair {
+                        edge_manager.delete_type_index(&mut batch, out_id, &t, in_id, update_datetime);
+                    }
+                    report
+                        .dangling_type_index_entries
+                        .push((EdgeKey::new(out_id, t, in_id), update_datetime));
+                }
+            }
+        }
+
+        let mut degrees: HashMap<Uuid, (i64, i64)> = HashMap::new();
+        for item in edge_manager.iterate_for_all_with_seq() {
+            let ((out_id, t, update_datetime, in_id), seq) = item?;
+            if self.config.degree_counters {
+                degrees.entry(out_id).or_default().0 += 1;
+                degrees.entry(in_id).or_default().1 += 1;
+            }
+
+            // Dangling edges are deleted outright below, so their other
+            // entries aren't checked
+            if !vertex_manager.exists(out_id)? || !vertex_manager.exists(in_id)? {
+                report.dangling_edges.push(EdgeKey::new(out_id, t, in_id));
+                continue;
+            }
+
+            let has_range = edge_range_manager.exists(out_id, &t, update_datetime, seq, in_id)?;
+            let has_reversed_range = reversed_edge_range_manager.exists(in_id, &t, update_datetime, seq, out_id)?;
+            if !has_range || !has_reversed_range {
+                if repair {
+                    edge_range_manager.set(&mut batch, out_id, &t, update_datetime, seq, in_id)?;
+                    reversed_edge_range_manager.set(&mut batch, in_id, &t, update_datetime, seq, out_id)?;
+                }
+                report.missing_edge_ranges.push(EdgeKey::new(out_id, t.clone(), in_id));
+            }
+
+            if self.config.edge_type_index && !edge_manager.type_index_exists(out_id, &t, in_id, update_datetime)? {
+                if repair {
+                    edge_manager.set_type_index(&mut batch, out_id, &t, in_id, update_datetime);
+                }
+                report.missing_type_index_entries.push(EdgeKey::new(out_id, t, in_id));
+            }
+        }
+
+        // Dangling edges are still counted here. Their deletes below adjust
+        // the counters after they're overwritten, which leaves them correct.
+        if self.config.degree_counters {
+            for (id, stored) in edge_manager.iterate_degrees_for_all()? {
+                let counted = degrees.remove(&id).unwrap_or_default();
+                if stored != counted {
+                    if repair {
+                        edge_manager.set_degrees(&mut batch, id, counted.0, counted.1);
+                    }
+                    report.mismatched_degrees.push(id);
+                }
+            }
+            for (id, (out_degree, in_degree)) in degrees {
+                if repair {
+                    edge_manager.set_degrees(&mut batch, id, out_degree, in_degree);
+                }
+                report.mismatched_degrees.push(id);
+            }
+            report.mismatched_degrees.sort();
+        }
+
+        if repair {
+            for key in &report.dangling_edges {
+                edge_manager.delete_by_triple(&mut batch, key.outbound_id, &key.t, key.inbound_id)?;
+            }
+            if self.config.degree_counters {
+                for key in &report.dangling_edges {
+                    for id in [key.outbound_id, key.inbound_id] {
+                        if !vertex_manager.exists(id)? {
+                            edge_manager.delete_degrees(&mut batch, id);
+                        }
+                    }
+                }
+            }
+        }
+
+        for item in vertex_property_manager.iterate_names_for_all() {
+            let (id, name) = item?;
+            if !vertex_manager.exists(id)? {
+                if repair {
+                    vertex_property_manager.delete(&mut batch, id, &name)?;
+                }
+                report.orphaned_vertex_properties.push((id, name));
+            }
+        }
+
+        for item in edge_property_manager.iterate_names_for_all() {
+            let (out_id, t, in_id, name) = item?;
+            if edge_manager.get(out_id, &t, in_id)?.is_none() {
+                if repair {
+                    edge_property_manager.delete(&mut batch, out_id, &t, in_id, &name)?;
+                }
+                report
+                    .orphaned_edge_properties
+                    .push((EdgeKey::new(out_id, t, in_id), name));
+            }
+        }
+
+        if repair {
+            db_ref.write(batch)?;
+        }
+
+        Ok(report)
+    }
+
+    /// Deletes everything in the datastore, including property indexes, in a
+    /// single atomic write. This is much faster than deleting vertices
+    /// individually. Vertex subscribers aren't notified of the deletes.
+    pub fn clear(&self) -> Result<()> {
+        let mut indexed_properties = self.indexed_properties.write().unwrap();
+        let mut batch = WriteBatch::default();
+
+        for cf_name in cf_names(&self.config) {
+            let cf = self.db.cf_handle(&cf_name).unwrap();
+            // Range deletes exclude the end key, so the last key is deleted
+            // separately
+            if let Some((last_key, _)) = self.db.iterator_cf(cf, IteratorMode::End).next() {
+                batch.delete_range_cf(cf, Vec::new(), last_key.to_vec());
+                batch.delete_cf(cf, last_key);
+            }
+        }
+
+        DBRef::new(&self.db, &indexed_properties, &self.config, &self.edge_seq).write(batch)?;
+        indexed_properties.clear();
+        self.property_cache.clear();
+        Ok(())
+    }
+
+    /// Bumps the update datetime of an existing edge to now, without
+    /// otherwise changing it. This moves the edge to the front of edge range
+    /// queries. Returns whether the edge exists.
+    ///
+    /// # Arguments
+    /// * `key`: The edge to touch.
+    pub fn touch_edge(&self, key: &EdgeKey) -> Result<bool> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        let edge_manager = EdgeManager::new(db_ref);
+        let mut batch = WriteBatch::default();
+
+        if edge_manager.touch(&mut batch, key.outbound_id, &key.t, key.inbound_id, Utc::now())? {
+            db_ref.write(batch)?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Checks which of a set of edges exist, in a single sorted pass.
+    /// Returns whether each edge exists, in the same order as `keys`.
+    ///
+    /// # Arguments
+    /// * `keys`: The edges to check.
+    pub fn edges_exist(&self, keys: &[EdgeKey]) -> Result<Vec<bool>> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        let keys: Vec<_> = keys
+            .iter()
+            .map(|key| (key.outbound_id, key.t.clone(), key.inbound_id))
+            .collect();
+        EdgeManager::new(db_ref).exists_bulk(&keys)
+    }
+
+    /// Gets vertices along with their outbound edges, in order of vertex
+    /// ID. Each vertex's edges are read with one range scan, rather than a
+    /// separate query per vertex.
+    ///
+    /// # Arguments
+    /// * `t`: If set, only vertices of this type are returned.
+    /// * `start_id`: If set, only vertices with IDs higher than this are
+    ///   returned, so the ID of the last vertex of one page can be used to
+    ///   get the next page.
+    /// * `limit`: The maximum number of vertices to return.
+    pub fn get_vertices_with_edges(
+        &self,
+        t: Option<&Identifier>,
+        start_id: Option<Uuid>,
+        limit: u32,
+    ) -> Result<Vec<(Vertex, Vec<Edge>)>> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        let vertex_manager = VertexManager::new(db_ref);
+        let iter = vertex_manager.iterate_with_edges(start_id, t).take(limit as usize);
+        iter.map(|item| {
+            let ((id, t), edges) = item?;
+            let edges = edges
+                .into_iter()
+                .map(|(out_id, t, update_datetime, in_id)| Edge::new(EdgeKey::new(out_id, t, in_id), update_datetime))
+                .collect();
+            Ok((Vertex::with_id(id, t), edges))
+        })
+        .collect()
+    }
+
+    /// Checks whether a vertex has a property, without reading its value.
+    ///
+    /// # Arguments
+    /// * `id`: The ID of the vertex.
+    /// * `name`: The property name.
+    pub fn has_vertex_property(&self, id: Uuid, name: &Identifier) -> Result<bool> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        VertexPropertyManager::new(db_ref).contains(id, name)
+    }
+
+    /// Checks whether an edge has a property, without reading its value.
+    ///
+    /// # Arguments
+    /// * `key`: The edge.
+    /// * `name`: The property name.
+    pub fn has_edge_property(&self, key: &EdgeKey, name: &Identifier) -> Result<bool> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        EdgePropertyManager::new(db_ref).contains(key.outbound_id, &key.t, key.inbound_id, name)
+    }
+
+    /// Gets the properties of a vertex whose names start with a given
+    /// prefix, e.g. `meta_` for a set of properties that share a namespace.
+    /// Properties are returned in order of name.
+    ///
+    /// # Arguments
+    /// * `id`: The ID of the vertex.
+    /// * `name_prefix`: The prefix that property names must start with.
+    pub fn get_vertex_properties_prefixed(&self, id: Uuid, name_prefix: &str) -> Result<Vec<NamedProperty>> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        let vertex_property_manager = VertexPropertyManager::new(db_ref);
+        let mut properties = Vec::new();
+        for item in vertex_property_manager.iterate_for_owner_prefixed(id, name_prefix)? {
+            let ((_, name), value) = item?;
+            properties.push(NamedProperty::new(name, value.0));
+        }
+        Ok(properties)
+    }
+
+    /// Creates an undirected edge. This is stored as a pair of directed edges,
+    /// one in each direction, written together, so the edge is found by
+    /// outbound and inbound queries from either vertex, and `get_edges` finds
+    /// it under either orientation of the key. Properties belong to each
+    /// directed half separately. Returns whether both vertices exist; if they
+    /// don't, no edge is created.
+    ///
+    /// # Arguments
+    /// * `key`: The edge to create. Its orientation doesn't matter.
+    pub fn create_undirected_edge(&self, key: &EdgeKey) -> Result<bool> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        let vertex_manager = VertexManager::new(db_ref);
+
+        if !vertex_manager.exists(key.outbound_id)? || !vertex_manager.exists(key.inbound_id)? {
+            Ok(false)
+        } else {
+            let edge_manager = EdgeManager::new(db_ref);
+            let mut batch = WriteBatch::default();
+            edge_manager.set_undirected(&mut batch, key.outbound_id, &key.t, key.inbound_id, Utc::now())?;
+            db_ref.write(batch)?;
+            Ok(true)
+        }
+    }
+
+    /// Deletes an edge along with its properties, without needing its update
+    /// datetime. Returns whether the edge existed.
+    ///
+    /// # Arguments
+    /// * `key`: The edge to delete.
+    pub fn delete_edge(&self, key: &EdgeKey) -> Result<bool> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        let mut batch = WriteBatch::default();
+        let existed = EdgeManager::new(db_ref).delete_by_triple(&mut batch, key.outbound_id, &key.t, key.inbound_id)?;
+        db_ref.write(batch)?;
+        Ok(existed)
+    }
+
+    /// Deletes both directed halves of an undirected edge, along with their
+    /// properties. Deleting with `delete_edges` only removes the half that
+    /// matches the query. Returns whether either half existed.
+    ///
+    /// # Arguments
+    /// * `key`: The edge to delete. Its orientation doesn't matter.
+    pub fn delete_undirected_edge(&self, key: &EdgeKey) -> Result<bool> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        let edge_manager = EdgeManager::new(db_ref);
+        let mut batch = WriteBatch::default();
+        let existed = edge_manager.delete_undirected(&mut batch, key.outbound_id, &key.t, key.inbound_id)?;
+        db_ref.write(batch)?;
+        Ok(existed)
+    }
+
+    /// Changes the type of an existing edge, keeping its update datetime and
+    /// properties. An edge that already has the new type is replaced.
+    /// Returns whether the edge exists.
+    ///
+    /// # Arguments
+    /// * `key`: The edge to retype.
+    /// * `new_t`: The new type of the edge.
+    pub fn retype_edge(&self, key: &EdgeKey, new_t: &Identifier) -> Result<bool> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        let edge_manager = EdgeManager::new(db_ref);
+        let mut batch = WriteBatch::default();
+
+        if edge_manager.retype(&mut batch, key.outbound_id, &key.t, key.inbound_id, new_t)? {
+            db_ref.write(batch)?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Changes the type of an existing vertex, keeping its properties and
+    /// edges. Edges are unaffected, since their keys only embed edge types.
+    /// Returns whether the vertex exists.
+    ///
+    /// # Arguments
+    /// * `id`: The ID of the vertex to retype.
+    /// * `new_t`: The new type of the vertex.
+    pub fn set_vertex_type(&self, id: Uuid, new_t: &Identifier) -> Result<bool> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        let mut batch = WriteBatch::default();
+
+        if VertexManager::new(db_ref).set_type(&mut batch, id, new_t)? {
+            db_ref.write(batch)?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Sets properties on many edges in a single atomic write. Like
+    /// `bulk_insert`, this does not check that the edges exist.
+    ///
+    /// # Arguments
+    /// * `items`: The edges, property names and values to set.
+    pub fn set_edge_properties_many(&self, items: &[(EdgeKey, Identifier, serde_json::Value)]) -> Result<()> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        let mut batch = WriteBatch::default();
+        let items: Vec<_> = items
+            .iter()
+            .map(|(key, name, value)| {
+                (
+                    key.outbound_id,
+                    key.t.clone(),
+                    key.inbound_id,
+                    name.clone(),
+                    Json::new(value.clone()),
+                )
+            })
+            .collect();
+        EdgePropertyManager::new(db_ref).set_many(&mut batch, &items)?;
+        db_ref.write(batch)?;
+        Ok(())
+    }
+
+    /// Gets edges of a given type across all vertices, from the most to the
+    /// least recently updated. This requires the edge type index to be
+    /// enabled, and otherwise fails with `Error::Unsupported`.
+    ///
+    /// # Arguments
+    /// * `t`: The type of edges to get.
+    /// * `high`: If set, only edges updated at or before this datetime are
+    ///   returned.
+    /// * `limit`: The maximum number of edges to return.
+    pub fn get_edges_by_type(&self, t: &Identifier, high: Option<DateTime<Utc>>, limit: u32) -> Result<Vec<Edge>> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        let edge_manager = EdgeManager::new(db_ref);
+        let iter = edge_manager.iterate_all_by_type(t, high)?.take(limit as usize);
+        iter.map(|item| {
+            let (out_id, t, update_datetime, in_id) = item?;
+            Ok(Edge::new(EdgeKey::new(out_id, t, in_id), update_datetime))
+        })
+        .collect()
+    }
+
+    /// Deletes every outbound edge of a given type from a vertex, along with
+    /// the edges' properties. Returns the number of edges deleted.
+    ///
+    /// # Arguments
+    /// * `id`: The ID of the vertex to delete edges from.
+    /// * `t`: The type of edges to delete.
+    pub fn delete_edges_by_type(&self, id: Uuid, t: &Identifier) -> Result<u64> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        let mut batch = WriteBatch::default();
+        let count = EdgeManager::new(db_ref).delete_by_type(&mut batch, id, t)?;
+        db_ref.write(batch)?;
+        Ok(count)
+    }
+
+    /// Deletes a vertex, along with its properties and all of its edges,
+    /// spreading the edge deletions across multiple threads. This is useful
+    /// for vertices with a very large number of edges.
+    ///
+    /// Unlike `delete_vertices`, this is not atomic: edges are deleted in
+    /// batches as they are scanned, and the vertex itself is deleted last. If
+    /// an error occurs, some edges may already be gone while the vertex
+    /// remains, in which case the delete can be retried.
+    ///
+    /// # Arguments
+    /// * `id`: The ID of the vertex to delete.
+    /// * `num_threads`: The number of threads to delete edges with.
+    pub fn delete_vertex_parallel(&self, id: Uuid, num_threads: usize) -> Result<()> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        VertexManager::new(db_ref).delete_parallel(id, num_threads)?;
+        self.notify_vertex_subscribers(vec![ChangeEvent::Remove(id)]);
+        Ok(())
+    }
+
+    /// Deletes a vertex, along with its properties and all of its edges,
+    /// writing the deletions in chunks rather than one batch. This bounds the
+    /// memory used to delete vertices with a very large number of edges.
+    ///
+    /// Unlike `delete_vertices`, this is not atomic: each chunk is written as
+    /// it fills up, and the vertex itself is deleted last. If an error
+    /// occurs, some edges may already be gone while the vertex remains, in
+    /// which case the delete can be retried.
+    ///
+    /// # Arguments
+    /// * `id`: The ID of the vertex to delete.
+    /// * `chunk_size`: The number of staged removals to write at a time.
+    pub fn delete_vertex_chunked(&self, id: Uuid, chunk_size: usize) -> Result<()> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        VertexManager::new(db_ref).delete_chunked(id, chunk_size)?;
+        self.notify_vertex_subscribers(vec![ChangeEvent::Remove(id)]);
+        Ok(())
+    }
+
+    /// Creates vertices from an iterator, writing them every `batch_size`
+    /// vertices rather than collecting them up front, so that memory stays
+    /// bounded for very large imports. Like `bulk_insert`, existing vertices
+    /// are overwritten rather than skipped. Returns how many vertices were
+    /// written.
+    ///
+    /// This is not atomic: if an error occurs, the batches written before it
+    /// remain.
+    ///
+    /// # Arguments
+    /// * `vertices`: The vertices to create.
+    /// * `batch_size`: The number of vertices to write at a time.
+    pub fn create_vertices_stream<I>(&self, vertices: I, batch_size: usize) -> Result<u64>
+    where
+        I: IntoIterator<Item = Vertex>,
+    {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        VertexManager::new(db_ref).create_stream(vertices, batch_size, |written| {
+            self.notify_vertex_subscribers(written.into_iter().map(ChangeEvent::Insert).collect());
+        })
+    }
+
+    /// Runs a closure that stages writes into a batch, and applies them
+    /// atomically if it succeeds. If the closure returns an error, nothing it
+    /// staged is written, and the error is returned.
+    ///
+    /// # Arguments
+    /// * `f`: The closure to run.
+    pub fn with_batch<F>(&self, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut RocksdbBatch) -> Result<()>,
+    {
+        self.apply_batch(f, false)
+    }
+
+    /// Like `with_batch`, but doesn't return until the batch is on disk, so
+    /// the writes survive a crash or power loss once this returns `Ok`. This
+    /// is much slower than `with_batch`, so it's best kept for writes that
+    /// must be durable before they're acknowledged.
+    ///
+    /// # Arguments
+    /// * `f`: The closure to run.
+    pub fn with_durable_batch<F>(&self, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut RocksdbBatch) -> Result<()>,
+    {
+        self.apply_batch(f, true)
+    }
+
+    fn apply_batch<F>(&self, f: F, durable: bool) -> Result<()>
+    where
+        F: FnOnce(&mut RocksdbBatch) -> Result<()>,
+    {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        let mut batch = RocksdbBatch {
+            db_ref,
+            property_cache: &self.property_cache,
+            session_cache: None,
+            batch: WriteBatch::default(),
+            created_vertices: HashSet::new(),
+            created_edges: HashSet::new(),
+            events: Vec::new(),
+        };
+
+        f(&mut batch)?;
+        if durable {
+            db_ref.write_durable(batch.batch)?;
+        } else {
+            db_ref.write(batch.batch)?;
+        }
+        self.notify_vertex_subscribers(batch.events);
+        Ok(())
+    }
+
+    /// Gets the number of distinct vertices a vertex is connected to in one
+    /// direction. Unlike `get_edge_count`, a neighbor connected by edges of
+    /// several types is only counted once.
+    ///
+    /// # Arguments
+    /// * `id`: The ID of the vertex.
+    /// * `direction`: The direction of edges to follow.
+    pub fn get_distinct_neighbor_count(&self, id: Uuid, direction: EdgeDirection) -> Result<u64> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        EdgeManager::new(db_ref).distinct_neighbors(id, direction)
+    }
+
+    /// Deletes a vertex, along with its properties and all of its edges, and
+    /// reports how much was removed.
+    ///
+    /// # Arguments
+    /// * `id`: The ID of the vertex to delete.
+    pub fn delete_vertex_counted(&self, id: Uuid) -> Result<DeleteCounts> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        let mut batch = WriteBatch::default();
+        let counts = VertexManager::new(db_ref).delete(&mut batch, id, &mut HashSet::new())?;
+        db_ref.write(batch)?;
+        if counts.vertex_removed {
+            self.notify_vertex_subscribers(vec![ChangeEvent::Remove(id)]);
+        }
+        Ok(counts)
+    }
+
+    /// Reports everything that deleting a vertex would remove, without
+    /// modifying anything. Returns `None` if the vertex doesn't exist.
+    ///
+    /// # Arguments
+    /// * `id`: The ID of the vertex.
+    pub fn preview_vertex_delete(&self, id: Uuid) -> Result<Option<DeletePlan>> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        VertexManager::new(db_ref).delete_preview(id)
+    }
+
+    /// Subscribes to vertex creations and deletions. Events are delivered
+    /// in the order their writes completed. The returned iterator blocks
+    /// until the next event, and ends once the datastore is dropped.
+    ///
+    /// # Arguments
+    /// * `id`: If specified, only events for the vertex with this ID are
+    ///   delivered.
+    pub fn subscribe_vertices(&self, id: Option<Uuid>) -> impl Iterator<Item = ChangeEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.vertex_subscribers.lock().unwrap().push((id, sender));
+        receiver.into_iter()
+    }
+
+    fn notify_vertex_subscribers(&self, events: Vec<ChangeEvent>) {
+        let mut subscribers = self.vertex_subscribers.lock().unwrap();
+
+        // Drop subscribers whose receiving end has gone away
+        subscribers.retain(|(id, sender)| {
+            events
+                .iter()
+                .filter(|event| match id {
+                    Some(id) => *id == event.id(),
+                    None => true,
+                })
+                .all(|event| sender.send(event.clone()).is_ok())
+        });
+    }
+}
+
+impl Drop for RocksdbDatastore {
+    fn drop(&mut self) {
+        if self.config.flush_on_drop {
+            // There's no way to surface an error from here, so a failed
+            // flush is ignored
+            let _ = flush(&self.db, &self.config);
+        }
+    }
+}
+
+impl Datastore for RocksdbDatastore {
+    fn sync(&self) -> Result<()> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        VertexManager::new(db_ref).compact();
+        EdgeManager::new(db_ref).compact();
+        EdgeRangeManager::new(db_ref).compact();
+        EdgeRangeManager::new_reversed(db_ref).compact();
+        VertexPropertyManager::new(db_ref).compact();
+        EdgePropertyManager::new(db_ref).compact();
+        VertexPropertyValueManager::new(db_ref).compact();
+        EdgePropertyValueManager::new(db_ref).compact();
+        MetadataManager::new(&db).compact();
+        flush(&db, &self.config)
+    }
+
+    fn create_vertex(&self, vertex: &Vertex) -> Result<bool> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        let vertex_manager = VertexManager::new(db_ref);
+
+        let mut batch = WriteBatch::default();
+        match vertex_manager.create(&mut batch, vertex) {
+            Ok(()) => {}
+            Err(Error::UuidTaken) => return Ok(false),
+            Err(err) => return Err(err),
+        }
+        db_ref.write(batch)?;
+        self.notify_vertex_subscribers(vec![ChangeEvent::Insert(vertex.clone())]);
+        Ok(true)
+    }
+
+    fn get_vertices(&self, q: VertexQuery) -> Result<Vec<Vertex>> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        let iter = execute_vertex_query(db_ref, q)?.into_iter();
+
+        let iter = iter.map(move |(id, t)| {
+            let vertex = Vertex::with_id(id, t);
+            Ok(vertex)
+        });
+
+        iter.collect()
+    }
+
+    fn delete_vertices(&self, q: VertexQuery) -> Result<()> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        let iter = execute_vertex_query(db_ref, q)?.into_iter();
+        let vertex_manager = VertexManager::new(db_ref);
+        let mut batch = WriteBatch::default();
+        let mut events = Vec::new();
+
+        // Queries can return the same vertex more than once, but it should
+        // only be deleted once
+        let mut deleted_ids = HashSet::new();
+        let mut deleted_edges = HashSet::new();
+
+        for (id, _) in iter {
+            if deleted_ids.insert(id) {
+                vertex_manager.delete(&mut batch, id, &mut deleted_edges)?;
+                events.push(ChangeEvent::Remove(id));
+            }
+        }
+
+        db_ref.write(batch)?;
+        self.notify_vertex_subscribers(events);
+        Ok(())
+    }
+
+    fn vertices_exist(&self, ids: &[Uuid]) -> Result<Vec<bool>> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        VertexManager::new(db_ref).exists_bulk(ids)
+    }
+
+    fn get_vertex_count(&self) -> Result<u64> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        let vertex_manager = VertexManager::new(db_ref);
+        let iterator = vertex_manager.iterate_for_range(Uuid::default());
+        Ok(iterator.count() as u64)
+    }
+
+    fn create_edge(&self, key: &EdgeKey) -> Result<bool> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        let vertex_manager = VertexManager::new(db_ref);
+
+        if !vertex_manager.exists(key.outbound_id)? || !vertex_manager.exists(key.inbound_id)? {
+            Ok(false)
+        } else {
+            let edge_manager = EdgeManager::new(db_ref);
+            let mut batch = WriteBatch::default();
+            edge_manager.set(&mut batch, key.outbound_id, &key.t, key.inbound_id, Utc::now())?;
+            db_ref.write(batch)?;
+            Ok(true)
+        }
+    }
+
+    fn get_edges(&self, q: EdgeQuery) -> Result<Vec<Edge>> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        let iter = execute_edge_query(db_ref, q)?.into_iter();
+
+        let iter = iter.map(move |(out_id, t, update_datetime, in_id)| {
+            let key = EdgeKey::new(out_id, t, in_id);
+            let edge = Edge::new(key, update_datetime);
+            Ok(edge)
+        });
+
+        iter.collect()
+    }
+
+    fn delete_edges(&self, q: EdgeQuery) -> Result<()> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        let edge_manager = EdgeManager::new(db_ref);
+        let vertex_manager = VertexManager::new(db_ref);
+        let iter = execute_edge_query(db_ref, q)?;
+        let mut batch = WriteBatch::default();
+
+        // Queries can return the same edge more than once, but it should
+        // only be deleted once
+        let iter: HashSet<EdgeRangeItem> = iter.into_iter().collect();
+
+        for (out_id, t, update_datetime, in_id) in iter {
+            if vertex_manager.get(out_id)?.is_some() {
+                edge_manager.delete(&mut batch, out_id, &t, in_id, update_datetime)?;
+            };
+        }
+
+        db_ref.write(batch)?;
+        Ok(())
+    }
+
+    fn get_edge_count(&self, id: Uuid, t: Option<&Identifier>, direction: EdgeDirection) -> Result<u64> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+
+        if t.is_none() && self.config.degree_counters {
+            let edge_manager = EdgeManager::new(db_ref);
+            return match direction {
+                EdgeDirection::Outbound => edge_manager.out_degree(id),
+                EdgeDirection::Inbound => edge_manager.in_degree(id),
+            };
+        }
+
+        let edge_range_manager = match direction {
+            EdgeDirection::Outbound => EdgeRangeManager::new(db_ref),
+            EdgeDirection::Inbound => EdgeRangeManager::new_reversed(db_ref),
+        };
+
+        let count = edge_range_manager.iterate_for_range(id, t, None)?.count();
+
+        Ok(count as u64)
+    }
+
+    fn get_vertex_properties(&self, q: VertexPropertyQuery) -> Result<Vec<VertexProperty>> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        let manager = CachedVertexPropertyManager::new(db_ref, &self.property_cache);
+        let mut properties = Vec::new();
+
+        for (id, _) in execute_vertex_query(db_ref, q.inner)?.into_iter() {
+            let value = manager.get(id, &q.name)?;
+
+            if let Some(value) = value {
+                properties.push(VertexProperty::new(id, value.0));
+            }
+        }
+
+        Ok(properties)
+    }
+
+    fn get_all_vertex_properties(&self, q: VertexQuery) -> Result<Vec<VertexProperties>> {
+        let db = self.db.clone();
+        let indexed_properties = self.indexed_properties.read().unwrap();
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
         let iter = execute_vertex_query(db_ref, q)?.into_iter();
         let manager = VertexPropertyManager::new(db_ref);
 
@@ -610,8 +2265,8 @@ impl Datastore for RocksdbDatastore {
     fn set_vertex_properties(&self, q: VertexPropertyQuery, value: serde_json::Value) -> Result<()> {
         let db = self.db.clone();
         let indexed_properties = self.indexed_properties.read().unwrap();
-        let db_ref = DBRef::new(&db, &indexed_properties);
-        let manager = VertexPropertyManager::new(db_ref);
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        let manager = CachedVertexPropertyManager::new(db_ref, &self.property_cache);
         let mut batch = WriteBatch::default();
 
         let wrapped_value = Json::new(value);
@@ -619,29 +2274,29 @@ impl Datastore for RocksdbDatastore {
             manager.set(&mut batch, id, &q.name, &wrapped_value)?;
         }
 
-        db.write(batch)?;
+        db_ref.write(batch)?;
         Ok(())
     }
 
     fn delete_vertex_properties(&self, q: VertexPropertyQuery) -> Result<()> {
         let db = self.db.clone();
         let indexed_properties = self.indexed_properties.read().unwrap();
-        let db_ref = DBRef::new(&db, &indexed_properties);
-        let manager = VertexPropertyManager::new(db_ref);
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
+        let manager = CachedVertexPropertyManager::new(db_ref, &self.property_cache);
         let mut batch = WriteBatch::default();
 
         for (id, _) in execute_vertex_query(db_ref, q.inner)?.into_iter() {
             manager.delete(&mut batch, id, &q.name)?;
         }
 
-        db.write(batch)?;
+        db_ref.write(batch)?;
         Ok(())
     }
 
     fn get_edge_properties(&self, q: EdgePropertyQuery) -> Result<Vec<EdgeProperty>> {
         let db = self.db.clone();
         let indexed_properties = self.indexed_properties.read().unwrap();
-        let db_ref = DBRef::new(&db, &indexed_properties);
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
         let manager = EdgePropertyManager::new(db_ref);
         let mut properties = Vec::new();
 
@@ -660,7 +2315,7 @@ impl Datastore for RocksdbDatastore {
     fn get_all_edge_properties(&self, q: EdgeQuery) -> Result<Vec<EdgeProperties>> {
         let db = self.db.clone();
         let indexed_properties = self.indexed_properties.read().unwrap();
-        let db_ref = DBRef::new(&db, &indexed_properties);
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
         let iter = execute_edge_query(db_ref, q)?.into_iter();
         let manager = EdgePropertyManager::new(db_ref);
 
@@ -682,7 +2337,7 @@ impl Datastore for RocksdbDatastore {
     fn set_edge_properties(&self, q: EdgePropertyQuery, value: serde_json::Value) -> Result<()> {
         let db = self.db.clone();
         let indexed_properties = self.indexed_properties.read().unwrap();
-        let db_ref = DBRef::new(&db, &indexed_properties);
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
         let manager = EdgePropertyManager::new(db_ref);
         let mut batch = WriteBatch::default();
 
@@ -691,14 +2346,14 @@ impl Datastore for RocksdbDatastore {
             manager.set(&mut batch, out_id, &t, in_id, &q.name, &wrapped_value)?;
         }
 
-        db.write(batch)?;
+        db_ref.write(batch)?;
         Ok(())
     }
 
     fn delete_edge_properties(&self, q: EdgePropertyQuery) -> Result<()> {
         let db = self.db.clone();
         let indexed_properties = self.indexed_properties.read().unwrap();
-        let db_ref = DBRef::new(&db, &indexed_properties);
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
         let manager = EdgePropertyManager::new(db_ref);
         let mut batch = WriteBatch::default();
 
@@ -706,7 +2361,7 @@ impl Datastore for RocksdbDatastore {
             manager.delete(&mut batch, out_id, &t, in_id, &q.name)?;
         }
 
-        db.write(batch)?;
+        db_ref.write(batch)?;
         Ok(())
     }
 
@@ -715,20 +2370,27 @@ impl Datastore for RocksdbDatastore {
     fn bulk_insert(&self, items: Vec<BulkInsertItem>) -> Result<()> {
         let db = self.db.clone();
         let indexed_properties = self.indexed_properties.read().unwrap();
-        let db_ref = DBRef::new(&db, &indexed_properties);
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
         let vertex_manager = VertexManager::new(db_ref);
         let edge_manager = EdgeManager::new(db_ref);
-        let vertex_property_manager = VertexPropertyManager::new(db_ref);
+        let vertex_property_manager = CachedVertexPropertyManager::new(db_ref, &self.property_cache);
         let edge_property_manager = EdgePropertyManager::new(db_ref);
         let mut batch = WriteBatch::default();
+        let mut events = Vec::new();
+        let mut inserted_edges = HashSet::new();
 
         for item in items {
             match item {
-                BulkInsertItem::Vertex(ref vertex) => {
-                    vertex_manager.create(&mut batch, vertex)?;
+                BulkInsertItem::Vertex(vertex) => {
+                    vertex_manager.upsert(&mut batch, &vertex)?;
+                    events.push(ChangeEvent::Insert(vertex));
                 }
                 BulkInsertItem::Edge(ref key) => {
-                    edge_manager.set(&mut batch, key.outbound_id, &key.t, key.inbound_id, Utc::now())?;
+                    // Edges are only checked for existence against the
+                    // database, so duplicates within the batch are skipped
+                    if inserted_edges.insert(key.clone()) {
+                        edge_manager.set(&mut batch, key.outbound_id, &key.t, key.inbound_id, Utc::now())?;
+                    }
                 }
                 BulkInsertItem::VertexProperty(id, ref name, ref value) => {
                     vertex_property_manager.set(&mut batch, id, name, &Json::new(value.clone()))?;
@@ -746,7 +2408,8 @@ impl Datastore for RocksdbDatastore {
             }
         }
 
-        self.db.write(batch)?;
+        db_ref.write(batch)?;
+        self.notify_vertex_subscribers(events);
         Ok(())
     }
 
@@ -757,7 +2420,7 @@ impl Datastore for RocksdbDatastore {
         }
 
         let db = self.db.clone();
-        let db_ref = DBRef::new(&db, &indexed_properties);
+        let db_ref = DBRef::new(&db, &indexed_properties, &self.config, &self.edge_seq);
         let mut batch = WriteBatch::default();
         let vertex_manager = VertexManager::new(db_ref);
         let edge_range_manager = EdgeRangeManager::new(db_ref);
@@ -782,7 +2445,7 @@ impl Datastore for RocksdbDatastore {
             }
         }
 
-        db.write(batch)?;
+        db_ref.write(batch)?;
         Ok(())
     }
 }
@@ -1,15 +1,25 @@
-use std::collections::HashSet;
+use std::cmp::max;
+use std::collections::{BTreeSet, HashSet};
 use std::io::Cursor;
+use std::mem;
 use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
 use std::u8;
 
-use crate::errors::Result;
+use crate::errors::{Error, Result};
 use crate::models;
+use crate::rdb::cache::PropertyCache;
+use crate::rdb::compress;
+use crate::rdb::datastore::{flush, DeleteCounts, DeletePlan, RocksdbConfig};
 use crate::util;
 
-use chrono::offset::Utc;
+use chrono::offset::{TimeZone, Utc};
 use chrono::DateTime;
-use rocksdb::{ColumnFamily, DBIterator, Direction, IteratorMode, WriteBatch, DB};
+use rocksdb::{
+    ColumnFamily, DBIterator, DBPinnableSlice, Direction, IteratorMode, MergeOperands, WriteBatch, WriteOptions, DB,
+};
 use uuid::Uuid;
 
 pub type OwnedPropertyItem = ((Uuid, models::Identifier), models::Json);
@@ -19,6 +29,45 @@ pub type EdgePropertyItem = ((Uuid, models::Identifier, Uuid, models::Identifier
 pub type VertexPropertyValueKey = (models::Identifier, u64, Uuid);
 pub type EdgePropertyValueKey = (models::Identifier, u64, (Uuid, models::Identifier, Uuid));
 
+const PARALLEL_DELETE_BATCH_SIZE: usize = 1_000;
+const EDGE_SEQ_BLOCK_SIZE: u64 = 1_000;
+
+fn read_degrees(bytes: &[u8]) -> (i64, i64) {
+    let (out_bytes, in_bytes) = bytes.split_at(8);
+    (
+        i64::from_be_bytes(out_bytes.try_into().unwrap()),
+        i64::from_be_bytes(in_bytes.try_into().unwrap()),
+    )
+}
+
+fn build_degrees(out_degree: i64, in_degree: i64) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(16);
+    bytes.extend_from_slice(&out_degree.to_be_bytes());
+    bytes.extend_from_slice(&in_degree.to_be_bytes());
+    bytes
+}
+
+// Merge operator for the degree counters. Values and operands are both pairs
+// of (outbound, inbound) counts, and merging them sums the pairs.
+pub(crate) fn merge_degrees(_key: &[u8], existing: Option<&[u8]>, operands: &mut MergeOperands) -> Option<Vec<u8>> {
+    let (mut out_degree, mut in_degree) = existing.map(read_degrees).unwrap_or_default();
+    for operand in operands {
+        let (out_delta, in_delta) = read_degrees(operand);
+        out_degree += out_delta;
+        in_degree += in_delta;
+    }
+    Some(build_degrees(out_degree, in_degree))
+}
+
+fn read_type_index_key(key: &[u8]) -> EdgeRangeItem {
+    let mut cursor = Cursor::new(key);
+    let t = util::read_identifier(&mut cursor);
+    let update_datetime = util::read_datetime(&mut cursor);
+    let out_id = util::read_uuid(&mut cursor);
+    let in_id = util::read_uuid(&mut cursor);
+    (out_id, t, update_datetime, in_id)
+}
+
 fn take_with_prefix(iterator: DBIterator<'_>, prefix: Vec<u8>) -> impl Iterator<Item = (Box<[u8]>, Box<[u8]>)> + '_ {
     iterator.take_while(move |item| -> bool {
         let (ref k, _) = *item;
@@ -26,15 +75,209 @@ fn take_with_prefix(iterator: DBIterator<'_>, prefix: Vec<u8>) -> impl Iterator<
     })
 }
 
+// Checks which keys exist by seeking a single iterator through them in
+// sorted order, which keeps the reads local. Results are in the order of
+// `keys`.
+fn keys_exist(db: &DB, cf: &ColumnFamily, keys: &[Vec<u8>]) -> Result<Vec<bool>> {
+    let mut order: Vec<usize> = (0..keys.len()).collect();
+    order.sort_by(|a, b| keys[*a].cmp(&keys[*b]));
+
+    let mut exists = vec![false; keys.len()];
+    let mut iterator = db.raw_iterator_cf(cf);
+    for i in order {
+        iterator.seek(&keys[i]);
+        exists[i] = iterator.key() == Some(&keys[i][..]);
+    }
+    iterator.status()?;
+    Ok(exists)
+}
+
+// Serialized JSON never starts with this byte, so uncompressed values are
+// stored as-is, and compressed values are prefixed with it followed by their
+// uncompressed length.
+const COMPRESSED_TAG: u8 = 0;
+const COMPRESSED_HEADER_LEN: usize = 5;
+
+fn read_json<F: FnOnce() -> String>(bytes: &[u8], location: &'static str, key: F) -> Result<models::Json> {
+    // Malformed compressed values are parsed as-is, so they're reported as
+    // corrupt JSON
+    let decompressed = decompress_json(bytes);
+    let bytes = decompressed.as_deref().unwrap_or(bytes);
+    serde_json::from_slice(bytes).map_err(|source| Error::Corrupt {
+        location,
+        key: key(),
+        source,
+    })
+}
+
+fn decompress_json(bytes: &[u8]) -> Option<Vec<u8>> {
+    match bytes.split_first() {
+        Some((&COMPRESSED_TAG, rest)) if rest.len() >= 4 => {
+            let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+            compress::decompress(&rest[4..], len)
+        }
+        _ => None,
+    }
+}
+
+fn write_json(db_ref: DBRef<'_>, value: &models::Json) -> Result<Vec<u8>> {
+    let value_json = serde_json::to_vec(value)?;
+    if let Some(max_property_bytes) = db_ref.config.max_property_bytes {
+        if value_json.len() > max_property_bytes {
+            return Err(Error::PropertyTooLarge);
+        }
+    }
+
+    match db_ref.config.property_compress_threshold {
+        Some(threshold) if value_json.len() > threshold => {
+            let compressed = compress::compress(&value_json);
+            if COMPRESSED_HEADER_LEN + compressed.len() < value_json.len() {
+                let mut bytes = Vec::with_capacity(COMPRESSED_HEADER_LEN + compressed.len());
+                bytes.push(COMPRESSED_TAG);
+                bytes.extend_from_slice(&(value_json.len() as u32).to_be_bytes());
+                bytes.extend_from_slice(&compressed);
+                Ok(bytes)
+            } else {
+                Ok(value_json)
+            }
+        }
+        _ => Ok(value_json),
+    }
+}
+
+fn read_name<T: AsRef<[u8]>>(cursor: &mut Cursor<T>, location: &'static str) -> Result<models::Identifier> {
+    let name_str = util::read_fixed_length_string(cursor).map_err(|err| Error::CorruptKey {
+        location,
+        source: Box::new(err),
+    })?;
+    Ok(unsafe { models::Identifier::new_unchecked(name_str) })
+}
+
+// Sequence numbers are only present in databases created with edge sequence
+// numbers, so a short read means the database was opened with the wrong setting
+fn read_sequence<T: AsRef<[u8]>>(cursor: &mut Cursor<T>, location: &'static str) -> Result<u64> {
+    util::read_sequence(cursor).map_err(|err| Error::CorruptKey {
+        location,
+        source: Box::new(err),
+    })
+}
+
+fn vertex_property_key_description(vertex_id: Uuid, name: &models::Identifier) -> String {
+    format!("vertex {} property {}", vertex_id, name.as_str())
+}
+
+fn edge_property_key_description(
+    out_id: Uuid,
+    t: &models::Identifier,
+    in_id: Uuid,
+    name: &models::Identifier,
+) -> String {
+    format!(
+        "edge ({}, {}, {}) property {}",
+        out_id,
+        t.as_str(),
+        in_id,
+        name.as_str()
+    )
+}
+
+// Records the size of the key operated on in the current span. The size is
+// only computed if the span is being recorded.
+#[cfg(feature = "tracing")]
+fn trace_key_bytes<F: FnOnce() -> usize>(f: F) {
+    let span = tracing::Span::current();
+    if !span.is_disabled() {
+        span.record("key_bytes", &f());
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+fn trace_key_bytes<F: FnOnce() -> usize>(_f: F) {}
+
+// Hands out edge sequence numbers. They're reserved in blocks, and the end of
+// each block is persisted before any number in it is handed out, so numbers
+// are never reused after a restart, although the rest of the block is
+// skipped.
+#[derive(Debug, Default)]
+pub(crate) struct EdgeSeq {
+    last: AtomicU64,
+    reserved: Mutex<u64>,
+}
+
+impl EdgeSeq {
+    pub fn new(reserved: u64) -> Self {
+        EdgeSeq {
+            last: AtomicU64::new(reserved),
+            reserved: Mutex::new(reserved),
+        }
+    }
+
+    pub fn next(&self, db: &DB) -> Result<u64> {
+        let seq = self.last.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut reserved = self.reserved.lock().unwrap();
+        if seq > *reserved {
+            let new_reserved = seq + EDGE_SEQ_BLOCK_SIZE - 1;
+            MetadataManager::new(db).set_edge_seq(new_reserved)?;
+            *reserved = new_reserved;
+        }
+        Ok(seq)
+    }
+}
+
 #[derive(Copy, Clone)]
 pub(crate) struct DBRef<'a> {
     pub db: &'a DB,
     pub indexed_properties: &'a HashSet<models::Identifier>,
+    pub config: &'a RocksdbConfig,
+    pub edge_seq: &'a EdgeSeq,
 }
 
 impl<'a> DBRef<'a> {
-    pub(crate) fn new(db: &'a DB, indexed_properties: &'a HashSet<models::Identifier>) -> Self {
-        DBRef { db, indexed_properties }
+    pub(crate) fn new(
+        db: &'a DB,
+        indexed_properties: &'a HashSet<models::Identifier>,
+        config: &'a RocksdbConfig,
+        edge_seq: &'a EdgeSeq,
+    ) -> Self {
+        DBRef {
+            db,
+            indexed_properties,
+            config,
+            edge_seq,
+        }
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "DBRef::write",
+            level = "trace",
+            skip_all,
+            err,
+            fields(ops = batch.len(), batch_bytes = batch.size_in_bytes())
+        )
+    )]
+    pub(crate) fn write(&self, batch: WriteBatch) -> Result<()> {
+        if self.config.manual_flush {
+            self.db.write_without_wal(batch)?;
+        } else {
+            self.db.write(batch)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn write_durable(&self, batch: WriteBatch) -> Result<()> {
+        if self.config.manual_flush {
+            // There's no write-ahead log to sync, so the memtables have to be
+            // flushed instead
+            self.db.write_without_wal(batch)?;
+            flush(self.db, self.config)?;
+        } else {
+            let mut opts = WriteOptions::default();
+            opts.set_sync(true);
+            self.db.write_opt(batch, &opts)?;
+        }
+        Ok(())
     }
 }
 
@@ -56,11 +299,25 @@ impl<'a> VertexManager<'a> {
     }
 
     pub fn exists(&self, id: Uuid) -> Result<bool> {
-        Ok(self.db_ref.db.get_cf(self.cf, &self.key(id))?.is_some())
+        Ok(self.get_type_bytes(id)?.is_some())
+    }
+
+    pub fn exists_bulk(&self, ids: &[Uuid]) -> Result<Vec<bool>> {
+        let keys: Vec<Vec<u8>> = ids.iter().map(|id| self.key(*id)).collect();
+        keys_exist(self.db_ref.db, self.cf, &keys)
+    }
+
+    pub fn get_type_bytes(&self, id: Uuid) -> Result<Option<DBPinnableSlice<'a>>> {
+        Ok(self.db_ref.db.get_pinned_cf(self.cf, self.key(id))?)
     }
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "VertexManager::get", level = "trace", skip_all, err, fields(key_bytes = tracing::field::Empty))
+    )]
     pub fn get(&self, id: Uuid) -> Result<Option<models::Identifier>> {
-        match self.db_ref.db.get_cf(self.cf, &self.key(id))? {
+        trace_key_bytes(|| self.key(id).len());
+        match self.get_type_bytes(id)? {
             Some(value_bytes) => {
                 let mut cursor = Cursor::new(value_bytes.deref());
                 Ok(Some(util::read_identifier(&mut cursor)))
@@ -90,20 +347,123 @@ impl<'a> VertexManager<'a> {
         })
     }
 
+    pub fn iterate_for_range_after(&'a self, id: Uuid) -> impl Iterator<Item = Result<VertexItem>> + 'a {
+        // Only the first item can have the starting ID, so this is cheap
+        self.iterate_for_range(id)
+            .skip_while(move |item| matches!(item, Ok((item_id, _)) if *item_id == id))
+    }
+
+    pub fn iterate_with_edges(
+        &'a self,
+        start_id: Option<Uuid>,
+        t: Option<&'a models::Identifier>,
+    ) -> impl Iterator<Item = Result<(VertexItem, Vec<EdgeRangeItem>)>> + 'a {
+        let vertices: Box<dyn Iterator<Item = Result<VertexItem>> + 'a> = match start_id {
+            Some(start_id) => Box::new(self.iterate_for_range_after(start_id)),
+            None => Box::new(self.iterate_for_range(Uuid::default())),
+        };
+
+        // Vertices aren't keyed by type, so every vertex in the range is
+        // read, but edges are only scanned for the ones that match
+        let vertices = vertices.filter(move |item| match (item, t) {
+            (Ok((_, vertex_t)), Some(t)) => vertex_t == t,
+            _ => true,
+        });
+        vertices.map(move |item| {
+            let (id, t) = item?;
+            let edge_range_manager = EdgeRangeManager::new(self.db_ref);
+            let edges = edge_range_manager
+                .iterate_for_range(id, None, None)?
+                .collect::<Result<Vec<_>>>()?;
+            Ok(((id, t), edges))
+        })
+    }
+
+    // Only the database is checked, so creating the same vertex twice in one
+    // batch isn't caught here
     pub fn create(&self, batch: &mut WriteBatch, vertex: &models::Vertex) -> Result<()> {
+        if self.exists(vertex.id)? {
+            return Err(Error::UuidTaken);
+        }
+        self.upsert(batch, vertex)
+    }
+
+    pub fn upsert(&self, batch: &mut WriteBatch, vertex: &models::Vertex) -> Result<()> {
         let key = self.key(vertex.id);
         batch.put_cf(self.cf, &key, &util::build(&[util::Component::Identifier(&vertex.t)]));
         Ok(())
     }
 
-    pub fn delete(&self, batch: &mut WriteBatch, id: Uuid) -> Result<()> {
-        batch.delete_cf(self.cf, &self.key(id));
+    pub fn set_type(&self, batch: &mut WriteBatch, id: Uuid, new_t: &models::Identifier) -> Result<bool> {
+        if !self.exists(id)? {
+            return Ok(false);
+        }
+
+        // Vertex types are only stored in the vertex's own value; edge keys
+        // embed edge types, so nothing else has to change
+        batch.put_cf(
+            self.cf,
+            self.key(id),
+            util::build(&[util::Component::Identifier(new_t)]),
+        );
+        Ok(true)
+    }
+
+    pub fn create_stream<I, F>(&self, iter: I, batch_size: usize, mut on_write: F) -> Result<u64>
+    where
+        I: IntoIterator<Item = models::Vertex>,
+        F: FnMut(Vec<models::Vertex>),
+    {
+        let batch_size = max(batch_size, 1);
+        let mut batch = WriteBatch::default();
+        let mut vertices = Vec::with_capacity(batch_size);
+        let mut count = 0;
+
+        for vertex in iter {
+            self.upsert(&mut batch, &vertex)?;
+            vertices.push(vertex);
+            if vertices.len() >= batch_size {
+                self.db_ref.write(mem::take(&mut batch))?;
+                count += vertices.len() as u64;
+                on_write(mem::replace(&mut vertices, Vec::with_capacity(batch_size)));
+            }
+        }
+
+        if !vertices.is_empty() {
+            self.db_ref.write(batch)?;
+            count += vertices.len() as u64;
+            on_write(vertices);
+        }
+
+        Ok(count)
+    }
 
+    fn delete_properties(&self, batch: &mut WriteBatch, id: Uuid) -> Result<u64> {
         let vertex_property_manager = VertexPropertyManager::new(self.db_ref);
+        let mut count = 0;
         for item in vertex_property_manager.iterate_for_owner(id)? {
             let ((vertex_property_owner_id, vertex_property_name), _) = item?;
             vertex_property_manager.delete(batch, vertex_property_owner_id, &vertex_property_name)?;
+            count += 1;
         }
+        Ok(count)
+    }
+
+    // `deleted_edges` holds the edges already deleted in `batch`, which reads
+    // from the database can't see, so that deleting both ends of an edge in
+    // one batch only deletes it, and adjusts its degree counters, once
+    pub fn delete(
+        &self,
+        batch: &mut WriteBatch,
+        id: Uuid,
+        deleted_edges: &mut HashSet<models::EdgeKey>,
+    ) -> Result<DeleteCounts> {
+        let mut counts = DeleteCounts {
+            vertex_removed: self.exists(id)?,
+            ..DeleteCounts::default()
+        };
+        batch.delete_cf(self.cf, self.key(id));
+        counts.properties_removed += self.delete_properties(batch, id)?;
 
         let edge_manager = EdgeManager::new(self.db_ref);
 
@@ -112,13 +472,20 @@ impl<'a> VertexManager<'a> {
             for item in edge_range_manager.iterate_for_range(id, None, None)? {
                 let (edge_range_out_id, edge_range_t, edge_range_update_datetime, edge_range_in_id) = item?;
                 debug_assert_eq!(edge_range_out_id, id);
-                edge_manager.delete(
-                    batch,
+                if deleted_edges.insert(models::EdgeKey::new(
                     edge_range_out_id,
-                    &edge_range_t,
+                    edge_range_t.clone(),
                     edge_range_in_id,
-                    edge_range_update_datetime,
-                )?;
+                )) {
+                    counts.properties_removed += edge_manager.delete(
+                        batch,
+                        edge_range_out_id,
+                        &edge_range_t,
+                        edge_range_in_id,
+                        edge_range_update_datetime,
+                    )?;
+                    counts.edges_removed += 1;
+                }
             }
         }
 
@@ -132,16 +499,197 @@ impl<'a> VertexManager<'a> {
                     reversed_edge_range_out_id,
                 ) = item?;
                 debug_assert_eq!(reversed_edge_range_in_id, id);
-                edge_manager.delete(
-                    batch,
+                // Edges from the vertex to itself were already deleted via
+                // the forward range
+                if deleted_edges.insert(models::EdgeKey::new(
                     reversed_edge_range_out_id,
-                    &reversed_edge_range_t,
+                    reversed_edge_range_t.clone(),
                     reversed_edge_range_in_id,
-                    reversed_edge_range_update_datetime,
-                )?;
+                )) {
+                    counts.properties_removed += edge_manager.delete(
+                        batch,
+                        reversed_edge_range_out_id,
+                        &reversed_edge_range_t,
+                        reversed_edge_range_in_id,
+                        reversed_edge_range_update_datetime,
+                    )?;
+                    counts.edges_removed += 1;
+                }
+            }
+        }
+
+        edge_manager.delete_degrees(batch, id);
+        Ok(counts)
+    }
+
+    pub fn delete_preview(&self, id: Uuid) -> Result<Option<DeletePlan>> {
+        let t = match self.get(id)? {
+            Some(t) => t,
+            None => return Ok(None),
+        };
+
+        let mut plan = DeletePlan {
+            vertex: models::Vertex::with_id(id, t),
+            vertex_properties: Vec::new(),
+            edges: Vec::new(),
+            edge_properties: Vec::new(),
+        };
+
+        let vertex_property_manager = VertexPropertyManager::new(self.db_ref);
+        for item in vertex_property_manager.iterate_for_owner(id)? {
+            let ((_, vertex_property_name), _) = item?;
+            plan.vertex_properties.push(vertex_property_name);
+        }
+
+        let edge_range_manager = EdgeRangeManager::new(self.db_ref);
+        for item in edge_range_manager.iterate_for_range(id, None, None)? {
+            let (out_id, t, _, in_id) = item?;
+            plan.edges.push(models::EdgeKey::new(out_id, t, in_id));
+        }
+
+        let reversed_edge_range_manager = EdgeRangeManager::new_reversed(self.db_ref);
+        for item in reversed_edge_range_manager.iterate_for_range(id, None, None)? {
+            let (in_id, t, _, out_id) = item?;
+            // Edges from the vertex to itself were already covered by the
+            // forward range
+            if out_id != in_id {
+                plan.edges.push(models::EdgeKey::new(out_id, t, in_id));
+            }
+        }
+
+        let edge_property_manager = EdgePropertyManager::new(self.db_ref);
+        for key in &plan.edges {
+            for item in edge_property_manager.iterate_for_owner(key.outbound_id, &key.t, key.inbound_id)? {
+                let ((_, _, _, edge_property_name), _) = item?;
+                plan.edge_properties.push((key.clone(), edge_property_name));
+            }
+        }
+
+        Ok(Some(plan))
+    }
+
+    pub fn delete_parallel(&self, id: Uuid, num_threads: usize) -> Result<()> {
+        let db_ref = self.db_ref;
+        let (sender, receiver) = mpsc::sync_channel::<Vec<EdgeRangeItem>>(num_threads);
+        let receiver = Mutex::new(receiver);
+
+        thread::scope(|scope| -> Result<()> {
+            let workers: Vec<_> = (0..max(num_threads, 1))
+                .map(|_| {
+                    scope.spawn(|| -> Result<()> {
+                        let edge_manager = EdgeManager::new(db_ref);
+                        let mut result = Ok(());
+
+                        // Keep draining chunks after an error, so that the
+                        // scanning thread never blocks on a full channel
+                        loop {
+                            let chunk = match receiver.lock().unwrap().recv() {
+                                Ok(chunk) => chunk,
+                                Err(_) => return result,
+                            };
+
+                            if result.is_ok() {
+                                result = (|| -> Result<()> {
+                                    let mut batch = WriteBatch::default();
+                                    for (out_id, t, update_datetime, in_id) in chunk {
+                                        edge_manager.delete(&mut batch, out_id, &t, in_id, update_datetime)?;
+                                    }
+                                    db_ref.write(batch)?;
+                                    Ok(())
+                                })();
+                            }
+                        }
+                    })
+                })
+                .collect();
+
+            let mut result = self.send_edge_chunks(id, sender);
+            for worker in workers {
+                let worker_result = worker.join().unwrap();
+                if result.is_ok() {
+                    result = worker_result;
+                }
+            }
+            result
+        })?;
+
+        // Delete the vertex last, so that if anything above fails, the
+        // delete can be retried
+        let mut batch = WriteBatch::default();
+        batch.delete_cf(self.cf, self.key(id));
+        self.delete_properties(&mut batch, id)?;
+        EdgeManager::new(self.db_ref).delete_degrees(&mut batch, id);
+        self.db_ref.write(batch)?;
+        Ok(())
+    }
+
+    pub fn delete_chunked(&self, id: Uuid, chunk_size: usize) -> Result<()> {
+        let edge_manager = EdgeManager::new(self.db_ref);
+        let mut batch = WriteBatch::default();
+        let mut delete_edge =
+            |out_id: Uuid, t: &models::Identifier, update_datetime: DateTime<Utc>, in_id: Uuid| -> Result<()> {
+                edge_manager.delete(&mut batch, out_id, t, in_id, update_datetime)?;
+                if batch.len() >= chunk_size {
+                    self.db_ref.write(mem::take(&mut batch))?;
+                }
+                Ok(())
+            };
+
+        let edge_range_manager = EdgeRangeManager::new(self.db_ref);
+        for item in edge_range_manager.iterate_for_range(id, None, None)? {
+            let (out_id, t, update_datetime, in_id) = item?;
+            delete_edge(out_id, &t, update_datetime, in_id)?;
+        }
+
+        let reversed_edge_range_manager = EdgeRangeManager::new_reversed(self.db_ref);
+        for item in reversed_edge_range_manager.iterate_for_range(id, None, None)? {
+            let (in_id, t, update_datetime, out_id) = item?;
+            // Edges from the vertex to itself were already deleted via the
+            // forward range
+            if out_id != in_id {
+                delete_edge(out_id, &t, update_datetime, in_id)?;
             }
         }
 
+        // Delete the vertex last, so that if anything above fails, the
+        // delete can be retried
+        batch.delete_cf(self.cf, self.key(id));
+        self.delete_properties(&mut batch, id)?;
+        edge_manager.delete_degrees(&mut batch, id);
+        self.db_ref.write(batch)?;
+        Ok(())
+    }
+
+    fn send_edge_chunks(&self, id: Uuid, sender: mpsc::SyncSender<Vec<EdgeRangeItem>>) -> Result<()> {
+        let mut chunk = Vec::with_capacity(PARALLEL_DELETE_BATCH_SIZE);
+        let mut push = |item: EdgeRangeItem| {
+            chunk.push(item);
+            if chunk.len() >= PARALLEL_DELETE_BATCH_SIZE {
+                let full_chunk = mem::replace(&mut chunk, Vec::with_capacity(PARALLEL_DELETE_BATCH_SIZE));
+                // The receiving end lives until every worker has exited
+                sender.send(full_chunk).unwrap();
+            }
+        };
+
+        let edge_range_manager = EdgeRangeManager::new(self.db_ref);
+        for item in edge_range_manager.iterate_for_range(id, None, None)? {
+            push(item?);
+        }
+
+        let reversed_edge_range_manager = EdgeRangeManager::new_reversed(self.db_ref);
+        for item in reversed_edge_range_manager.iterate_for_range(id, None, None)? {
+            let (in_id, t, update_datetime, out_id) = item?;
+            // Edges from the vertex to itself were already sent via the
+            // forward range
+            if out_id != in_id {
+                push((out_id, t, update_datetime, in_id));
+            }
+        }
+
+        if !chunk.is_empty() {
+            sender.send(chunk).unwrap();
+        }
+
         Ok(())
     }
 
@@ -155,6 +703,8 @@ impl<'a> VertexManager<'a> {
 pub(crate) struct EdgeManager<'a> {
     db_ref: DBRef<'a>,
     cf: &'a ColumnFamily,
+    degrees_cf: &'a ColumnFamily,
+    type_index_cf: &'a ColumnFamily,
 }
 
 impl<'a> EdgeManager<'a> {
@@ -162,9 +712,26 @@ impl<'a> EdgeManager<'a> {
         EdgeManager {
             db_ref,
             cf: db_ref.db.cf_handle("edges:v1").unwrap(),
+            degrees_cf: db_ref.db.cf_handle("vertex_degrees:v1").unwrap(),
+            type_index_cf: db_ref.db.cf_handle("edges_by_type:v1").unwrap(),
         }
     }
 
+    fn type_index_key(
+        &self,
+        t: &models::Identifier,
+        update_datetime: DateTime<Utc>,
+        out_id: Uuid,
+        in_id: Uuid,
+    ) -> Vec<u8> {
+        util::build(&[
+            util::Component::Identifier(t),
+            util::Component::DateTime(update_datetime),
+            util::Component::Uuid(out_id),
+            util::Component::Uuid(in_id),
+        ])
+    }
+
     fn key(&self, out_id: Uuid, t: &models::Identifier, in_id: Uuid) -> Vec<u8> {
         util::build(&[
             util::Component::Uuid(out_id),
@@ -173,16 +740,80 @@ impl<'a> EdgeManager<'a> {
         ])
     }
 
+    pub fn exists_bulk(&self, keys: &[(Uuid, models::Identifier, Uuid)]) -> Result<Vec<bool>> {
+        let keys: Vec<Vec<u8>> = keys
+            .iter()
+            .map(|(out_id, t, in_id)| self.key(*out_id, t, *in_id))
+            .collect();
+        keys_exist(self.db_ref.db, self.cf, &keys)
+    }
+
+    fn value(&self, update_datetime: DateTime<Utc>, seq: Option<u64>) -> Vec<u8> {
+        match seq {
+            Some(seq) => util::build(&[
+                util::Component::DateTime(update_datetime),
+                util::Component::Sequence(seq),
+            ]),
+            None => util::build(&[util::Component::DateTime(update_datetime)]),
+        }
+    }
+
+    fn read_value(&self, value_bytes: &[u8]) -> Result<(DateTime<Utc>, Option<u64>)> {
+        let mut cursor = Cursor::new(value_bytes);
+        let update_datetime = util::read_datetime(&mut cursor);
+        let seq = if self.db_ref.config.edge_sequence_numbers {
+            Some(read_sequence(&mut cursor, "edges:v1")?)
+        } else {
+            None
+        };
+        Ok((update_datetime, seq))
+    }
+
     pub fn get(&self, out_id: Uuid, t: &models::Identifier, in_id: Uuid) -> Result<Option<DateTime<Utc>>> {
-        match self.db_ref.db.get_cf(self.cf, &self.key(out_id, t, in_id))? {
-            Some(value_bytes) => {
-                let mut cursor = Cursor::new(value_bytes.deref());
-                Ok(Some(util::read_datetime(&mut cursor)))
-            }
+        Ok(self
+            .get_with_seq(out_id, t, in_id)?
+            .map(|(update_datetime, _)| update_datetime))
+    }
+
+    pub fn get_with_seq(
+        &self,
+        out_id: Uuid,
+        t: &models::Identifier,
+        in_id: Uuid,
+    ) -> Result<Option<(DateTime<Utc>, Option<u64>)>> {
+        match self.db_ref.db.get_pinned_cf(self.cf, self.key(out_id, t, in_id))? {
+            Some(value_bytes) => Ok(Some(self.read_value(&value_bytes)?)),
             None => Ok(None),
         }
     }
 
+    // Rounds an update datetime down to the configured resolution, if any.
+    fn round_datetime(&self, update_datetime: DateTime<Utc>) -> DateTime<Utc> {
+        let resolution = match self.db_ref.config.edge_time_resolution {
+            Some(resolution) => resolution.as_nanos() as i128,
+            None => return update_datetime,
+        };
+        let nanos = i128::from(update_datetime.timestamp()) * 1_000_000_000
+            + i128::from(update_datetime.timestamp_subsec_nanos());
+        let rounded = nanos - nanos.rem_euclid(resolution);
+        Utc.timestamp(
+            rounded.div_euclid(1_000_000_000) as i64,
+            rounded.rem_euclid(1_000_000_000) as u32,
+        )
+    }
+
+    fn next_seq(&self) -> Result<Option<u64>> {
+        if !self.db_ref.config.edge_sequence_numbers {
+            return Ok(None);
+        }
+
+        Ok(Some(self.db_ref.edge_seq.next(self.db_ref.db)?))
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "EdgeManager::set", level = "trace", skip_all, err, fields(key_bytes = tracing::field::Empty))
+    )]
     pub fn set(
         &self,
         batch: &mut WriteBatch,
@@ -191,23 +822,83 @@ impl<'a> EdgeManager<'a> {
         in_id: Uuid,
         new_update_datetime: DateTime<Utc>,
     ) -> Result<()> {
+        trace_key_bytes(|| self.key(out_id, t, in_id).len());
+        self.upsert(batch, out_id, t, in_id, new_update_datetime)?;
+        Ok(())
+    }
+
+    pub fn upsert(
+        &self,
+        batch: &mut WriteBatch,
+        out_id: Uuid,
+        t: &models::Identifier,
+        in_id: Uuid,
+        new_update_datetime: DateTime<Utc>,
+    ) -> Result<bool> {
         let edge_range_manager = EdgeRangeManager::new(self.db_ref);
         let reversed_edge_range_manager = EdgeRangeManager::new_reversed(self.db_ref);
 
-        if let Some(update_datetime) = self.get(out_id, t, in_id)? {
-            edge_range_manager.delete(batch, out_id, t, update_datetime, in_id)?;
-            reversed_edge_range_manager.delete(batch, in_id, t, update_datetime, out_id)?;
+        let new_update_datetime = self.round_datetime(new_update_datetime);
+        let existing = self.get_with_seq(out_id, t, in_id)?;
+        if let Some((update_datetime, seq)) = existing {
+            // Without sequence numbers, rewriting the edge at the same
+            // datetime wouldn't change anything
+            if update_datetime == new_update_datetime && seq.is_none() {
+                return Ok(false);
+            }
+            edge_range_manager.delete(batch, out_id, t, update_datetime, seq, in_id)?;
+            reversed_edge_range_manager.delete(batch, in_id, t, update_datetime, seq, out_id)?;
+            self.delete_type_index(batch, out_id, t, in_id, update_datetime);
         }
 
+        let new_seq = self.next_seq()?;
         let key = self.key(out_id, t, in_id);
-        batch.put_cf(
-            self.cf,
-            &key,
-            &util::build(&[util::Component::DateTime(new_update_datetime)]),
-        );
-        edge_range_manager.set(batch, out_id, t, new_update_datetime, in_id)?;
-        reversed_edge_range_manager.set(batch, in_id, t, new_update_datetime, out_id)?;
-        Ok(())
+        batch.put_cf(self.cf, key, self.value(new_update_datetime, new_seq));
+        edge_range_manager.set(batch, out_id, t, new_update_datetime, new_seq, in_id)?;
+        reversed_edge_range_manager.set(batch, in_id, t, new_update_datetime, new_seq, out_id)?;
+        self.set_type_index(batch, out_id, t, in_id, new_update_datetime);
+
+        if existing.is_none() {
+            self.adjust_degrees(batch, out_id, in_id, 1);
+        }
+
+        Ok(existing.is_none())
+    }
+
+    pub fn touch(
+        &self,
+        batch: &mut WriteBatch,
+        out_id: Uuid,
+        t: &models::Identifier,
+        in_id: Uuid,
+        new_update_datetime: DateTime<Utc>,
+    ) -> Result<bool> {
+        let new_update_datetime = self.round_datetime(new_update_datetime);
+        let (update_datetime, seq) = match self.get_with_seq(out_id, t, in_id)? {
+            Some(value) => value,
+            None => return Ok(false),
+        };
+
+        // Nothing to move if the edge is already at the requested datetime
+        if update_datetime == new_update_datetime {
+            return Ok(true);
+        }
+
+        let new_seq = self.next_seq()?;
+        let edge_range_manager = EdgeRangeManager::new(self.db_ref);
+        edge_range_manager.delete(batch, out_id, t, update_datetime, seq, in_id)?;
+        edge_range_manager.set(batch, out_id, t, new_update_datetime, new_seq, in_id)?;
+
+        let reversed_edge_range_manager = EdgeRangeManager::new_reversed(self.db_ref);
+        reversed_edge_range_manager.delete(batch, in_id, t, update_datetime, seq, out_id)?;
+        reversed_edge_range_manager.set(batch, in_id, t, new_update_datetime, new_seq, out_id)?;
+
+        self.delete_type_index(batch, out_id, t, in_id, update_datetime);
+        self.set_type_index(batch, out_id, t, in_id, new_update_datetime);
+
+        let key = self.key(out_id, t, in_id);
+        batch.put_cf(self.cf, &key, self.value(new_update_datetime, new_seq));
+        Ok(true)
     }
 
     pub fn delete(
@@ -217,78 +908,415 @@ impl<'a> EdgeManager<'a> {
         t: &models::Identifier,
         in_id: Uuid,
         update_datetime: DateTime<Utc>,
-    ) -> Result<()> {
+    ) -> Result<u64> {
+        // Callers only pass the update datetime, so the sequence number is
+        // read back from the edge
+        let seq = if self.db_ref.config.edge_sequence_numbers {
+            self.get_with_seq(out_id, t, in_id)?.and_then(|(_, seq)| seq)
+        } else {
+            None
+        };
+
+        self.delete_entries(batch, out_id, t, in_id, update_datetime, seq)
+    }
+
+    pub fn delete_by_triple(
+        &self,
+        batch: &mut WriteBatch,
+        out_id: Uuid,
+        t: &models::Identifier,
+        in_id: Uuid,
+    ) -> Result<bool> {
+        match self.get_with_seq(out_id, t, in_id)? {
+            Some((update_datetime, seq)) => {
+                self.delete_entries(batch, out_id, t, in_id, update_datetime, seq)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn delete_entries(
+        &self,
+        batch: &mut WriteBatch,
+        out_id: Uuid,
+        t: &models::Identifier,
+        in_id: Uuid,
+        update_datetime: DateTime<Utc>,
+        seq: Option<u64>,
+    ) -> Result<u64> {
         batch.delete_cf(self.cf, &self.key(out_id, t, in_id));
+        self.adjust_degrees(batch, out_id, in_id, -1);
 
         let edge_range_manager = EdgeRangeManager::new(self.db_ref);
-        edge_range_manager.delete(batch, out_id, t, update_datetime, in_id)?;
+        edge_range_manager.delete(batch, out_id, t, update_datetime, seq, in_id)?;
 
         let reversed_edge_range_manager = EdgeRangeManager::new_reversed(self.db_ref);
-        reversed_edge_range_manager.delete(batch, in_id, t, update_datetime, out_id)?;
+        reversed_edge_range_manager.delete(batch, in_id, t, update_datetime, seq, out_id)?;
+
+        self.delete_type_index(batch, out_id, t, in_id, update_datetime);
 
         let edge_property_manager = EdgePropertyManager::new(self.db_ref);
-        for item in edge_property_manager.iterate_for_owner(out_id, t, in_id)? {
-            let ((edge_property_out_id, edge_property_t, edge_property_in_id, edge_property_name), _) = item?;
-            edge_property_manager.delete(
+        edge_property_manager.delete_all_for_owner(batch, out_id, t, in_id)
+    }
+
+    pub fn iterate_for_all_with_seq(&'a self) -> impl Iterator<Item = Result<(EdgeRangeItem, Option<u64>)>> + 'a {
+        let iterator = self.db_ref.db.iterator_cf(self.cf, IteratorMode::Start);
+        iterator.map(move |item| -> Result<(EdgeRangeItem, Option<u64>)> {
+            let (k, v) = item;
+            let mut cursor = Cursor::new(k);
+            let out_id = util::read_uuid(&mut cursor);
+            let t = util::read_identifier(&mut cursor);
+            let in_id = util::read_uuid(&mut cursor);
+            let (update_datetime, seq) = self.read_value(&v)?;
+            Ok(((out_id, t, update_datetime, in_id), seq))
+        })
+    }
+
+    pub fn set_undirected(
+        &self,
+        batch: &mut WriteBatch,
+        a_id: Uuid,
+        t: &models::Identifier,
+        b_id: Uuid,
+        new_update_datetime: DateTime<Utc>,
+    ) -> Result<()> {
+        self.set(batch, a_id, t, b_id, new_update_datetime)?;
+        if a_id != b_id {
+            self.set(batch, b_id, t, a_id, new_update_datetime)?;
+        }
+        Ok(())
+    }
+
+    pub fn delete_undirected(
+        &self,
+        batch: &mut WriteBatch,
+        a_id: Uuid,
+        t: &models::Identifier,
+        b_id: Uuid,
+    ) -> Result<bool> {
+        let mut existed = false;
+        for (out_id, in_id) in [(a_id, b_id), (b_id, a_id)] {
+            existed |= self.delete_by_triple(batch, out_id, t, in_id)?;
+            if a_id == b_id {
+                break;
+            }
+        }
+        Ok(existed)
+    }
+
+    pub fn retype(
+        &self,
+        batch: &mut WriteBatch,
+        out_id: Uuid,
+        old_t: &models::Identifier,
+        in_id: Uuid,
+        new_t: &models::Identifier,
+    ) -> Result<bool> {
+        let (update_datetime, seq) = match self.get_with_seq(out_id, old_t, in_id)? {
+            Some(value) => value,
+            None => return Ok(false),
+        };
+
+        if old_t == new_t {
+            return Ok(true);
+        }
+
+        // Replace any edge that already has the new type
+        if let Some(existing_update_datetime) = self.get(out_id, new_t, in_id)? {
+            self.delete(batch, out_id, new_t, in_id, existing_update_datetime)?;
+        }
+
+        batch.delete_cf(self.cf, self.key(out_id, old_t, in_id));
+        batch.put_cf(
+            self.cf,
+            self.key(out_id, new_t, in_id),
+            self.value(update_datetime, seq),
+        );
+
+        let edge_range_manager = EdgeRangeManager::new(self.db_ref);
+        edge_range_manager.delete(batch, out_id, old_t, update_datetime, seq, in_id)?;
+        edge_range_manager.set(batch, out_id, new_t, update_datetime, seq, in_id)?;
+
+        let reversed_edge_range_manager = EdgeRangeManager::new_reversed(self.db_ref);
+        reversed_edge_range_manager.delete(batch, in_id, old_t, update_datetime, seq, out_id)?;
+        reversed_edge_range_manager.set(batch, in_id, new_t, update_datetime, seq, out_id)?;
+
+        self.delete_type_index(batch, out_id, old_t, in_id, update_datetime);
+        self.set_type_index(batch, out_id, new_t, in_id, update_datetime);
+
+        let edge_property_manager = EdgePropertyManager::new(self.db_ref);
+        for item in edge_property_manager.iterate_for_owner(out_id, old_t, in_id)? {
+            let ((_, _, _, edge_property_name), edge_property_value) = item?;
+            edge_property_manager.delete(batch, out_id, old_t, in_id, &edge_property_name)?;
+            edge_property_manager.set(batch, out_id, new_t, in_id, &edge_property_name, &edge_property_value)?;
+        }
+
+        Ok(true)
+    }
+
+    pub fn set_type_index(
+        &self,
+        batch: &mut WriteBatch,
+        out_id: Uuid,
+        t: &models::Identifier,
+        in_id: Uuid,
+        update_datetime: DateTime<Utc>,
+    ) {
+        if self.db_ref.config.edge_type_index {
+            batch.put_cf(
+                self.type_index_cf,
+                self.type_index_key(t, update_datetime, out_id, in_id),
+                [],
+            );
+        }
+    }
+
+    pub fn delete_type_index(
+        &self,
+        batch: &mut WriteBatch,
+        out_id: Uuid,
+        t: &models::Identifier,
+        in_id: Uuid,
+        update_datetime: DateTime<Utc>,
+    ) {
+        if self.db_ref.config.edge_type_index {
+            batch.delete_cf(
+                self.type_index_cf,
+                self.type_index_key(t, update_datetime, out_id, in_id),
+            );
+        }
+    }
+
+    pub fn iterate_all_by_type(
+        &'a self,
+        t: &models::Identifier,
+        high: Option<DateTime<Utc>>,
+    ) -> Result<impl Iterator<Item = Result<EdgeRangeItem>> + 'a> {
+        if !self.db_ref.config.edge_type_index {
+            return Err(Error::Unsupported);
+        }
+
+        let high = high.unwrap_or(*util::MAX_DATETIME);
+        let prefix = util::build(&[util::Component::Identifier(t)]);
+        let low_key = util::build(&[util::Component::Identifier(t), util::Component::DateTime(high)]);
+        let iterator = self
+            .db_ref
+            .db
+            .iterator_cf(self.type_index_cf, IteratorMode::From(&low_key, Direction::Forward));
+        Ok(take_with_prefix(iterator, prefix).map(|(k, _)| Ok(read_type_index_key(&k))))
+    }
+
+    pub fn iterate_type_index_for_all(&'a self) -> Result<impl Iterator<Item = EdgeRangeItem> + 'a> {
+        if !self.db_ref.config.edge_type_index {
+            return Err(Error::Unsupported);
+        }
+
+        let iterator = self.db_ref.db.iterator_cf(self.type_index_cf, IteratorMode::Start);
+        Ok(iterator.map(|(k, _)| read_type_index_key(&k)))
+    }
+
+    pub fn type_index_exists(
+        &self,
+        out_id: Uuid,
+        t: &models::Identifier,
+        in_id: Uuid,
+        update_datetime: DateTime<Utc>,
+    ) -> Result<bool> {
+        let key = self.type_index_key(t, update_datetime, out_id, in_id);
+        Ok(self.db_ref.db.get_pinned_cf(self.type_index_cf, key)?.is_some())
+    }
+
+    fn adjust_degrees(&self, batch: &mut WriteBatch, out_id: Uuid, in_id: Uuid, delta: i64) {
+        if self.db_ref.config.degree_counters {
+            batch.merge_cf(self.degrees_cf, out_id.as_bytes(), build_degrees(delta, 0));
+            batch.merge_cf(self.degrees_cf, in_id.as_bytes(), build_degrees(0, delta));
+        }
+    }
+
+    fn get_degrees(&self, id: Uuid) -> Result<(u64, u64)> {
+        if !self.db_ref.config.degree_counters {
+            return Err(Error::Unsupported);
+        }
+
+        match self.db_ref.db.get_pinned_cf(self.degrees_cf, id.as_bytes())? {
+            Some(value_bytes) => {
+                let (out_degree, in_degree) = read_degrees(&value_bytes);
+                Ok((out_degree.max(0) as u64, in_degree.max(0) as u64))
+            }
+            None => Ok((0, 0)),
+        }
+    }
+
+    pub fn out_degree(&self, id: Uuid) -> Result<u64> {
+        Ok(self.get_degrees(id)?.0)
+    }
+
+    pub fn in_degree(&self, id: Uuid) -> Result<u64> {
+        Ok(self.get_degrees(id)?.1)
+    }
+
+    pub fn distinct_neighbors(&self, id: Uuid, direction: models::EdgeDirection) -> Result<u64> {
+        let edge_range_manager = match direction {
+            models::EdgeDirection::Outbound => EdgeRangeManager::new(self.db_ref),
+            models::EdgeDirection::Inbound => EdgeRangeManager::new_reversed(self.db_ref),
+        };
+
+        // The range is ordered by type first, so the same neighbor can show
+        // up anywhere in it
+        let mut neighbors = HashSet::new();
+        for item in edge_range_manager.iterate_for_range(id, None, None)? {
+            let (_, _, _, neighbor_id) = item?;
+            neighbors.insert(neighbor_id);
+        }
+        Ok(neighbors.len() as u64)
+    }
+
+    pub fn delete_degrees(&self, batch: &mut WriteBatch, id: Uuid) {
+        batch.delete_cf(self.degrees_cf, id.as_bytes());
+    }
+
+    // Overwrites the counters rather than adjusting them, so any adjustments
+    // made earlier in the same batch are discarded
+    pub fn set_degrees(&self, batch: &mut WriteBatch, id: Uuid, out_degree: i64, in_degree: i64) {
+        batch.put_cf(self.degrees_cf, id.as_bytes(), build_degrees(out_degree, in_degree));
+    }
+
+    // Yields the raw counters, which can be negative if they've drifted
+    pub fn iterate_degrees_for_all(&'a self) -> Result<impl Iterator<Item = (Uuid, (i64, i64))> + 'a> {
+        if !self.db_ref.config.degree_counters {
+            return Err(Error::Unsupported);
+        }
+
+        let iterator = self.db_ref.db.iterator_cf(self.degrees_cf, IteratorMode::Start);
+        Ok(iterator.map(|(k, v)| (util::read_uuid(&mut Cursor::new(k)), read_degrees(&v))))
+    }
+
+    pub fn delete_by_type(&self, batch: &mut WriteBatch, out_id: Uuid, t: &models::Identifier) -> Result<u64> {
+        let edge_range_manager = EdgeRangeManager::new(self.db_ref);
+        let mut count = 0;
+        for item in edge_range_manager.iterate_for_range(out_id, Some(t), None)? {
+            let (edge_range_out_id, edge_range_t, edge_range_update_datetime, edge_range_in_id) = item?;
+            self.delete(
                 batch,
-                edge_property_out_id,
-                &edge_property_t,
-                edge_property_in_id,
-                &edge_property_name,
+                edge_range_out_id,
+                &edge_range_t,
+                edge_range_in_id,
+                edge_range_update_datetime,
             )?;
+            count += 1;
         }
-
-        Ok(())
+        Ok(count)
     }
 
     pub fn compact(&self) {
         self.db_ref
             .db
             .compact_range_cf(self.cf, Option::<&[u8]>::None, Option::<&[u8]>::None);
+        self.db_ref
+            .db
+            .compact_range_cf(self.type_index_cf, Option::<&[u8]>::None, Option::<&[u8]>::None);
+    }
+}
+
+// Edge range shards after the first get a numbered suffix, so that an
+// unsharded database is the same as one with a single shard.
+pub(crate) fn shard_cf_name(cf_name: &str, shard: usize) -> String {
+    if shard == 0 {
+        cf_name.to_string()
+    } else {
+        format!("{}:{}", cf_name, shard)
     }
 }
 
 pub(crate) struct EdgeRangeManager<'a> {
     db_ref: DBRef<'a>,
-    cf: &'a ColumnFamily,
+    cfs: Vec<&'a ColumnFamily>,
+    location: &'static str,
 }
 
 impl<'a> EdgeRangeManager<'a> {
     pub fn new(db_ref: DBRef<'a>) -> Self {
-        EdgeRangeManager {
-            db_ref,
-            cf: db_ref.db.cf_handle("edge_ranges:v1").unwrap(),
-        }
+        Self::new_sharded(db_ref, "edge_ranges:v1")
     }
 
     pub fn new_reversed(db_ref: DBRef<'a>) -> Self {
+        Self::new_sharded(db_ref, "reversed_edge_ranges:v1")
+    }
+
+    fn new_sharded(db_ref: DBRef<'a>, cf_name: &'static str) -> Self {
+        let cfs = (0..db_ref.config.edge_range_shards)
+            .map(|shard| db_ref.db.cf_handle(&shard_cf_name(cf_name, shard)).unwrap())
+            .collect();
         EdgeRangeManager {
             db_ref,
-            cf: db_ref.db.cf_handle("reversed_edge_ranges:v1").unwrap(),
+            cfs,
+            location: cf_name,
         }
     }
 
-    fn key(&self, first_id: Uuid, t: &models::Identifier, update_datetime: DateTime<Utc>, second_id: Uuid) -> Vec<u8> {
-        util::build(&[
-            util::Component::Uuid(first_id),
-            util::Component::Identifier(t),
-            util::Component::DateTime(update_datetime),
-            util::Component::Uuid(second_id),
-        ])
+    fn cf(&self, first_id: Uuid) -> &'a ColumnFamily {
+        // Most bits of a v1 UUID rarely vary, so the halves are folded
+        // together and mixed with the splitmix64 finalizer. This decides
+        // where existing entries live, so it must never change.
+        let bits = first_id.as_u128();
+        let mut h = (bits >> 64) as u64 ^ bits as u64;
+        h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        h ^= h >> 31;
+        self.cfs[(h % self.cfs.len() as u64) as usize]
+    }
+
+    fn key(
+        &self,
+        first_id: Uuid,
+        t: &models::Identifier,
+        update_datetime: DateTime<Utc>,
+        seq: Option<u64>,
+        second_id: Uuid,
+    ) -> Vec<u8> {
+        match seq {
+            Some(seq) => util::build(&[
+                util::Component::Uuid(first_id),
+                util::Component::Identifier(t),
+                util::Component::DateTime(update_datetime),
+                util::Component::Sequence(seq),
+                util::Component::Uuid(second_id),
+            ]),
+            None => util::build(&[
+                util::Component::Uuid(first_id),
+                util::Component::Identifier(t),
+                util::Component::DateTime(update_datetime),
+                util::Component::Uuid(second_id),
+            ]),
+        }
     }
 
     fn iterate<I>(&'a self, iterator: I) -> impl Iterator<Item = Result<EdgeRangeItem>> + 'a
     where
         I: Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a,
     {
-        iterator.map(move |item| -> Result<EdgeRangeItem> {
+        self.iterate_with_seq(iterator).map(|item| item.map(|(item, _)| item))
+    }
+
+    fn iterate_with_seq<I>(&'a self, iterator: I) -> impl Iterator<Item = Result<(EdgeRangeItem, Option<u64>)>> + 'a
+    where
+        I: Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a,
+    {
+        let has_seq = self.db_ref.config.edge_sequence_numbers;
+        iterator.map(move |item| -> Result<(EdgeRangeItem, Option<u64>)> {
             let (k, _) = item;
             let mut cursor = Cursor::new(k);
             let first_id = util::read_uuid(&mut cursor);
             let t = util::read_identifier(&mut cursor);
             let update_datetime = util::read_datetime(&mut cursor);
+            let seq = if has_seq {
+                Some(read_sequence(&mut cursor, self.location)?)
+            } else {
+                None
+            };
             let second_id = util::read_uuid(&mut cursor);
-            Ok((first_id, t, update_datetime, second_id))
+            Ok(((first_id, t, update_datetime, second_id), seq))
         })
     }
 
@@ -310,7 +1338,7 @@ impl<'a> EdgeRangeManager<'a> {
                 let iterator = self
                     .db_ref
                     .db
-                    .iterator_cf(self.cf, IteratorMode::From(&low_key, Direction::Forward));
+                    .iterator_cf(self.cf(id), IteratorMode::From(&low_key, Direction::Forward));
                 let iterator = take_with_prefix(iterator, prefix);
                 Ok(Box::new(self.iterate(iterator)))
             }
@@ -319,7 +1347,7 @@ impl<'a> EdgeRangeManager<'a> {
                 let iterator = self
                     .db_ref
                     .db
-                    .iterator_cf(self.cf, IteratorMode::From(&prefix, Direction::Forward));
+                    .iterator_cf(self.cf(id), IteratorMode::From(&prefix, Direction::Forward));
                 let iterator = take_with_prefix(iterator, prefix);
                 let mapped = self.iterate(iterator);
 
@@ -343,9 +1371,74 @@ impl<'a> EdgeRangeManager<'a> {
         }
     }
 
+    // Reads at most `limit` entries, so that checks that only care about the
+    // first few edges of a hub don't scan all of them
+    pub fn iterate_for_owner_limited(
+        &'a self,
+        id: Uuid,
+        limit: usize,
+    ) -> Result<impl Iterator<Item = Result<EdgeRangeItem>> + 'a> {
+        Ok(self.iterate_for_range(id, None, None)?.take(limit))
+    }
+
+    pub fn has_at_least_edges(&self, id: Uuid, k: usize) -> Result<bool> {
+        let mut count = 0;
+        for item in self.iterate_for_owner_limited(id, k)? {
+            item?;
+            count += 1;
+        }
+        Ok(count == k)
+    }
+
+    pub fn iterate_for_window(
+        &'a self,
+        id: Uuid,
+        low: DateTime<Utc>,
+        high: DateTime<Utc>,
+        t: Option<&models::Identifier>,
+    ) -> Result<Box<dyn Iterator<Item = Result<EdgeRangeItem>> + 'a>> {
+        let iterator = self.iterate_for_range(id, t, Some(high))?;
+        let in_window = move |item: &Result<EdgeRangeItem>| {
+            if let Ok((_, _, update_datetime, _)) = *item {
+                update_datetime >= low
+            } else {
+                true
+            }
+        };
+
+        if t.is_some() {
+            // Within a single type, keys are ordered from newest to oldest,
+            // so we can stop at the first edge older than `low`.
+            Ok(Box::new(iterator.take_while(in_window)))
+        } else {
+            // Across types, datetimes aren't ordered, so every edge of the
+            // vertex has to be checked.
+            Ok(Box::new(iterator.filter(in_window)))
+        }
+    }
+
     pub fn iterate_for_all(&'a self) -> impl Iterator<Item = Result<EdgeRangeItem>> + 'a {
-        let iterator = self.db_ref.db.iterator_cf(self.cf, IteratorMode::Start);
-        self.iterate(iterator)
+        self.iterate_for_all_with_seq().map(|item| item.map(|(item, _)| item))
+    }
+
+    pub fn iterate_for_all_with_seq(&'a self) -> impl Iterator<Item = Result<(EdgeRangeItem, Option<u64>)>> + 'a {
+        let iterator = self
+            .cfs
+            .iter()
+            .flat_map(move |cf| self.db_ref.db.iterator_cf(cf, IteratorMode::Start));
+        self.iterate_with_seq(iterator)
+    }
+
+    pub fn exists(
+        &self,
+        first_id: Uuid,
+        t: &models::Identifier,
+        update_datetime: DateTime<Utc>,
+        seq: Option<u64>,
+        second_id: Uuid,
+    ) -> Result<bool> {
+        let key = self.key(first_id, t, update_datetime, seq, second_id);
+        Ok(self.db_ref.db.get_pinned_cf(self.cf(first_id), key)?.is_some())
     }
 
     pub fn set(
@@ -354,10 +1447,11 @@ impl<'a> EdgeRangeManager<'a> {
         first_id: Uuid,
         t: &models::Identifier,
         update_datetime: DateTime<Utc>,
+        seq: Option<u64>,
         second_id: Uuid,
     ) -> Result<()> {
-        let key = self.key(first_id, t, update_datetime, second_id);
-        batch.put_cf(self.cf, &key, &[]);
+        let key = self.key(first_id, t, update_datetime, seq, second_id);
+        batch.put_cf(self.cf(first_id), key, []);
         Ok(())
     }
 
@@ -367,16 +1461,22 @@ impl<'a> EdgeRangeManager<'a> {
         first_id: Uuid,
         t: &models::Identifier,
         update_datetime: DateTime<Utc>,
+        seq: Option<u64>,
         second_id: Uuid,
     ) -> Result<()> {
-        batch.delete_cf(self.cf, &self.key(first_id, t, update_datetime, second_id));
+        batch.delete_cf(
+            self.cf(first_id),
+            self.key(first_id, t, update_datetime, seq, second_id),
+        );
         Ok(())
     }
 
     pub fn compact(&self) {
-        self.db_ref
-            .db
-            .compact_range_cf(self.cf, Option::<&[u8]>::None, Option::<&[u8]>::None);
+        for cf in &self.cfs {
+            self.db_ref
+                .db
+                .compact_range_cf(cf, Option::<&[u8]>::None, Option::<&[u8]>::None);
+        }
     }
 }
 
@@ -404,7 +1504,18 @@ impl<'a> VertexPropertyManager<'a> {
         &'a self,
         vertex_id: Uuid,
     ) -> Result<impl Iterator<Item = Result<OwnedPropertyItem>> + 'a> {
-        let prefix = util::build(&[util::Component::Uuid(vertex_id)]);
+        self.iterate_for_owner_prefixed(vertex_id, "")
+    }
+
+    pub fn iterate_for_owner_prefixed(
+        &'a self,
+        vertex_id: Uuid,
+        name_prefix: &str,
+    ) -> Result<impl Iterator<Item = Result<OwnedPropertyItem>> + 'a> {
+        let prefix = util::build(&[
+            util::Component::Uuid(vertex_id),
+            util::Component::FixedLengthString(name_prefix),
+        ]);
 
         let iterator = self
             .db_ref
@@ -418,22 +1529,64 @@ impl<'a> VertexPropertyManager<'a> {
             let mut cursor = Cursor::new(k);
             let owner_id = util::read_uuid(&mut cursor);
             debug_assert_eq!(vertex_id, owner_id);
-            let name_str = util::read_fixed_length_string(&mut cursor);
-            let name = unsafe { models::Identifier::new_unchecked(name_str) };
-            let value = serde_json::from_slice(&v)?;
+            let name = read_name(&mut cursor, "vertex_properties:v1")?;
+            let value = read_json(&v, "vertex_properties:v1", || {
+                vertex_property_key_description(owner_id, &name)
+            })?;
             Ok(((owner_id, name), value))
         }))
     }
 
+    pub fn iterate_names_for_all(&'a self) -> impl Iterator<Item = Result<(Uuid, models::Identifier)>> + 'a {
+        let iterator = self.db_ref.db.iterator_cf(self.cf, IteratorMode::Start);
+        iterator.map(|(k, _)| {
+            let mut cursor = Cursor::new(k);
+            let owner_id = util::read_uuid(&mut cursor);
+            let name = read_name(&mut cursor, "vertex_properties:v1")?;
+            Ok((owner_id, name))
+        })
+    }
+
+    pub fn distinct_names(&self) -> Result<Vec<models::Identifier>> {
+        let mut names = BTreeSet::new();
+        for item in self.iterate_names_for_all() {
+            let (_, name) = item?;
+            names.insert(name);
+        }
+        Ok(names.into_iter().collect())
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "VertexPropertyManager::get", level = "trace", skip_all, err, fields(key_bytes = tracing::field::Empty))
+    )]
     pub fn get(&self, vertex_id: Uuid, name: &models::Identifier) -> Result<Option<models::Json>> {
-        let key = self.key(vertex_id, name);
-
-        match self.db_ref.db.get_cf(self.cf, &key)? {
-            Some(value_bytes) => Ok(Some(serde_json::from_slice(&value_bytes)?)),
+        trace_key_bytes(|| self.key(vertex_id, name).len());
+        match self.get_bytes(vertex_id, name)? {
+            Some(value_bytes) => Ok(Some(self.decode(vertex_id, name, &value_bytes)?)),
             None => Ok(None),
         }
     }
 
+    pub fn contains(&self, vertex_id: Uuid, name: &models::Identifier) -> Result<bool> {
+        Ok(self.get_bytes(vertex_id, name)?.is_some())
+    }
+
+    fn get_bytes(&self, vertex_id: Uuid, name: &models::Identifier) -> Result<Option<DBPinnableSlice<'a>>> {
+        let key = self.key(vertex_id, name);
+        Ok(self.db_ref.db.get_pinned_cf(self.cf, &key)?)
+    }
+
+    fn decode(&self, vertex_id: Uuid, name: &models::Identifier, value_bytes: &[u8]) -> Result<models::Json> {
+        read_json(value_bytes, "vertex_properties:v1", || {
+            vertex_property_key_description(vertex_id, name)
+        })
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "VertexPropertyManager::set", level = "trace", skip_all, err, fields(key_bytes = tracing::field::Empty))
+    )]
     pub fn set(
         &self,
         batch: &mut WriteBatch,
@@ -441,12 +1594,13 @@ impl<'a> VertexPropertyManager<'a> {
         name: &models::Identifier,
         value: &models::Json,
     ) -> Result<()> {
+        let value_json = write_json(self.db_ref, value)?;
         let is_indexed = self.db_ref.indexed_properties.contains(name);
         let key = self.key(vertex_id, name);
+        trace_key_bytes(|| key.len());
         if is_indexed {
             self.delete(batch, vertex_id, name)?;
         }
-        let value_json = serde_json::to_vec(value)?;
         batch.put_cf(self.cf, &key, &value_json);
         if is_indexed {
             let vertex_property_value_manager = VertexPropertyValueManager::new(self.db_ref);
@@ -527,10 +1681,16 @@ impl<'a> EdgePropertyManager<'a> {
             let edge_property_in_id = util::read_uuid(&mut cursor);
             debug_assert_eq!(edge_property_in_id, in_id);
 
-            let edge_property_name_str = util::read_fixed_length_string(&mut cursor);
-            let edge_property_name = unsafe { models::Identifier::new_unchecked(edge_property_name_str) };
+            let edge_property_name = read_name(&mut cursor, "edge_properties:v1")?;
 
-            let value = serde_json::from_slice(&v)?;
+            let value = read_json(&v, "edge_properties:v1", || {
+                edge_property_key_description(
+                    edge_property_out_id,
+                    &edge_property_t,
+                    edge_property_in_id,
+                    &edge_property_name,
+                )
+            })?;
             Ok((
                 (
                     edge_property_out_id,
@@ -545,6 +1705,33 @@ impl<'a> EdgePropertyManager<'a> {
         Ok(Box::new(mapped))
     }
 
+    pub fn iterate_names_for_all(
+        &'a self,
+    ) -> impl Iterator<Item = Result<(Uuid, models::Identifier, Uuid, models::Identifier)>> + 'a {
+        let iterator = self.db_ref.db.iterator_cf(self.cf, IteratorMode::Start);
+        iterator.map(|(k, _)| {
+            let mut cursor = Cursor::new(k);
+            let out_id = util::read_uuid(&mut cursor);
+            let t = util::read_identifier(&mut cursor);
+            let in_id = util::read_uuid(&mut cursor);
+            let name = read_name(&mut cursor, "edge_properties:v1")?;
+            Ok((out_id, t, in_id, name))
+        })
+    }
+
+    pub fn distinct_names(&self) -> Result<Vec<models::Identifier>> {
+        let mut names = BTreeSet::new();
+        for item in self.iterate_names_for_all() {
+            let (_, _, _, name) = item?;
+            names.insert(name);
+        }
+        Ok(names.into_iter().collect())
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "EdgePropertyManager::get", level = "trace", skip_all, err, fields(key_bytes = tracing::field::Empty))
+    )]
     pub fn get(
         &self,
         out_id: Uuid,
@@ -553,13 +1740,31 @@ impl<'a> EdgePropertyManager<'a> {
         name: &models::Identifier,
     ) -> Result<Option<models::Json>> {
         let key = self.key(out_id, t, in_id, name);
+        trace_key_bytes(|| key.len());
 
         match self.db_ref.db.get_cf(self.cf, &key)? {
-            Some(value_bytes) => Ok(Some(serde_json::from_slice(&value_bytes)?)),
+            Some(value_bytes) => Ok(Some(read_json(&value_bytes, "edge_properties:v1", || {
+                edge_property_key_description(out_id, t, in_id, name)
+            })?)),
             None => Ok(None),
         }
     }
 
+    pub fn contains(
+        &self,
+        out_id: Uuid,
+        t: &models::Identifier,
+        in_id: Uuid,
+        name: &models::Identifier,
+    ) -> Result<bool> {
+        let key = self.key(out_id, t, in_id, name);
+        Ok(self.db_ref.db.get_pinned_cf(self.cf, key)?.is_some())
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "EdgePropertyManager::set", level = "trace", skip_all, err, fields(key_bytes = tracing::field::Empty))
+    )]
     pub fn set(
         &self,
         batch: &mut WriteBatch,
@@ -569,12 +1774,13 @@ impl<'a> EdgePropertyManager<'a> {
         name: &models::Identifier,
         value: &models::Json,
     ) -> Result<()> {
+        let value_json = write_json(self.db_ref, value)?;
         let is_indexed = self.db_ref.indexed_properties.contains(name);
         let key = self.key(out_id, t, in_id, name);
+        trace_key_bytes(|| key.len());
         if is_indexed {
             self.delete(batch, out_id, t, in_id, name)?;
         }
-        let value_json = serde_json::to_vec(value)?;
         batch.put_cf(self.cf, &key, &value_json);
         if is_indexed {
             let edge_property_value_manager = EdgePropertyValueManager::new(self.db_ref);
@@ -583,6 +1789,17 @@ impl<'a> EdgePropertyManager<'a> {
         Ok(())
     }
 
+    pub fn set_many(
+        &self,
+        batch: &mut WriteBatch,
+        items: &[(Uuid, models::Identifier, Uuid, models::Identifier, models::Json)],
+    ) -> Result<()> {
+        for (out_id, t, in_id, name, value) in items {
+            self.set(batch, *out_id, t, *in_id, name, value)?;
+        }
+        Ok(())
+    }
+
     pub fn delete(
         &self,
         batch: &mut WriteBatch,
@@ -601,6 +1818,28 @@ impl<'a> EdgePropertyManager<'a> {
         Ok(())
     }
 
+    pub fn delete_all_for_owner(
+        &self,
+        batch: &mut WriteBatch,
+        out_id: Uuid,
+        t: &models::Identifier,
+        in_id: Uuid,
+    ) -> Result<u64> {
+        let edge_property_value_manager = EdgePropertyValueManager::new(self.db_ref);
+        let mut count = 0;
+        for item in self.iterate_for_owner(out_id, t, in_id)? {
+            let ((_, _, _, name), value) = item?;
+            // The value was just read, so there's no need to look it up again
+            // like `delete` does
+            if self.db_ref.indexed_properties.contains(&name) {
+                edge_property_value_manager.delete(batch, out_id, t, in_id, &name, &value);
+            }
+            batch.delete_cf(self.cf, self.key(out_id, t, in_id, &name));
+            count += 1;
+        }
+        Ok(count)
+    }
+
     pub fn compact(&self) {
         self.db_ref
             .db
@@ -608,9 +1847,59 @@ impl<'a> EdgePropertyManager<'a> {
     }
 }
 
+pub(crate) struct CachedVertexPropertyManager<'a> {
+    manager: VertexPropertyManager<'a>,
+    cache: &'a PropertyCache,
+}
+
+impl<'a> CachedVertexPropertyManager<'a> {
+    pub fn new(db_ref: DBRef<'a>, cache: &'a PropertyCache) -> Self {
+        CachedVertexPropertyManager {
+            manager: VertexPropertyManager::new(db_ref),
+            cache,
+        }
+    }
+
+    pub fn get(&self, vertex_id: Uuid, name: &models::Identifier) -> Result<Option<models::Json>> {
+        match self.manager.get_bytes(vertex_id, name)? {
+            Some(value_bytes) => {
+                if let Some(value) = self.cache.get(vertex_id, name, &value_bytes) {
+                    return Ok(Some(value));
+                }
+                let value = self.manager.decode(vertex_id, name, &value_bytes)?;
+                self.cache.insert(vertex_id, name, &value_bytes, value.clone());
+                Ok(Some(value))
+            }
+            None => {
+                self.cache.invalidate(vertex_id, name);
+                Ok(None)
+            }
+        }
+    }
+
+    pub fn set(
+        &self,
+        batch: &mut WriteBatch,
+        vertex_id: Uuid,
+        name: &models::Identifier,
+        value: &models::Json,
+    ) -> Result<()> {
+        self.cache.invalidate(vertex_id, name);
+        self.manager.set(batch, vertex_id, name, value)
+    }
+
+    pub fn delete(&self, batch: &mut WriteBatch, vertex_id: Uuid, name: &models::Identifier) -> Result<()> {
+        self.cache.invalidate(vertex_id, name);
+        self.manager.delete(batch, vertex_id, name)
+    }
+}
+
 pub(crate) struct VertexPropertyValueManager<'a> {
     db_ref: DBRef<'a>,
     cf: &'a ColumnFamily,
+    // Numeric values are additionally indexed in numeric order, so that they
+    // can be range scanned
+    numbers_cf: &'a ColumnFamily,
 }
 
 impl<'a> VertexPropertyValueManager<'a> {
@@ -618,9 +1907,18 @@ impl<'a> VertexPropertyValueManager<'a> {
         VertexPropertyValueManager {
             db_ref,
             cf: db_ref.db.cf_handle("vertex_property_values:v1").unwrap(),
+            numbers_cf: db_ref.db.cf_handle("vertex_property_numbers:v1").unwrap(),
         }
     }
 
+    fn number_key(&self, property_name: &models::Identifier, number: f64, vertex_id: Uuid) -> Vec<u8> {
+        util::build(&[
+            util::Component::Identifier(property_name),
+            util::Component::Number(number),
+            util::Component::Uuid(vertex_id),
+        ])
+    }
+
     fn key(&self, property_name: &models::Identifier, property_value: &models::Json, vertex_id: Uuid) -> Vec<u8> {
         util::build(&[
             util::Component::Identifier(property_name),
@@ -674,6 +1972,31 @@ impl<'a> VertexPropertyValueManager<'a> {
         self.iterate(iter, prefix)
     }
 
+    // Yields the IDs of vertices whose value for the property is a number
+    // between `low` and `high` inclusive, in ascending order of value
+    pub fn iterate_for_number_range(
+        &'a self,
+        property_name: &models::Identifier,
+        low: f64,
+        high: f64,
+    ) -> impl Iterator<Item = Uuid> + 'a {
+        let prefix = util::build(&[util::Component::Identifier(property_name)]);
+        let low_key = util::build(&[util::Component::Identifier(property_name), util::Component::Number(low)]);
+        let iter = self
+            .db_ref
+            .db
+            .iterator_cf(self.numbers_cf, IteratorMode::From(&low_key, Direction::Forward));
+        take_with_prefix(iter, prefix)
+            .map(|(k, _)| {
+                let mut cursor = Cursor::new(k);
+                util::read_identifier(&mut cursor);
+                let number = util::read_number(&mut cursor);
+                (number, util::read_uuid(&mut cursor))
+            })
+            .take_while(move |(number, _)| *number <= high)
+            .map(|(_, vertex_id)| vertex_id)
+    }
+
     pub fn set(
         &self,
         batch: &mut WriteBatch,
@@ -683,6 +2006,9 @@ impl<'a> VertexPropertyValueManager<'a> {
     ) {
         let key = self.key(property_name, property_value, vertex_id);
         batch.put_cf(self.cf, key, &[]);
+        if let Some(number) = property_value.0.as_f64() {
+            batch.put_cf(self.numbers_cf, self.number_key(property_name, number, vertex_id), []);
+        }
     }
 
     pub fn delete(
@@ -694,12 +2020,18 @@ impl<'a> VertexPropertyValueManager<'a> {
     ) {
         let key = self.key(property_name, property_value, vertex_id);
         batch.delete_cf(self.cf, key);
+        if let Some(number) = property_value.0.as_f64() {
+            batch.delete_cf(self.numbers_cf, self.number_key(property_name, number, vertex_id));
+        }
     }
 
     pub fn compact(&self) {
         self.db_ref
             .db
             .compact_range_cf(self.cf, Option::<&[u8]>::None, Option::<&[u8]>::None);
+        self.db_ref
+            .db
+            .compact_range_cf(self.numbers_cf, Option::<&[u8]>::None, Option::<&[u8]>::None);
     }
 }
 
@@ -835,8 +2167,1280 @@ impl<'a> MetadataManager<'a> {
         Ok(())
     }
 
+    pub fn get_edge_seq(&self) -> Result<u64> {
+        match self.db.get_cf(self.cf, "edge_seq")? {
+            Some(value_bytes) => Ok(util::read_u64(&mut Cursor::new(value_bytes))),
+            None => Ok(0),
+        }
+    }
+
+    // Written directly rather than in a batch, so that it's persisted before
+    // any batch that uses the sequence numbers it reserves
+    pub fn set_edge_seq(&self, seq: u64) -> Result<()> {
+        self.db.put_cf(self.cf, "edge_seq", seq.to_be_bytes())?;
+        Ok(())
+    }
+
+    pub fn get_setting(&self, name: &str) -> Result<Option<bool>> {
+        match self.db.get_cf(self.cf, name)? {
+            Some(value_bytes) => Ok(Some(value_bytes == [1])),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_setting(&self, batch: &mut WriteBatch, name: &str, enabled: bool) {
+        batch.put_cf(self.cf, name, [enabled as u8]);
+    }
+
     pub fn compact(&self) {
         self.db
             .compact_range_cf(self.cf, Option::<&[u8]>::None, Option::<&[u8]>::None);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::error::Error as StdError;
+    use std::io::Cursor;
+
+    use super::{
+        merge_degrees, CachedVertexPropertyManager, DBRef, EdgeManager, EdgePropertyManager, EdgePropertyValueManager,
+        EdgeRangeManager, EdgeSeq, MetadataManager, VertexManager, VertexPropertyManager,
+    };
+    use crate::errors::Error;
+    use crate::models;
+    use crate::rdb::cache::PropertyCache;
+    use crate::rdb::datastore::{RocksdbConfig, CF_NAMES};
+    use crate::util;
+
+    use chrono::offset::{TimeZone, Utc};
+    use chrono::Duration;
+    use rocksdb::{ColumnFamilyDescriptor, Options, WriteBatch, DB};
+    use tempfile::{tempdir, TempDir};
+    use uuid::Uuid;
+
+    // A temporary database along with the state that a `DBRef` borrows
+    struct TestDb {
+        _dir: TempDir,
+        db: DB,
+        indexed_properties: HashSet<models::Identifier>,
+        config: RocksdbConfig,
+        edge_seq: EdgeSeq,
+    }
+
+    impl TestDb {
+        fn config(mut self, config: RocksdbConfig) -> Self {
+            self.config = config;
+            self
+        }
+
+        fn indexed_properties(mut self, indexed_properties: HashSet<models::Identifier>) -> Self {
+            self.indexed_properties = indexed_properties;
+            self
+        }
+
+        fn db_ref(&self) -> DBRef<'_> {
+            DBRef::new(&self.db, &self.indexed_properties, &self.config, &self.edge_seq)
+        }
+    }
+
+    fn open_db() -> TestDb {
+        open_db_with_options(Options::default())
+    }
+
+    fn open_db_with_options(mut opts: Options) -> TestDb {
+        let dir = tempdir().unwrap();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        opts.set_merge_operator_associative("vertex_degrees", merge_degrees);
+        let cf_descriptors = CF_NAMES
+            .iter()
+            .map(|cf_name| ColumnFamilyDescriptor::new(*cf_name, opts.clone()));
+        let db = DB::open_cf_descriptors(&opts, dir.path(), cf_descriptors).unwrap();
+        TestDb {
+            _dir: dir,
+            db,
+            indexed_properties: HashSet::default(),
+            config: RocksdbConfig::default(),
+            edge_seq: EdgeSeq::default(),
+        }
+    }
+
+    // Reads a ticker from the statistics of a database opened with `opts`,
+    // which must have had statistics enabled
+    fn get_ticker(opts: &Options, name: &str) -> u64 {
+        let statistics = opts.get_statistics().unwrap();
+        let line = statistics
+            .lines()
+            .find(|line| line.starts_with(&format!("{} ", name)))
+            .unwrap();
+        line.rsplit(' ').next().unwrap().parse().unwrap()
+    }
+
+    #[test]
+    fn should_upsert_new_edge() {
+        let test_db = open_db();
+        let (db, db_ref) = (&test_db.db, test_db.db_ref());
+        let edge_manager = EdgeManager::new(db_ref);
+        let t = models::Identifier::new("test_edge_type").unwrap();
+        let (out_id, in_id) = (util::generate_uuid_v1(), util::generate_uuid_v1());
+
+        let mut batch = WriteBatch::default();
+        assert!(edge_manager.upsert(&mut batch, out_id, &t, in_id, Utc::now()).unwrap());
+        db.write(batch).unwrap();
+        assert!(edge_manager.get(out_id, &t, in_id).unwrap().is_some());
+    }
+
+    #[test]
+    fn should_upsert_existing_edge() {
+        let test_db = open_db();
+        let (db, db_ref) = (&test_db.db, test_db.db_ref());
+        let edge_manager = EdgeManager::new(db_ref);
+        let t = models::Identifier::new("test_edge_type").unwrap();
+        let (out_id, in_id) = (util::generate_uuid_v1(), util::generate_uuid_v1());
+
+        let mut batch = WriteBatch::default();
+        edge_manager.set(&mut batch, out_id, &t, in_id, Utc::now()).unwrap();
+        db.write(batch).unwrap();
+
+        let update_datetime = Utc::now();
+        let mut batch = WriteBatch::default();
+        assert!(!edge_manager
+            .upsert(&mut batch, out_id, &t, in_id, update_datetime)
+            .unwrap());
+        db.write(batch).unwrap();
+        assert_eq!(edge_manager.get(out_id, &t, in_id).unwrap(), Some(update_datetime));
+
+        // The old range entries should have been replaced, not duplicated
+        let edge_range_manager = EdgeRangeManager::new(db_ref);
+        assert_eq!(
+            edge_range_manager
+                .iterate_for_range(out_id, None, None)
+                .unwrap()
+                .count(),
+            1
+        );
+        let reversed_edge_range_manager = EdgeRangeManager::new_reversed(db_ref);
+        assert_eq!(
+            reversed_edge_range_manager
+                .iterate_for_range(in_id, None, None)
+                .unwrap()
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn should_get_corrupt_vertex_property() {
+        let test_db = open_db();
+        let (db, db_ref) = (&test_db.db, test_db.db_ref());
+        let vertex_property_manager = VertexPropertyManager::new(db_ref);
+        let name = models::Identifier::new("test_property").unwrap();
+        let (corrupt_id, missing_id) = (util::generate_uuid_v1(), util::generate_uuid_v1());
+
+        let key = vertex_property_manager.key(corrupt_id, &name);
+        db.put_cf(vertex_property_manager.cf, key, b"{not json").unwrap();
+
+        match vertex_property_manager.get(corrupt_id, &name) {
+            Err(err @ Error::Corrupt { .. }) => {
+                // The message should name the offending key, and the
+                // deserialization error should be preserved
+                let message = err.to_string();
+                assert!(message.contains("vertex_properties:v1"));
+                assert!(message.contains(&corrupt_id.to_string()));
+                assert!(message.contains("test_property"));
+                assert!(err.source().is_some());
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(vertex_property_manager.get(missing_id, &name).unwrap(), None);
+    }
+
+    #[test]
+    fn should_compress_property_values_above_threshold() {
+        let test_db = open_db().config(RocksdbConfig::default().property_compress_threshold(Some(64)));
+        let (db, db_ref) = (&test_db.db, test_db.db_ref());
+        let vertex_property_manager = VertexPropertyManager::new(db_ref);
+        let edge_property_manager = EdgePropertyManager::new(db_ref);
+        let t = models::Identifier::new("test_type").unwrap();
+        let name = models::Identifier::new("test_property").unwrap();
+        let (out_id, in_id) = (util::generate_uuid_v1(), util::generate_uuid_v1());
+        let small_value = models::Json::new(serde_json::json!({"name": "small"}));
+        let large_value = models::Json::new(serde_json::json!(vec!["repeated value"; 50]));
+
+        let mut batch = WriteBatch::default();
+        vertex_property_manager
+            .set(&mut batch, out_id, &name, &small_value)
+            .unwrap();
+        vertex_property_manager
+            .set(&mut batch, in_id, &name, &large_value)
+            .unwrap();
+        edge_property_manager
+            .set(&mut batch, out_id, &t, in_id, &name, &large_value)
+            .unwrap();
+        db.write(batch).unwrap();
+
+        // Below the threshold, values are stored as plain JSON
+        let small_bytes = vertex_property_manager.get_bytes(out_id, &name).unwrap().unwrap();
+        assert_eq!(&small_bytes[..], &serde_json::to_vec(&small_value).unwrap()[..]);
+
+        let large_json = serde_json::to_vec(&large_value).unwrap();
+        let large_bytes = vertex_property_manager.get_bytes(in_id, &name).unwrap().unwrap();
+        assert_eq!(large_bytes[0], 0);
+        assert!(large_bytes.len() < large_json.len());
+        let key = edge_property_manager.key(out_id, &t, in_id, &name);
+        let edge_bytes = db.get_cf(edge_property_manager.cf, key).unwrap().unwrap();
+        assert_eq!(edge_bytes, &large_bytes[..]);
+
+        assert_eq!(vertex_property_manager.get(out_id, &name).unwrap(), Some(small_value));
+        assert_eq!(
+            vertex_property_manager.get(in_id, &name).unwrap(),
+            Some(large_value.clone())
+        );
+        assert_eq!(
+            edge_property_manager.get(out_id, &t, in_id, &name).unwrap(),
+            Some(large_value.clone())
+        );
+
+        // Compressed values are still readable with compression disabled
+        let config = RocksdbConfig::default();
+        let db_ref = DBRef::new(db, &test_db.indexed_properties, &config, &test_db.edge_seq);
+        assert_eq!(
+            VertexPropertyManager::new(db_ref).get(in_id, &name).unwrap(),
+            Some(large_value)
+        );
+    }
+
+    #[test]
+    fn should_error_on_property_names_that_arent_utf8() {
+        let test_db = open_db();
+        let (db, db_ref) = (&test_db.db, test_db.db_ref());
+        let vertex_property_manager = VertexPropertyManager::new(db_ref);
+        let edge_property_manager = EdgePropertyManager::new(db_ref);
+        let t = models::Identifier::new("test_type").unwrap();
+        let (out_id, in_id) = (util::generate_uuid_v1(), util::generate_uuid_v1());
+        let invalid_name = [0xff, 0xfe];
+
+        let mut key = util::build(&[util::Component::Uuid(out_id)]);
+        key.extend_from_slice(&invalid_name);
+        db.put_cf(vertex_property_manager.cf, key, b"true").unwrap();
+        let mut key = util::build(&[
+            util::Component::Uuid(out_id),
+            util::Component::Identifier(&t),
+            util::Component::Uuid(in_id),
+        ]);
+        key.extend_from_slice(&invalid_name);
+        db.put_cf(edge_property_manager.cf, key, b"true").unwrap();
+
+        let items: Vec<_> = vertex_property_manager.iterate_for_owner(out_id).unwrap().collect();
+        assert_eq!(items.len(), 1);
+        match &items[0] {
+            Err(err @ Error::CorruptKey { .. }) => {
+                assert!(err.to_string().contains("vertex_properties:v1"));
+                assert!(err.source().is_some());
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        let items: Vec<_> = edge_property_manager
+            .iterate_for_owner(out_id, &t, in_id)
+            .unwrap()
+            .collect();
+        assert_eq!(items.len(), 1);
+        assert!(matches!(items[0], Err(Error::CorruptKey { .. })));
+
+        assert!(matches!(
+            vertex_property_manager.distinct_names(),
+            Err(Error::CorruptKey { .. })
+        ));
+        assert!(matches!(
+            edge_property_manager.distinct_names(),
+            Err(Error::CorruptKey { .. })
+        ));
+    }
+
+    #[test]
+    fn should_error_on_edges_without_sequence_numbers() {
+        let test_db = open_db();
+        let (db, db_ref) = (&test_db.db, test_db.db_ref());
+        let t = models::Identifier::new("test_edge_type").unwrap();
+        let (out_id, in_id) = (util::generate_uuid_v1(), util::generate_uuid_v1());
+
+        let mut batch = WriteBatch::default();
+        EdgeManager::new(db_ref)
+            .set(&mut batch, out_id, &t, in_id, Utc::now())
+            .unwrap();
+        db.write(batch).unwrap();
+
+        // Reading the edge as if it had been written with a sequence number
+        let config = RocksdbConfig::default().edge_sequence_numbers();
+        let db_ref = DBRef::new(db, &test_db.indexed_properties, &config, &test_db.edge_seq);
+        match EdgeManager::new(db_ref).get(out_id, &t, in_id) {
+            Err(err @ Error::CorruptKey { .. }) => {
+                assert!(err.to_string().contains("edges:v1"));
+                assert!(err.source().is_some());
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn should_check_property_presence_without_decoding() {
+        let test_db = open_db();
+        let (db, db_ref) = (&test_db.db, test_db.db_ref());
+        let vertex_property_manager = VertexPropertyManager::new(db_ref);
+        let edge_property_manager = EdgePropertyManager::new(db_ref);
+        let t = models::Identifier::new("test_type").unwrap();
+        let name = models::Identifier::new("test_property").unwrap();
+        let other_name = models::Identifier::new("other_test_property").unwrap();
+        let (out_id, in_id) = (util::generate_uuid_v1(), util::generate_uuid_v1());
+        let value = models::Json::new(serde_json::json!(true));
+
+        let mut batch = WriteBatch::default();
+        vertex_property_manager.set(&mut batch, out_id, &name, &value).unwrap();
+        edge_property_manager
+            .set(&mut batch, out_id, &t, in_id, &name, &value)
+            .unwrap();
+        db.write(batch).unwrap();
+        assert!(vertex_property_manager.contains(out_id, &name).unwrap());
+        assert!(edge_property_manager.contains(out_id, &t, in_id, &name).unwrap());
+        assert!(!vertex_property_manager.contains(out_id, &other_name).unwrap());
+        assert!(!edge_property_manager.contains(out_id, &t, in_id, &other_name).unwrap());
+
+        // Values that wouldn't decode are still present
+        let key = vertex_property_manager.key(in_id, &name);
+        db.put_cf(vertex_property_manager.cf, key, b"{not json").unwrap();
+        assert!(vertex_property_manager.contains(in_id, &name).unwrap());
+
+        let mut batch = WriteBatch::default();
+        vertex_property_manager.delete(&mut batch, out_id, &name).unwrap();
+        edge_property_manager
+            .delete(&mut batch, out_id, &t, in_id, &name)
+            .unwrap();
+        db.write(batch).unwrap();
+        assert!(!vertex_property_manager.contains(out_id, &name).unwrap());
+        assert!(!edge_property_manager.contains(out_id, &t, in_id, &name).unwrap());
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn should_trace_manager_operations() {
+        use std::sync::{Arc, Mutex};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata, Subscriber};
+
+        // Records the names of the spans that are created
+        struct SpanNames(Arc<Mutex<Vec<&'static str>>>);
+
+        impl Subscriber for SpanNames {
+            fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                let mut names = self.0.lock().unwrap();
+                names.push(span.metadata().name());
+                Id::from_u64(names.len() as u64)
+            }
+
+            fn record(&self, _span: &Id, _values: &Record<'_>) {}
+            fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+            fn event(&self, _event: &Event<'_>) {}
+            fn enter(&self, _span: &Id) {}
+            fn exit(&self, _span: &Id) {}
+        }
+
+        let test_db = open_db();
+        let (db, db_ref) = (&test_db.db, test_db.db_ref());
+        let names = Arc::new(Mutex::new(Vec::new()));
+
+        tracing::subscriber::with_default(SpanNames(names.clone()), || {
+            let vertex_manager = VertexManager::new(db_ref);
+            let edge_manager = EdgeManager::new(db_ref);
+            let vertex_property_manager = VertexPropertyManager::new(db_ref);
+            let edge_property_manager = EdgePropertyManager::new(db_ref);
+            let t = models::Identifier::new("test_type").unwrap();
+            let name = models::Identifier::new("test_property").unwrap();
+            let value = models::Json::new(serde_json::json!(true));
+            let (out_v, in_v) = (models::Vertex::new(t.clone()), models::Vertex::new(t.clone()));
+
+            let mut batch = WriteBatch::default();
+            vertex_manager.create(&mut batch, &out_v).unwrap();
+            vertex_manager.create(&mut batch, &in_v).unwrap();
+            edge_manager.set(&mut batch, out_v.id, &t, in_v.id, Utc::now()).unwrap();
+            vertex_property_manager
+                .set(&mut batch, out_v.id, &name, &value)
+                .unwrap();
+            edge_property_manager
+                .set(&mut batch, out_v.id, &t, in_v.id, &name, &value)
+                .unwrap();
+            db_ref.write(batch).unwrap();
+
+            vertex_manager.get(out_v.id).unwrap();
+            vertex_property_manager.get(out_v.id, &name).unwrap();
+            edge_property_manager.get(out_v.id, &t, in_v.id, &name).unwrap();
+        });
+
+        assert_eq!(
+            *names.lock().unwrap(),
+            vec![
+                "EdgeManager::set",
+                "VertexPropertyManager::set",
+                "EdgePropertyManager::set",
+                "DBRef::write",
+                "VertexManager::get",
+                "VertexPropertyManager::get",
+                "EdgePropertyManager::get",
+            ]
+        );
+    }
+
+    #[test]
+    fn should_set_vertex_type() {
+        let test_db = open_db();
+        let (db, db_ref) = (&test_db.db, test_db.db_ref());
+        let vertex_manager = VertexManager::new(db_ref);
+        let old_t = models::Identifier::new("old_vertex_type").unwrap();
+        let new_t = models::Identifier::new("new_vertex_type").unwrap();
+        let vertex = models::Vertex::new(old_t.clone());
+
+        let mut batch = WriteBatch::default();
+        vertex_manager.create(&mut batch, &vertex).unwrap();
+        db.write(batch).unwrap();
+
+        let mut batch = WriteBatch::default();
+        assert!(vertex_manager.set_type(&mut batch, vertex.id, &new_t).unwrap());
+        assert!(!vertex_manager
+            .set_type(&mut batch, util::generate_uuid_v1(), &new_t)
+            .unwrap());
+        db.write(batch).unwrap();
+
+        assert_eq!(vertex_manager.get(vertex.id).unwrap(), Some(new_t.clone()));
+        let types: Vec<_> = vertex_manager
+            .iterate_for_range(Uuid::default())
+            .map(|item| item.unwrap().1)
+            .collect();
+        assert_eq!(types, vec![new_t]);
+    }
+
+    #[test]
+    fn should_not_create_an_existing_vertex() {
+        let test_db = open_db();
+        let (db, db_ref) = (&test_db.db, test_db.db_ref());
+        let vertex_manager = VertexManager::new(db_ref);
+        let old_t = models::Identifier::new("old_vertex_type").unwrap();
+        let new_t = models::Identifier::new("new_vertex_type").unwrap();
+        let vertex = models::Vertex::new(old_t.clone());
+
+        let mut batch = WriteBatch::default();
+        vertex_manager.create(&mut batch, &vertex).unwrap();
+        db.write(batch).unwrap();
+
+        let mut batch = WriteBatch::default();
+        let duplicate = models::Vertex::with_id(vertex.id, new_t.clone());
+        assert!(matches!(
+            vertex_manager.create(&mut batch, &duplicate),
+            Err(Error::UuidTaken)
+        ));
+        assert!(batch.is_empty());
+        assert_eq!(vertex_manager.get(vertex.id).unwrap(), Some(old_t));
+
+        // Overwriting has to be asked for explicitly
+        vertex_manager.upsert(&mut batch, &duplicate).unwrap();
+        db.write(batch).unwrap();
+        assert_eq!(vertex_manager.get(vertex.id).unwrap(), Some(new_t));
+    }
+
+    #[test]
+    fn should_get_vertex_type_bytes() {
+        let test_db = open_db();
+        let (db, db_ref) = (&test_db.db, test_db.db_ref());
+        let vertex_manager = VertexManager::new(db_ref);
+        let vertex = models::Vertex::new(models::Identifier::new("test_vertex_type").unwrap());
+
+        let mut batch = WriteBatch::default();
+        vertex_manager.create(&mut batch, &vertex).unwrap();
+        db.write(batch).unwrap();
+
+        let type_bytes = vertex_manager.get_type_bytes(vertex.id).unwrap().unwrap();
+        assert_eq!(
+            &*type_bytes,
+            &util::build(&[util::Component::Identifier(&vertex.t)])[..]
+        );
+        let mut cursor = Cursor::new(&*type_bytes);
+        assert_eq!(
+            Some(util::read_identifier(&mut cursor)),
+            vertex_manager.get(vertex.id).unwrap()
+        );
+
+        let missing_id = util::generate_uuid_v1();
+        assert!(vertex_manager.get_type_bytes(missing_id).unwrap().is_none());
+        assert!(!vertex_manager.exists(missing_id).unwrap());
+    }
+
+    #[test]
+    fn should_iterate_for_window() {
+        let test_db = open_db();
+        let (db, db_ref) = (&test_db.db, test_db.db_ref());
+        let edge_manager = EdgeManager::new(db_ref);
+        let (t1, t2) = (
+            models::Identifier::new("test_edge_type_1").unwrap(),
+            models::Identifier::new("test_edge_type_2").unwrap(),
+        );
+        let out_id = util::generate_uuid_v1();
+        let in_ids: Vec<_> = (0..5).map(|_| util::generate_uuid_v1()).collect();
+        let low = Utc::now();
+        let high = low + Duration::seconds(10);
+
+        // Before, on the low boundary, inside, on the high boundary, and
+        // after the window, alternating between types
+        let datetimes = [
+            low - Duration::seconds(1),
+            low,
+            low + Duration::seconds(5),
+            high,
+            high + Duration::seconds(1),
+        ];
+        let mut batch = WriteBatch::default();
+        for (i, (in_id, update_datetime)) in in_ids.iter().zip(datetimes).enumerate() {
+            let t = if i % 2 == 0 { &t1 } else { &t2 };
+            edge_manager
+                .set(&mut batch, out_id, t, *in_id, update_datetime)
+                .unwrap();
+        }
+        db.write(batch).unwrap();
+
+        let edge_range_manager = EdgeRangeManager::new(db_ref);
+        let in_window: HashSet<_> = edge_range_manager
+            .iterate_for_window(out_id, low, high, None)
+            .unwrap()
+            .map(|item| item.unwrap().3)
+            .collect();
+        assert_eq!(in_window, in_ids[1..4].iter().copied().collect());
+
+        let in_window: Vec<_> = edge_range_manager
+            .iterate_for_window(out_id, low, high, Some(&t1))
+            .unwrap()
+            .map(|item| item.unwrap().3)
+            .collect();
+        assert_eq!(in_window, vec![in_ids[2]]);
+
+        let reversed_edge_range_manager = EdgeRangeManager::new_reversed(db_ref);
+        let in_window: Vec<_> = reversed_edge_range_manager
+            .iterate_for_window(in_ids[3], low, high, Some(&t2))
+            .unwrap()
+            .map(|item| item.unwrap().3)
+            .collect();
+        assert_eq!(in_window, vec![out_id]);
+        assert_eq!(
+            reversed_edge_range_manager
+                .iterate_for_window(in_ids[4], low, high, None)
+                .unwrap()
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn should_delete_edges_by_type() {
+        let test_db = open_db();
+        let (db, db_ref) = (&test_db.db, test_db.db_ref());
+        let edge_manager = EdgeManager::new(db_ref);
+        let (blocked_t, follows_t) = (
+            models::Identifier::new("blocked").unwrap(),
+            models::Identifier::new("follows").unwrap(),
+        );
+        let out_id = util::generate_uuid_v1();
+        let in_ids: Vec<_> = (0..3).map(|_| util::generate_uuid_v1()).collect();
+
+        let mut batch = WriteBatch::default();
+        for in_id in &in_ids {
+            edge_manager
+                .set(&mut batch, out_id, &blocked_t, *in_id, Utc::now())
+                .unwrap();
+            edge_manager
+                .set(&mut batch, out_id, &follows_t, *in_id, Utc::now())
+                .unwrap();
+        }
+        db.write(batch).unwrap();
+
+        let mut batch = WriteBatch::default();
+        assert_eq!(edge_manager.delete_by_type(&mut batch, out_id, &blocked_t).unwrap(), 3);
+        db.write(batch).unwrap();
+
+        let edge_range_manager = EdgeRangeManager::new(db_ref);
+        let remaining: Vec<_> = edge_range_manager
+            .iterate_for_range(out_id, None, None)
+            .unwrap()
+            .map(|item| item.unwrap().1)
+            .collect();
+        assert_eq!(remaining, vec![follows_t.clone(); 3]);
+
+        let reversed_edge_range_manager = EdgeRangeManager::new_reversed(db_ref);
+        for in_id in in_ids {
+            assert!(edge_manager.get(out_id, &blocked_t, in_id).unwrap().is_none());
+            assert!(edge_manager.get(out_id, &follows_t, in_id).unwrap().is_some());
+            let remaining: Vec<_> = reversed_edge_range_manager
+                .iterate_for_range(in_id, None, None)
+                .unwrap()
+                .map(|item| item.unwrap().1)
+                .collect();
+            assert_eq!(remaining, vec![follows_t.clone()]);
+        }
+    }
+
+    #[test]
+    fn should_stop_scanning_edges_at_the_limit() {
+        let mut opts = Options::default();
+        opts.enable_statistics();
+        let test_db = open_db_with_options(opts.clone());
+        let (db, db_ref) = (&test_db.db, test_db.db_ref());
+        let edge_manager = EdgeManager::new(db_ref);
+        let edge_range_manager = EdgeRangeManager::new(db_ref);
+        let t = models::Identifier::new("test_edge_type").unwrap();
+        let hub = util::generate_uuid_v1();
+
+        let mut batch = WriteBatch::default();
+        for _ in 0..100 {
+            edge_manager
+                .set(&mut batch, hub, &t, util::generate_uuid_v1(), Utc::now())
+                .unwrap();
+        }
+        db.write(batch).unwrap();
+
+        let nexts_before = get_ticker(&opts, "rocksdb.number.db.next");
+        assert!(edge_range_manager.has_at_least_edges(hub, 5).unwrap());
+        let nexts = get_ticker(&opts, "rocksdb.number.db.next") - nexts_before;
+        assert!(nexts <= 5, "stepped the iterator {} times", nexts);
+
+        assert!(edge_range_manager.has_at_least_edges(hub, 100).unwrap());
+        assert!(!edge_range_manager.has_at_least_edges(hub, 101).unwrap());
+        assert!(edge_range_manager.has_at_least_edges(hub, 0).unwrap());
+        assert!(!edge_range_manager
+            .has_at_least_edges(util::generate_uuid_v1(), 1)
+            .unwrap());
+    }
+
+    #[test]
+    fn should_maintain_degree_counters() {
+        let test_db = open_db().config(RocksdbConfig::default().degree_counters());
+        let (db, db_ref) = (&test_db.db, test_db.db_ref());
+        let edge_manager = EdgeManager::new(db_ref);
+        let vertex_manager = VertexManager::new(db_ref);
+        let t = models::Identifier::new("test_edge_type").unwrap();
+        let hub = models::Vertex::new(models::Identifier::new("test_vertex_type").unwrap());
+        let spokes: Vec<_> = (0..3).map(|_| util::generate_uuid_v1()).collect();
+
+        let mut batch = WriteBatch::default();
+        vertex_manager.create(&mut batch, &hub).unwrap();
+        for spoke in &spokes {
+            edge_manager.set(&mut batch, hub.id, &t, *spoke, Utc::now()).unwrap();
+        }
+        edge_manager.set(&mut batch, spokes[0], &t, hub.id, Utc::now()).unwrap();
+        edge_manager.set(&mut batch, hub.id, &t, hub.id, Utc::now()).unwrap();
+        db.write(batch).unwrap();
+        assert_eq!(edge_manager.out_degree(hub.id).unwrap(), 4);
+        assert_eq!(edge_manager.in_degree(hub.id).unwrap(), 2);
+        assert_eq!(edge_manager.in_degree(spokes[0]).unwrap(), 1);
+
+        // Overwriting an edge doesn't change the counts
+        let mut batch = WriteBatch::default();
+        edge_manager.set(&mut batch, hub.id, &t, spokes[1], Utc::now()).unwrap();
+        db.write(batch).unwrap();
+        assert_eq!(edge_manager.out_degree(hub.id).unwrap(), 4);
+        assert_eq!(edge_manager.in_degree(spokes[1]).unwrap(), 1);
+
+        let update_datetime = edge_manager.get(hub.id, &t, spokes[1]).unwrap().unwrap();
+        let mut batch = WriteBatch::default();
+        edge_manager
+            .delete(&mut batch, hub.id, &t, spokes[1], update_datetime)
+            .unwrap();
+        db.write(batch).unwrap();
+        assert_eq!(edge_manager.out_degree(hub.id).unwrap(), 3);
+        assert_eq!(edge_manager.in_degree(spokes[1]).unwrap(), 0);
+
+        // Deleting the vertex removes its counters, and its edges from the
+        // counters of the other end
+        let mut batch = WriteBatch::default();
+        vertex_manager.delete(&mut batch, hub.id, &mut HashSet::new()).unwrap();
+        db.write(batch).unwrap();
+        assert_eq!(edge_manager.out_degree(hub.id).unwrap(), 0);
+        assert_eq!(edge_manager.in_degree(hub.id).unwrap(), 0);
+        assert_eq!(edge_manager.in_degree(spokes[0]).unwrap(), 0);
+        assert_eq!(edge_manager.out_degree(spokes[0]).unwrap(), 0);
+        assert_eq!(edge_manager.in_degree(spokes[2]).unwrap(), 0);
+    }
+
+    #[test]
+    fn should_not_reuse_edge_seqs_after_a_restart() {
+        let test_db = open_db();
+        let db = &test_db.db;
+
+        let edge_seq = EdgeSeq::new(MetadataManager::new(db).get_edge_seq().unwrap());
+        let before: Vec<u64> = (0..5).map(|_| edge_seq.next(db).unwrap()).collect();
+        assert_eq!(before, vec![1, 2, 3, 4, 5]);
+
+        // Nothing past the reservation is handed out without persisting it
+        let edge_seq = EdgeSeq::new(MetadataManager::new(db).get_edge_seq().unwrap());
+        let after = edge_seq.next(db).unwrap();
+        assert!(after > 5);
+        assert!(MetadataManager::new(db).get_edge_seq().unwrap() >= after);
+    }
+
+    #[test]
+    fn should_order_edges_with_the_same_datetime_by_sequence() {
+        let test_db = open_db().config(RocksdbConfig::default().edge_sequence_numbers());
+        let (db, db_ref) = (&test_db.db, test_db.db_ref());
+        let edge_manager = EdgeManager::new(db_ref);
+        let edge_range_manager = EdgeRangeManager::new(db_ref);
+        let t = models::Identifier::new("test_edge_type").unwrap();
+        let hub_id = util::generate_uuid_v1();
+        let now = Utc::now();
+
+        // IDs that sort in the opposite order of insertion
+        let spoke_ids: Vec<Uuid> = (0..100).map(|i| Uuid::from_u128(u128::MAX - i)).rev().collect();
+        let mut batch = WriteBatch::default();
+        for spoke_id in &spoke_ids {
+            edge_manager.set(&mut batch, hub_id, &t, *spoke_id, now).unwrap();
+        }
+        db.write(batch).unwrap();
+
+        let listed = |t: Option<&models::Identifier>| -> Vec<Uuid> {
+            edge_range_manager
+                .iterate_for_range(hub_id, t, None)
+                .unwrap()
+                .map(|item| item.unwrap().3)
+                .collect()
+        };
+        let newest_first: Vec<Uuid> = spoke_ids.iter().rev().copied().collect();
+        assert_eq!(listed(Some(&t)), newest_first);
+        assert_eq!(listed(None), newest_first);
+        assert!(MetadataManager::new(db).get_edge_seq().unwrap() >= 100);
+
+        // Rewriting an edge at the same datetime moves it to the front, but
+        // touching one doesn't, since its datetime doesn't change
+        let mut batch = WriteBatch::default();
+        assert!(edge_manager.touch(&mut batch, hub_id, &t, spoke_ids[0], now).unwrap());
+        assert!(!edge_manager.upsert(&mut batch, hub_id, &t, spoke_ids[1], now).unwrap());
+        db.write(batch).unwrap();
+        let listed_after_rewrite = listed(Some(&t));
+        assert_eq!(listed_after_rewrite[0], spoke_ids[1]);
+        assert_eq!(listed_after_rewrite[99], spoke_ids[0]);
+
+        let mut batch = WriteBatch::default();
+        for spoke_id in &spoke_ids {
+            edge_manager.delete(&mut batch, hub_id, &t, *spoke_id, now).unwrap();
+        }
+        db.write(batch).unwrap();
+        assert!(listed(None).is_empty());
+        let reversed_edge_range_manager = EdgeRangeManager::new_reversed(db_ref);
+        assert_eq!(reversed_edge_range_manager.iterate_for_all().count(), 0);
+    }
+
+    #[test]
+    fn should_not_rewrite_edges_within_the_time_resolution() {
+        let test_db =
+            open_db().config(RocksdbConfig::default().edge_time_resolution(std::time::Duration::from_secs(60)));
+        let (db, db_ref) = (&test_db.db, test_db.db_ref());
+        let edge_manager = EdgeManager::new(db_ref);
+        let edge_range_manager = EdgeRangeManager::new(db_ref);
+        let t = models::Identifier::new("test_edge_type").unwrap();
+        let (out_id, in_id) = (util::generate_uuid_v1(), util::generate_uuid_v1());
+        let bucket_start = Utc.ymd(2021, 6, 1).and_hms(12, 30, 0);
+
+        let mut batch = WriteBatch::default();
+        assert!(edge_manager
+            .upsert(&mut batch, out_id, &t, in_id, bucket_start + Duration::seconds(5))
+            .unwrap());
+        db.write(batch).unwrap();
+        assert_eq!(edge_manager.get(out_id, &t, in_id).unwrap(), Some(bucket_start));
+
+        // Writes within the same minute don't touch the range entries
+        for seconds in [10, 30, 59] {
+            let update_datetime = bucket_start + Duration::seconds(seconds);
+            let mut batch = WriteBatch::default();
+            edge_manager
+                .set(&mut batch, out_id, &t, in_id, update_datetime)
+                .unwrap();
+            assert!(edge_manager
+                .touch(&mut batch, out_id, &t, in_id, update_datetime)
+                .unwrap());
+            assert!(batch.is_empty());
+        }
+
+        let mut batch = WriteBatch::default();
+        edge_manager
+            .set(&mut batch, out_id, &t, in_id, bucket_start + Duration::seconds(61))
+            .unwrap();
+        assert!(!batch.is_empty());
+        db.write(batch).unwrap();
+        let ranges: Vec<_> = edge_range_manager
+            .iterate_for_range(out_id, Some(&t), None)
+            .unwrap()
+            .map(|item| item.unwrap())
+            .collect();
+        assert_eq!(ranges, vec![(out_id, t, bucket_start + Duration::minutes(1), in_id)]);
+    }
+
+    #[test]
+    fn should_delete_edge_by_triple() {
+        let test_db = open_db();
+        let (db, db_ref) = (&test_db.db, test_db.db_ref());
+        let edge_manager = EdgeManager::new(db_ref);
+        let edge_property_manager = EdgePropertyManager::new(db_ref);
+        let t = models::Identifier::new("test_edge_type").unwrap();
+        let name = models::Identifier::new("test_property").unwrap();
+        let out_id = util::generate_uuid_v1();
+        let in_id = util::generate_uuid_v1();
+
+        // The datetime is never kept, so the caller couldn't pass it
+        let mut batch = WriteBatch::default();
+        edge_manager.set(&mut batch, out_id, &t, in_id, Utc::now()).unwrap();
+        edge_property_manager
+            .set(
+                &mut batch,
+                out_id,
+                &t,
+                in_id,
+                &name,
+                &models::Json::new(serde_json::json!(true)),
+            )
+            .unwrap();
+        db.write(batch).unwrap();
+
+        let mut batch = WriteBatch::default();
+        assert!(edge_manager.delete_by_triple(&mut batch, out_id, &t, in_id).unwrap());
+        db.write(batch).unwrap();
+        assert_eq!(edge_manager.get(out_id, &t, in_id).unwrap(), None);
+        assert_eq!(EdgeRangeManager::new(db_ref).iterate_for_all().count(), 0);
+        assert_eq!(EdgeRangeManager::new_reversed(db_ref).iterate_for_all().count(), 0);
+        assert!(edge_property_manager.get(out_id, &t, in_id, &name).unwrap().is_none());
+
+        let mut batch = WriteBatch::default();
+        assert!(!edge_manager.delete_by_triple(&mut batch, out_id, &t, in_id).unwrap());
+        assert!(batch.is_empty());
+    }
+
+    #[test]
+    fn should_count_distinct_neighbors() {
+        let test_db = open_db();
+        let (db, db_ref) = (&test_db.db, test_db.db_ref());
+        let edge_manager = EdgeManager::new(db_ref);
+        let friend_t = models::Identifier::new("friend").unwrap();
+        let coworker_t = models::Identifier::new("coworker").unwrap();
+        let a = util::generate_uuid_v1();
+        let b = util::generate_uuid_v1();
+        let c = util::generate_uuid_v1();
+
+        let mut batch = WriteBatch::default();
+        edge_manager.set(&mut batch, a, &friend_t, b, Utc::now()).unwrap();
+        edge_manager.set(&mut batch, a, &coworker_t, b, Utc::now()).unwrap();
+        db.write(batch).unwrap();
+        assert_eq!(
+            edge_manager
+                .distinct_neighbors(a, models::EdgeDirection::Outbound)
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            edge_manager
+                .distinct_neighbors(b, models::EdgeDirection::Inbound)
+                .unwrap(),
+            1
+        );
+
+        let mut batch = WriteBatch::default();
+        edge_manager.set(&mut batch, a, &coworker_t, c, Utc::now()).unwrap();
+        db.write(batch).unwrap();
+        assert_eq!(
+            edge_manager
+                .distinct_neighbors(a, models::EdgeDirection::Outbound)
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            edge_manager
+                .distinct_neighbors(a, models::EdgeDirection::Inbound)
+                .unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn should_not_read_disabled_degree_counters() {
+        let test_db = open_db();
+        let db_ref = test_db.db_ref();
+        let edge_manager = EdgeManager::new(db_ref);
+        assert!(matches!(
+            edge_manager.out_degree(util::generate_uuid_v1()),
+            Err(Error::Unsupported)
+        ));
+    }
+
+    #[test]
+    fn should_set_many_edge_properties() {
+        let test_db = open_db();
+        let (db, db_ref) = (&test_db.db, test_db.db_ref());
+        let edge_property_manager = EdgePropertyManager::new(db_ref);
+        let t = models::Identifier::new("test_edge_type").unwrap();
+        let name = models::Identifier::new("weight").unwrap();
+        let out_id = util::generate_uuid_v1();
+
+        let items: Vec<_> = (0..1000)
+            .map(|i| {
+                (
+                    out_id,
+                    t.clone(),
+                    util::generate_uuid_v1(),
+                    name.clone(),
+                    models::Json::new(serde_json::json!(i)),
+                )
+            })
+            .collect();
+        let mut batch = WriteBatch::default();
+        edge_property_manager.set_many(&mut batch, &items).unwrap();
+        db.write(batch).unwrap();
+
+        for (out_id, t, in_id, name, value) in items.iter().step_by(97) {
+            assert_eq!(
+                edge_property_manager.get(*out_id, t, *in_id, name).unwrap().as_ref(),
+                Some(value)
+            );
+        }
+    }
+
+    #[test]
+    fn should_get_distinct_property_names() {
+        let test_db = open_db();
+        let (db, db_ref) = (&test_db.db, test_db.db_ref());
+        let vertex_property_manager = VertexPropertyManager::new(db_ref);
+        let edge_property_manager = EdgePropertyManager::new(db_ref);
+        let t = models::Identifier::new("test_edge_type").unwrap();
+        let names: Vec<models::Identifier> = ["age", "name", "weight"]
+            .iter()
+            .map(|name| models::Identifier::new(*name).unwrap())
+            .collect();
+        let value = models::Json::new(serde_json::json!(true));
+
+        let mut batch = WriteBatch::default();
+        for i in 0..10 {
+            let (out_id, in_id) = (util::generate_uuid_v1(), util::generate_uuid_v1());
+            // Every owner gets an overlapping subset of the names
+            let vertex_names = if i % 2 == 0 { &names[..2] } else { &names[1..2] };
+            for name in vertex_names {
+                vertex_property_manager.set(&mut batch, out_id, name, &value).unwrap();
+            }
+            edge_property_manager
+                .set(&mut batch, out_id, &t, in_id, &names[2], &value)
+                .unwrap();
+            edge_property_manager
+                .set(&mut batch, out_id, &t, in_id, &names[1], &value)
+                .unwrap();
+        }
+        db.write(batch).unwrap();
+
+        assert_eq!(vertex_property_manager.distinct_names().unwrap(), names[..2].to_vec());
+        assert_eq!(edge_property_manager.distinct_names().unwrap(), names[1..].to_vec());
+    }
+
+    #[test]
+    fn should_delete_all_edge_properties_for_owner() {
+        let test_db = open_db().indexed_properties(HashSet::from([models::Identifier::new("property_0").unwrap()]));
+        let (db, db_ref) = (&test_db.db, test_db.db_ref());
+        let edge_property_manager = EdgePropertyManager::new(db_ref);
+        let edge_property_value_manager = EdgePropertyValueManager::new(db_ref);
+        let t = models::Identifier::new("test_edge_type").unwrap();
+        let value = models::Json::new(serde_json::json!("foo"));
+        let (out_id, in_id, other_in_id) = (
+            util::generate_uuid_v1(),
+            util::generate_uuid_v1(),
+            util::generate_uuid_v1(),
+        );
+
+        let mut batch = WriteBatch::default();
+        for i in 0..50 {
+            let name = models::Identifier::new(format!("property_{}", i)).unwrap();
+            edge_property_manager
+                .set(&mut batch, out_id, &t, in_id, &name, &value)
+                .unwrap();
+            edge_property_manager
+                .set(&mut batch, out_id, &t, other_in_id, &name, &value)
+                .unwrap();
+        }
+        db.write(batch).unwrap();
+
+        let mut batch = WriteBatch::default();
+        assert_eq!(
+            edge_property_manager
+                .delete_all_for_owner(&mut batch, out_id, &t, in_id)
+                .unwrap(),
+            50
+        );
+        db.write(batch).unwrap();
+
+        assert_eq!(
+            edge_property_manager
+                .iterate_for_owner(out_id, &t, in_id)
+                .unwrap()
+                .count(),
+            0
+        );
+        assert_eq!(
+            edge_property_manager
+                .iterate_for_owner(out_id, &t, other_in_id)
+                .unwrap()
+                .count(),
+            50
+        );
+        let name = models::Identifier::new("property_0").unwrap();
+        let indexed: Vec<_> = edge_property_value_manager
+            .iterate_for_name(&name)
+            .map(|(_, _, key)| key)
+            .collect();
+        assert_eq!(indexed, vec![(out_id, t.clone(), other_in_id)]);
+    }
+
+    #[test]
+    fn should_serve_repeated_reads_from_property_cache() {
+        let test_db = open_db();
+        let (db, db_ref) = (&test_db.db, test_db.db_ref());
+        let cache = PropertyCache::new(16);
+        let manager = CachedVertexPropertyManager::new(db_ref, &cache);
+        let name = models::Identifier::new("test_property").unwrap();
+        let id = util::generate_uuid_v1();
+        let value = models::Json::new(serde_json::json!({"a": [1, 2, 3]}));
+
+        let mut batch = WriteBatch::default();
+        manager.set(&mut batch, id, &name, &value).unwrap();
+        db.write(batch).unwrap();
+
+        assert_eq!(manager.get(id, &name).unwrap(), Some(value.clone()));
+        assert_eq!(cache.hits(), 0);
+        assert_eq!(manager.get(id, &name).unwrap(), Some(value.clone()));
+        assert_eq!(cache.hits(), 1);
+
+        // Writes that bypass the cache are still seen
+        let new_value = models::Json::new(serde_json::json!("foo"));
+        let mut batch = WriteBatch::default();
+        VertexPropertyManager::new(db_ref)
+            .set(&mut batch, id, &name, &new_value)
+            .unwrap();
+        db.write(batch).unwrap();
+        assert_eq!(manager.get(id, &name).unwrap(), Some(new_value));
+        assert_eq!(cache.hits(), 1);
+
+        let mut batch = WriteBatch::default();
+        manager.delete(&mut batch, id, &name).unwrap();
+        db.write(batch).unwrap();
+        assert_eq!(manager.get(id, &name).unwrap(), None);
+    }
+
+    #[test]
+    fn should_check_existence_in_bulk() {
+        let test_db = open_db();
+        let (db, db_ref) = (&test_db.db, test_db.db_ref());
+        let vertex_manager = VertexManager::new(db_ref);
+        let edge_manager = EdgeManager::new(db_ref);
+        let t = models::Identifier::new("test_type").unwrap();
+        let vertices: Vec<_> = (0..4).map(|_| models::Vertex::new(t.clone())).collect();
+        let absent_ids: Vec<_> = (0..4).map(|_| util::generate_uuid_v1()).collect();
+
+        let mut batch = WriteBatch::default();
+        for vertex in &vertices {
+            vertex_manager.create(&mut batch, vertex).unwrap();
+        }
+        edge_manager
+            .set(&mut batch, vertices[0].id, &t, vertices[1].id, Utc::now())
+            .unwrap();
+        db.write(batch).unwrap();
+
+        // Interleave present and absent IDs, in reverse key order
+        let ids = vec![
+            absent_ids[3],
+            vertices[3].id,
+            absent_ids[0],
+            vertices[0].id,
+            vertices[0].id,
+            absent_ids[1],
+        ];
+        assert_eq!(
+            vertex_manager.exists_bulk(&ids).unwrap(),
+            vec![false, true, false, true, true, false]
+        );
+        assert!(vertex_manager.exists_bulk(&[]).unwrap().is_empty());
+
+        let other_t = models::Identifier::new("test_other_type").unwrap();
+        let keys = vec![
+            (vertices[1].id, t.clone(), vertices[0].id),
+            (vertices[0].id, t.clone(), vertices[1].id),
+            (vertices[0].id, other_t, vertices[1].id),
+            (vertices[0].id, t.clone(), absent_ids[2]),
+        ];
+        assert_eq!(
+            edge_manager.exists_bulk(&keys).unwrap(),
+            vec![false, true, false, false]
+        );
+    }
+
+    #[test]
+    fn should_retype_edge() {
+        let test_db = open_db();
+        let (db, db_ref) = (&test_db.db, test_db.db_ref());
+        let edge_manager = EdgeManager::new(db_ref);
+        let edge_property_manager = EdgePropertyManager::new(db_ref);
+        let (pending_t, confirmed_t) = (
+            models::Identifier::new("pending").unwrap(),
+            models::Identifier::new("confirmed").unwrap(),
+        );
+        let name = models::Identifier::new("test_property").unwrap();
+        let value = models::Json::new(serde_json::json!("foo"));
+        let (out_id, in_id) = (util::generate_uuid_v1(), util::generate_uuid_v1());
+        let update_datetime = Utc::now() - Duration::hours(1);
+
+        let mut batch = WriteBatch::default();
+        assert!(!edge_manager
+            .retype(&mut batch, out_id, &pending_t, in_id, &confirmed_t)
+            .unwrap());
+        edge_manager
+            .set(&mut batch, out_id, &pending_t, in_id, update_datetime)
+            .unwrap();
+        edge_property_manager
+            .set(&mut batch, out_id, &pending_t, in_id, &name, &value)
+            .unwrap();
+        db.write(batch).unwrap();
+
+        let mut batch = WriteBatch::default();
+        assert!(edge_manager
+            .retype(&mut batch, out_id, &pending_t, in_id, &confirmed_t)
+            .unwrap());
+        db.write(batch).unwrap();
+
+        assert_eq!(edge_manager.get(out_id, &pending_t, in_id).unwrap(), None);
+        assert_eq!(
+            edge_manager.get(out_id, &confirmed_t, in_id).unwrap(),
+            Some(update_datetime)
+        );
+        assert_eq!(
+            edge_property_manager.get(out_id, &pending_t, in_id, &name).unwrap(),
+            None
+        );
+        assert_eq!(
+            edge_property_manager.get(out_id, &confirmed_t, in_id, &name).unwrap(),
+            Some(value)
+        );
+
+        let edge_range_manager = EdgeRangeManager::new(db_ref);
+        let ranges: Vec<_> = edge_range_manager
+            .iterate_for_range(out_id, None, None)
+            .unwrap()
+            .map(|item| item.unwrap())
+            .collect();
+        assert_eq!(ranges, vec![(out_id, confirmed_t.clone(), update_datetime, in_id)]);
+        let reversed_edge_range_manager = EdgeRangeManager::new_reversed(db_ref);
+        let ranges: Vec<_> = reversed_edge_range_manager
+            .iterate_for_range(in_id, None, None)
+            .unwrap()
+            .map(|item| item.unwrap())
+            .collect();
+        assert_eq!(ranges, vec![(in_id, confirmed_t, update_datetime, out_id)]);
+    }
+
+    #[test]
+    fn should_iterate_for_owner_prefixed() {
+        let test_db = open_db();
+        let (db, db_ref) = (&test_db.db, test_db.db_ref());
+        let vertex_property_manager = VertexPropertyManager::new(db_ref);
+        let (id, other_id) = (util::generate_uuid_v1(), util::generate_uuid_v1());
+        let value = models::Json::new(serde_json::json!(true));
+
+        let mut batch = WriteBatch::default();
+        for name in ["meta_created_by", "meta_updated_by", "meta", "metadata", "name"] {
+            let name = models::Identifier::new(name).unwrap();
+            vertex_property_manager.set(&mut batch, id, &name, &value).unwrap();
+        }
+        let name = models::Identifier::new("meta_created_by").unwrap();
+        vertex_property_manager
+            .set(&mut batch, other_id, &name, &value)
+            .unwrap();
+        db.write(batch).unwrap();
+
+        let names: Vec<_> = vertex_property_manager
+            .iterate_for_owner_prefixed(id, "meta_")
+            .unwrap()
+            .map(|item| item.unwrap().0 .1 .0)
+            .collect();
+        assert_eq!(names, vec!["meta_created_by", "meta_updated_by"]);
+        assert_eq!(
+            vertex_property_manager
+                .iterate_for_owner_prefixed(id, "")
+                .unwrap()
+                .count(),
+            5
+        );
+    }
+
+    #[test]
+    fn should_page_with_iterate_for_range_after() {
+        let test_db = open_db();
+        let (db, db_ref) = (&test_db.db, test_db.db_ref());
+        let vertex_manager = VertexManager::new(db_ref);
+        let t = models::Identifier::new("test_vertex_type").unwrap();
+
+        let mut batch = WriteBatch::default();
+        for i in 1..=10 {
+            let vertex = models::Vertex::with_id(Uuid::from_u128(i), t.clone());
+            vertex_manager.create(&mut batch, &vertex).unwrap();
+        }
+        db.write(batch).unwrap();
+
+        let mut seen = Vec::new();
+        let mut page: Vec<_> = vertex_manager
+            .iterate_for_range(Uuid::default())
+            .take(3)
+            .map(|item| item.unwrap().0)
+            .collect();
+        while let Some(cursor) = page.last().copied() {
+            seen.extend(page);
+            page = vertex_manager
+                .iterate_for_range_after(cursor)
+                .take(3)
+                .map(|item| item.unwrap().0)
+                .collect();
+        }
+        let expected: Vec<_> = (1..=10).map(Uuid::from_u128).collect();
+        assert_eq!(seen, expected);
+
+        // The starting ID doesn't have to exist
+        let after: Vec<_> = vertex_manager.iterate_for_range_after(Uuid::from_u128(11)).collect();
+        assert!(after.is_empty());
+        let ids: Vec<_> = vertex_manager
+            .iterate_for_range_after(Uuid::from_u128(u128::MAX))
+            .collect();
+        assert!(ids.is_empty());
+    }
+
+    #[test]
+    fn should_create_vertices_from_stream() {
+        let test_db = open_db();
+        let db_ref = test_db.db_ref();
+        let vertex_manager = VertexManager::new(db_ref);
+        let t = models::Identifier::new("test_vertex_type").unwrap();
+
+        let vertices = (1..=50_000).map(|i| models::Vertex::with_id(Uuid::from_u128(i), t.clone()));
+        let mut writes = Vec::new();
+        let count = vertex_manager
+            .create_stream(vertices, 128, |written| writes.push(written.len()))
+            .unwrap();
+        assert_eq!(count, 50_000);
+        assert_eq!(writes.len(), 391);
+        assert!(writes[..390].iter().all(|len| *len == 128));
+        assert_eq!(writes[390], 80);
+
+        let ids: Vec<Uuid> = vertex_manager
+            .iterate_for_range(Uuid::default())
+            .map(|item| item.unwrap().0)
+            .collect();
+        let expected: Vec<Uuid> = (1..=50_000).map(Uuid::from_u128).collect();
+        assert_eq!(ids, expected);
+    }
+}
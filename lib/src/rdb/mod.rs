@@ -1,9 +1,14 @@
 //! The rocksdb datastore implementation.
 
+mod cache;
+mod compress;
 mod datastore;
 mod managers;
 
-pub use self::datastore::RocksdbDatastore;
+pub use self::datastore::{
+    ChangeEvent, DeleteCounts, DeletePlan, IntegrityReport, MaintenanceReport, RecompressStats, RocksdbBatch,
+    RocksdbConfig, RocksdbDatastore,
+};
 
 #[cfg(feature = "bench-suite")]
 full_bench_impl!({
@@ -35,3 +40,1780 @@ fn should_repair() {
     // Now try to repair
     RocksdbDatastore::repair(dir.path(), Some(1)).unwrap();
 }
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_touch_edge() {
+    use super::RocksdbDatastore;
+    use crate::{
+        Datastore, EdgeDirection, EdgeKey, Identifier, SpecificEdgeQuery, SpecificVertexQuery, Vertex, VertexQueryExt,
+    };
+    use tempfile::tempdir;
+    use uuid::Uuid;
+
+    let dir = tempdir().unwrap();
+    let datastore = RocksdbDatastore::new(dir.path(), Some(1)).unwrap();
+
+    let vertex_t = Identifier::new("test_vertex_type").unwrap();
+    let outbound_v = Vertex::new(vertex_t.clone());
+    let first_inbound_v = Vertex::new(vertex_t.clone());
+    let second_inbound_v = Vertex::new(vertex_t);
+    datastore.create_vertex(&outbound_v).unwrap();
+    datastore.create_vertex(&first_inbound_v).unwrap();
+    datastore.create_vertex(&second_inbound_v).unwrap();
+
+    let edge_t = Identifier::new("test_edge_type").unwrap();
+    let first_key = EdgeKey::new(outbound_v.id, edge_t.clone(), first_inbound_v.id);
+    let second_key = EdgeKey::new(outbound_v.id, edge_t.clone(), second_inbound_v.id);
+    datastore.create_edge(&first_key).unwrap();
+    datastore.create_edge(&second_key).unwrap();
+
+    let get_inbound_ids = || -> Vec<Uuid> {
+        datastore
+            .get_edges(SpecificVertexQuery::single(outbound_v.id).outbound().into())
+            .unwrap()
+            .into_iter()
+            .map(|e| e.key.inbound_id)
+            .collect()
+    };
+
+    // Edge ranges are ordered newest first
+    assert_eq!(get_inbound_ids(), vec![second_inbound_v.id, first_inbound_v.id]);
+    assert!(datastore.touch_edge(&first_key).unwrap());
+    assert_eq!(get_inbound_ids(), vec![first_inbound_v.id, second_inbound_v.id]);
+
+    // The reversed range entry should have moved along with the forward one
+    let touched = datastore
+        .get_edges(SpecificEdgeQuery::single(first_key).into())
+        .unwrap();
+    let reversed = datastore
+        .get_edges(SpecificVertexQuery::single(first_inbound_v.id).inbound().into())
+        .unwrap();
+    assert_eq!(reversed.len(), 1);
+    assert_eq!(reversed[0].created_datetime, touched[0].created_datetime);
+
+    let missing_key = EdgeKey::new(outbound_v.id, edge_t, Uuid::default());
+    assert!(!datastore.touch_edge(&missing_key).unwrap());
+    assert_eq!(
+        datastore
+            .get_edge_count(outbound_v.id, None, EdgeDirection::Outbound)
+            .unwrap(),
+        2
+    );
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_read_writes_from_other_threads() {
+    use super::RocksdbDatastore;
+    use crate::{Datastore, Identifier, SpecificVertexQuery, VertexQueryExt};
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use tempfile::tempdir;
+
+    let dir = tempdir().unwrap();
+    let datastore = Arc::new(RocksdbDatastore::new(dir.path(), Some(1)).unwrap());
+    let (sender, receiver) = mpsc::channel();
+
+    let writer = {
+        let datastore = datastore.clone();
+        thread::spawn(move || {
+            let t = Identifier::new("test_vertex_type").unwrap();
+            let name = Identifier::new("test_property").unwrap();
+            for i in 0..100 {
+                let id = datastore.create_vertex_from_type(t.clone()).unwrap();
+                let q = SpecificVertexQuery::single(id).property(name.clone());
+                datastore.set_vertex_properties(q, serde_json::json!(i)).unwrap();
+                sender.send((id, i)).unwrap();
+            }
+        })
+    };
+
+    // Each write should be visible as soon as the writer has returned from
+    // it, without any intermediate sync
+    let reader = thread::spawn(move || {
+        let name = Identifier::new("test_property").unwrap();
+        for (id, i) in receiver {
+            let q = SpecificVertexQuery::single(id).property(name.clone());
+            let properties = datastore.get_vertex_properties(q).unwrap();
+            assert_eq!(properties.len(), 1);
+            assert_eq!(properties[0].value, serde_json::json!(i));
+        }
+    });
+
+    writer.join().unwrap();
+    reader.join().unwrap();
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_delete_vertex_parallel() {
+    use super::RocksdbDatastore;
+    use crate::{
+        BulkInsertItem, Datastore, EdgeDirection, EdgeKey, EdgeQueryExt, Identifier, RangeVertexQuery,
+        SpecificEdgeQuery, SpecificVertexQuery, Vertex, VertexQueryExt,
+    };
+    use tempfile::tempdir;
+
+    let dir = tempdir().unwrap();
+    let datastore = RocksdbDatastore::new(dir.path(), Some(1)).unwrap();
+
+    let vertex_t = Identifier::new("test_vertex_type").unwrap();
+    let edge_t = Identifier::new("test_edge_type").unwrap();
+    let property_name = Identifier::new("test_property").unwrap();
+    let v = Vertex::new(vertex_t.clone());
+    let mut items = vec![
+        BulkInsertItem::Vertex(v.clone()),
+        BulkInsertItem::VertexProperty(v.id, property_name.clone(), serde_json::json!(true)),
+    ];
+    let mut keys = Vec::new();
+
+    for i in 0..10_000 {
+        let other_v = Vertex::new(vertex_t.clone());
+        let key = if i % 2 == 0 {
+            EdgeKey::new(v.id, edge_t.clone(), other_v.id)
+        } else {
+            EdgeKey::new(other_v.id, edge_t.clone(), v.id)
+        };
+        items.push(BulkInsertItem::Vertex(other_v));
+        items.push(BulkInsertItem::Edge(key.clone()));
+        items.push(BulkInsertItem::EdgeProperty(
+            key.clone(),
+            property_name.clone(),
+            serde_json::json!(i),
+        ));
+        keys.push(key);
+    }
+
+    datastore.bulk_insert(items).unwrap();
+    datastore.delete_vertex_parallel(v.id, 4).unwrap();
+
+    assert_eq!(
+        datastore
+            .get_vertices(SpecificVertexQuery::single(v.id).into())
+            .unwrap()
+            .len(),
+        0
+    );
+    assert_eq!(
+        datastore
+            .get_vertex_properties(SpecificVertexQuery::single(v.id).property(property_name.clone()))
+            .unwrap()
+            .len(),
+        0
+    );
+    assert_eq!(
+        datastore.get_edge_count(v.id, None, EdgeDirection::Outbound).unwrap(),
+        0
+    );
+    assert_eq!(datastore.get_edge_count(v.id, None, EdgeDirection::Inbound).unwrap(), 0);
+    assert_eq!(
+        datastore
+            .get_edges(SpecificEdgeQuery::new(keys.clone()).into())
+            .unwrap()
+            .len(),
+        0
+    );
+    assert_eq!(
+        datastore
+            .get_edge_properties(SpecificEdgeQuery::new(keys).property(property_name))
+            .unwrap()
+            .len(),
+        0
+    );
+
+    // The other ends of the edges should be untouched
+    assert_eq!(
+        datastore.get_vertices(RangeVertexQuery::new().into()).unwrap().len(),
+        10_000
+    );
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_delete_vertex_chunked() {
+    use super::RocksdbDatastore;
+    use crate::{
+        BulkInsertItem, Datastore, EdgeDirection, EdgeKey, EdgeQueryExt, Identifier, RangeVertexQuery,
+        SpecificEdgeQuery, SpecificVertexQuery, Vertex, VertexQueryExt,
+    };
+    use tempfile::tempdir;
+
+    let dir = tempdir().unwrap();
+    let datastore = RocksdbDatastore::new(dir.path(), Some(1)).unwrap();
+
+    let vertex_t = Identifier::new("test_vertex_type").unwrap();
+    let edge_t = Identifier::new("test_edge_type").unwrap();
+    let property_name = Identifier::new("test_property").unwrap();
+    let v = Vertex::new(vertex_t.clone());
+    let mut items = vec![
+        BulkInsertItem::Vertex(v.clone()),
+        BulkInsertItem::VertexProperty(v.id, property_name.clone(), serde_json::json!(true)),
+        BulkInsertItem::Edge(EdgeKey::new(v.id, edge_t.clone(), v.id)),
+    ];
+    let mut keys = vec![EdgeKey::new(v.id, edge_t.clone(), v.id)];
+
+    for i in 0..1_000 {
+        let other_v = Vertex::new(vertex_t.clone());
+        let key = if i % 2 == 0 {
+            EdgeKey::new(v.id, edge_t.clone(), other_v.id)
+        } else {
+            EdgeKey::new(other_v.id, edge_t.clone(), v.id)
+        };
+        items.push(BulkInsertItem::Vertex(other_v));
+        items.push(BulkInsertItem::Edge(key.clone()));
+        items.push(BulkInsertItem::EdgeProperty(
+            key.clone(),
+            property_name.clone(),
+            serde_json::json!(i),
+        ));
+        keys.push(key);
+    }
+
+    datastore.bulk_insert(items).unwrap();
+    datastore.delete_vertex_chunked(v.id, 16).unwrap();
+
+    assert_eq!(
+        datastore
+            .get_vertices(SpecificVertexQuery::single(v.id).into())
+            .unwrap()
+            .len(),
+        0
+    );
+    assert_eq!(
+        datastore
+            .get_vertex_properties(SpecificVertexQuery::single(v.id).property(property_name.clone()))
+            .unwrap()
+            .len(),
+        0
+    );
+    assert_eq!(
+        datastore.get_edge_count(v.id, None, EdgeDirection::Outbound).unwrap(),
+        0
+    );
+    assert_eq!(datastore.get_edge_count(v.id, None, EdgeDirection::Inbound).unwrap(), 0);
+    assert_eq!(
+        datastore
+            .get_edges(SpecificEdgeQuery::new(keys.clone()).into())
+            .unwrap()
+            .len(),
+        0
+    );
+    assert_eq!(
+        datastore
+            .get_edge_properties(SpecificEdgeQuery::new(keys).property(property_name))
+            .unwrap()
+            .len(),
+        0
+    );
+    assert!(datastore.check_integrity().unwrap().is_empty());
+    assert_eq!(
+        datastore.get_vertices(RangeVertexQuery::new().into()).unwrap().len(),
+        1_000
+    );
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_persist_manual_flush_after_sync() {
+    use super::{RocksdbConfig, RocksdbDatastore};
+    use crate::{BulkInsertItem, Datastore, Identifier, RangeVertexQuery, Vertex, VertexQueryExt};
+    use tempfile::tempdir;
+
+    let dir = tempdir().unwrap();
+    let t = Identifier::new("test_vertex_type").unwrap();
+    let name = Identifier::new("test_property").unwrap();
+
+    {
+        let datastore = RocksdbConfig::default()
+            .max_open_files(Some(1))
+            .manual_flush()
+            .open(dir.path())
+            .unwrap();
+        let mut items = Vec::new();
+        for i in 0..10_000 {
+            let vertex = Vertex::new(t.clone());
+            items.push(BulkInsertItem::VertexProperty(
+                vertex.id,
+                name.clone(),
+                serde_json::json!(i),
+            ));
+            items.push(BulkInsertItem::Vertex(vertex));
+        }
+        datastore.bulk_insert(items).unwrap();
+        datastore.sync().unwrap();
+    }
+
+    let datastore = RocksdbDatastore::new(dir.path(), Some(1)).unwrap();
+    assert_eq!(datastore.get_vertex_count().unwrap(), 10_000);
+    assert_eq!(
+        datastore
+            .get_vertex_properties(RangeVertexQuery::new().property(name))
+            .unwrap()
+            .len(),
+        10_000
+    );
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_flush_on_drop() {
+    use super::{RocksdbConfig, RocksdbDatastore};
+    use crate::{Datastore, Identifier, SpecificVertexQuery, VertexQueryExt};
+    use tempfile::tempdir;
+
+    let dir = tempdir().unwrap();
+    let name = Identifier::new("test_property").unwrap();
+
+    let id = {
+        // Skip the write-ahead log, so that nothing is persisted unless the
+        // datastore is flushed
+        let datastore = RocksdbConfig::default()
+            .max_open_files(Some(1))
+            .manual_flush()
+            .flush_on_drop(true)
+            .open(dir.path())
+            .unwrap();
+        let id = datastore
+            .create_vertex_from_type(Identifier::new("test_vertex_type").unwrap())
+            .unwrap();
+        let q = SpecificVertexQuery::single(id).property(name.clone());
+        datastore.set_vertex_properties(q, serde_json::json!(true)).unwrap();
+        id
+    };
+
+    let datastore = RocksdbDatastore::new(dir.path(), Some(1)).unwrap();
+    let properties = datastore
+        .get_vertex_properties(SpecificVertexQuery::single(id).property(name))
+        .unwrap();
+    assert_eq!(properties.len(), 1);
+    assert_eq!(properties[0].value, serde_json::json!(true));
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_subscribe_to_vertex_changes() {
+    use super::{ChangeEvent, RocksdbDatastore};
+    use crate::{Datastore, Identifier, SpecificVertexQuery, Vertex};
+    use tempfile::tempdir;
+
+    let dir = tempdir().unwrap();
+    let datastore = RocksdbDatastore::new(dir.path(), Some(1)).unwrap();
+    let t = Identifier::new("test_vertex_type").unwrap();
+    let first_v = Vertex::new(t.clone());
+    let second_v = Vertex::new(t);
+
+    let all_events = datastore.subscribe_vertices(None);
+    let second_v_events = datastore.subscribe_vertices(Some(second_v.id));
+
+    datastore.create_vertex(&first_v).unwrap();
+    datastore.create_vertex(&second_v).unwrap();
+    datastore
+        .delete_vertices(SpecificVertexQuery::single(first_v.id).into())
+        .unwrap();
+
+    // Dropping the datastore ends the subscriptions
+    drop(datastore);
+
+    assert_eq!(
+        all_events.collect::<Vec<ChangeEvent>>(),
+        vec![
+            ChangeEvent::Insert(first_v.clone()),
+            ChangeEvent::Insert(second_v.clone()),
+            ChangeEvent::Remove(first_v.id),
+        ]
+    );
+    assert_eq!(
+        second_v_events.collect::<Vec<ChangeEvent>>(),
+        vec![ChangeEvent::Insert(second_v)]
+    );
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_preview_vertex_delete() {
+    use super::RocksdbDatastore;
+    use crate::{
+        Datastore, EdgeKey, EdgeQueryExt, Identifier, RangeVertexQuery, SpecificEdgeQuery, SpecificVertexQuery, Vertex,
+        VertexQueryExt,
+    };
+    use tempfile::tempdir;
+    use uuid::Uuid;
+
+    let dir = tempdir().unwrap();
+    let datastore = RocksdbDatastore::new(dir.path(), Some(1)).unwrap();
+
+    let vertex_t = Identifier::new("test_vertex_type").unwrap();
+    let edge_t = Identifier::new("test_edge_type").unwrap();
+    let name = Identifier::new("test_property").unwrap();
+    let v = Vertex::new(vertex_t.clone());
+    let other_v = Vertex::new(vertex_t);
+    datastore.create_vertex(&v).unwrap();
+    datastore.create_vertex(&other_v).unwrap();
+    datastore
+        .set_vertex_properties(
+            SpecificVertexQuery::single(v.id).property(name.clone()),
+            serde_json::json!(1),
+        )
+        .unwrap();
+
+    let keys = vec![
+        EdgeKey::new(v.id, edge_t.clone(), other_v.id),
+        EdgeKey::new(other_v.id, edge_t.clone(), v.id),
+        EdgeKey::new(v.id, edge_t, v.id),
+    ];
+    for key in &keys {
+        datastore.create_edge(key).unwrap();
+    }
+    datastore
+        .set_edge_properties(
+            SpecificEdgeQuery::new(keys.clone()).property(name.clone()),
+            serde_json::json!(2),
+        )
+        .unwrap();
+
+    let count_vertex_properties = || {
+        datastore
+            .get_vertex_properties(RangeVertexQuery::new().property(name.clone()))
+            .unwrap()
+            .len()
+    };
+    let count_edges = || {
+        datastore
+            .get_edges(SpecificEdgeQuery::new(keys.clone()).into())
+            .unwrap()
+            .len()
+    };
+    let count_edge_properties = || {
+        datastore
+            .get_edge_properties(SpecificEdgeQuery::new(keys.clone()).property(name.clone()))
+            .unwrap()
+            .len()
+    };
+
+    let plan = datastore.preview_vertex_delete(v.id).unwrap().unwrap();
+    assert_eq!(plan.vertex, v);
+    assert_eq!(plan.vertex_properties, vec![name.clone()]);
+    assert_eq!(plan.edges.len(), 3);
+    assert_eq!(plan.edge_properties.len(), 3);
+
+    // Previewing shouldn't have changed anything
+    let (vertex_properties_before, edges_before, edge_properties_before) =
+        (count_vertex_properties(), count_edges(), count_edge_properties());
+    assert_eq!(
+        (vertex_properties_before, edges_before, edge_properties_before),
+        (1, 3, 3)
+    );
+
+    datastore
+        .delete_vertices(SpecificVertexQuery::single(v.id).into())
+        .unwrap();
+    assert_eq!(
+        vertex_properties_before - count_vertex_properties(),
+        plan.vertex_properties.len()
+    );
+    assert_eq!(edges_before - count_edges(), plan.edges.len());
+    assert_eq!(
+        edge_properties_before - count_edge_properties(),
+        plan.edge_properties.len()
+    );
+
+    assert_eq!(datastore.preview_vertex_delete(Uuid::default()).unwrap(), None);
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_enforce_max_property_bytes() {
+    use super::RocksdbConfig;
+    use crate::{
+        Datastore, EdgeKey, EdgeQueryExt, Error, Identifier, SpecificEdgeQuery, SpecificVertexQuery, Vertex,
+        VertexQueryExt,
+    };
+    use tempfile::tempdir;
+
+    let dir = tempdir().unwrap();
+    let datastore = RocksdbConfig::default()
+        .max_open_files(Some(1))
+        .max_property_bytes(Some(10))
+        .open(dir.path())
+        .unwrap();
+
+    let t = Identifier::new("test_type").unwrap();
+    let name = Identifier::new("test_property").unwrap();
+    let outbound_v = Vertex::new(t.clone());
+    let inbound_v = Vertex::new(t.clone());
+    datastore.create_vertex(&outbound_v).unwrap();
+    datastore.create_vertex(&inbound_v).unwrap();
+    let key = EdgeKey::new(outbound_v.id, t, inbound_v.id);
+    datastore.create_edge(&key).unwrap();
+
+    // Strings serialize with surrounding quotes, so these are exactly 10 and
+    // 11 bytes
+    let under = serde_json::json!("12345678");
+    let over = serde_json::json!("123456789");
+
+    let vertex_q = SpecificVertexQuery::single(outbound_v.id).property(name.clone());
+    datastore
+        .set_vertex_properties(vertex_q.clone(), under.clone())
+        .unwrap();
+    match datastore.set_vertex_properties(vertex_q.clone(), over.clone()) {
+        Err(Error::PropertyTooLarge) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert_eq!(datastore.get_vertex_properties(vertex_q).unwrap()[0].value, under);
+
+    let edge_q = SpecificEdgeQuery::single(key).property(name);
+    datastore.set_edge_properties(edge_q.clone(), under.clone()).unwrap();
+    match datastore.set_edge_properties(edge_q.clone(), over) {
+        Err(Error::PropertyTooLarge) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert_eq!(datastore.get_edge_properties(edge_q).unwrap()[0].value, under);
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_migrate_compression() {
+    use super::{RocksdbConfig, RocksdbDatastore};
+    use crate::{
+        Datastore, EdgeKey, Identifier, RangeVertexQuery, SpecificEdgeQuery, SpecificVertexQuery, VertexQueryExt,
+    };
+    use tempfile::tempdir;
+
+    let src_dir = tempdir().unwrap();
+    let dst_dir = tempdir().unwrap();
+    let t = Identifier::new("test_type").unwrap();
+    let name = Identifier::new("test_property").unwrap();
+
+    let (ids, key) = {
+        let datastore = RocksdbConfig::default()
+            .max_open_files(Some(1))
+            .compression(false)
+            .open(src_dir.path())
+            .unwrap();
+        let ids: Vec<_> = (0..10)
+            .map(|_| datastore.create_vertex_from_type(t.clone()).unwrap())
+            .collect();
+        let key = EdgeKey::new(ids[0], t.clone(), ids[1]);
+        datastore.create_edge(&key).unwrap();
+        let q = SpecificVertexQuery::new(ids.clone()).property(name.clone());
+        datastore.set_vertex_properties(q, serde_json::json!(true)).unwrap();
+        datastore.index_property(name.clone()).unwrap();
+        (ids, key)
+    };
+
+    RocksdbDatastore::migrate_compression(
+        src_dir.path(),
+        dst_dir.path(),
+        RocksdbConfig::default().max_open_files(Some(1)).compression(true),
+    )
+    .unwrap();
+
+    let src = RocksdbDatastore::new(src_dir.path(), Some(1)).unwrap();
+    let dst = RocksdbDatastore::new(dst_dir.path(), Some(1)).unwrap();
+    for datastore in [&src, &dst] {
+        let vertices = datastore.get_vertices(RangeVertexQuery::new().into()).unwrap();
+        assert_eq!(vertices.len(), ids.len());
+        let edges = datastore
+            .get_edges(SpecificEdgeQuery::single(key.clone()).into())
+            .unwrap();
+        assert_eq!(edges.len(), 1);
+        let properties = datastore
+            .get_vertex_properties(SpecificVertexQuery::new(ids.clone()).property(name.clone()))
+            .unwrap();
+        assert_eq!(properties.len(), ids.len());
+    }
+    assert_eq!(
+        src.get_vertices(RangeVertexQuery::new().into()).unwrap(),
+        dst.get_vertices(RangeVertexQuery::new().into()).unwrap()
+    );
+    // Indexes are carried over too
+    let q = crate::PropertyValueVertexQuery::new(name, serde_json::json!(true));
+    assert_eq!(dst.get_vertices(q.into()).unwrap().len(), ids.len());
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_not_migrate_a_missing_database() {
+    use super::{RocksdbConfig, RocksdbDatastore};
+    use crate::Error;
+    use tempfile::tempdir;
+
+    let dir = tempdir().unwrap();
+    let src_path = dir.path().join("missing");
+    let dst_path = dir.path().join("dst");
+    assert!(matches!(
+        RocksdbDatastore::migrate_compression(&src_path, &dst_path, RocksdbConfig::default()),
+        Err(Error::Datastore(_))
+    ));
+    assert!(!src_path.exists());
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_recompress_in_place() {
+    use super::RocksdbConfig;
+    use crate::{Datastore, Identifier, RangeVertexQuery, SpecificVertexQuery, VertexQueryExt};
+    use tempfile::tempdir;
+
+    let dir = tempdir().unwrap();
+    let t = Identifier::new("test_type").unwrap();
+    let name = Identifier::new("test_property").unwrap();
+    let value = serde_json::json!(vec!["a repetitive property value"; 20]);
+
+    let ids = {
+        let datastore = RocksdbConfig::default()
+            .max_open_files(Some(1))
+            .compression(false)
+            .open(dir.path())
+            .unwrap();
+        let ids: Vec<_> = (0..500)
+            .map(|_| datastore.create_vertex_from_type(t.clone()).unwrap())
+            .collect();
+        let q = SpecificVertexQuery::new(ids.clone()).property(name.clone());
+        datastore.set_vertex_properties(q, value.clone()).unwrap();
+        datastore.sync().unwrap();
+        ids
+    };
+
+    let datastore = RocksdbConfig::default()
+        .max_open_files(Some(1))
+        .compression(true)
+        .open(dir.path())
+        .unwrap();
+    let stats = datastore.recompress().unwrap();
+    assert!(stats.size_after < stats.size_before);
+    // Running it again is harmless
+    datastore.recompress().unwrap();
+
+    let vertices = datastore.get_vertices(RangeVertexQuery::new().into()).unwrap();
+    assert_eq!(vertices.len(), ids.len());
+    let properties = datastore
+        .get_vertex_properties(SpecificVertexQuery::new(ids.clone()).property(name))
+        .unwrap();
+    assert_eq!(properties.len(), ids.len());
+    assert!(properties.iter().all(|p| p.value == value));
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_check_for_at_least_k_edges() {
+    use super::RocksdbConfig;
+    use crate::{Datastore, EdgeDirection, EdgeKey, Identifier};
+
+    for config in [RocksdbConfig::default(), RocksdbConfig::default().degree_counters()] {
+        let datastore = config.temporary().open("ignored").unwrap();
+        let t = Identifier::new("test_type").unwrap();
+        let hub = datastore.create_vertex_from_type(t.clone()).unwrap();
+        for _ in 0..3 {
+            let spoke = datastore.create_vertex_from_type(t.clone()).unwrap();
+            datastore.create_edge(&EdgeKey::new(hub, t.clone(), spoke)).unwrap();
+        }
+
+        assert!(datastore.has_at_least_edges(hub, EdgeDirection::Outbound, 3).unwrap());
+        assert!(!datastore.has_at_least_edges(hub, EdgeDirection::Outbound, 4).unwrap());
+        assert!(!datastore.has_at_least_edges(hub, EdgeDirection::Inbound, 1).unwrap());
+    }
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_count_edges_with_degree_counters() {
+    use super::RocksdbConfig;
+    use crate::{Datastore, EdgeDirection, EdgeKey, Identifier, SpecificEdgeQuery};
+    use tempfile::tempdir;
+
+    let dir = tempdir().unwrap();
+    let t = Identifier::new("test_type").unwrap();
+
+    let (out_id, in_id) = {
+        let datastore = RocksdbConfig::default()
+            .max_open_files(Some(1))
+            .degree_counters()
+            .open(dir.path())
+            .unwrap();
+        let out_id = datastore.create_vertex_from_type(t.clone()).unwrap();
+        let in_ids: Vec<_> = (0..3)
+            .map(|_| datastore.create_vertex_from_type(t.clone()).unwrap())
+            .collect();
+        for in_id in &in_ids {
+            datastore.create_edge(&EdgeKey::new(out_id, t.clone(), *in_id)).unwrap();
+        }
+        let q = SpecificEdgeQuery::single(EdgeKey::new(out_id, t.clone(), in_ids[1]));
+        datastore.delete_edges(q.into()).unwrap();
+        (out_id, in_ids[0])
+    };
+
+    let datastore = RocksdbConfig::default()
+        .max_open_files(Some(1))
+        .degree_counters()
+        .open(dir.path())
+        .unwrap();
+    assert_eq!(
+        datastore.get_edge_count(out_id, None, EdgeDirection::Outbound).unwrap(),
+        2
+    );
+    assert_eq!(
+        datastore.get_edge_count(in_id, None, EdgeDirection::Inbound).unwrap(),
+        1
+    );
+    assert_eq!(
+        datastore
+            .get_edge_count(out_id, Some(&t), EdgeDirection::Outbound)
+            .unwrap(),
+        2
+    );
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_keep_degree_counters_when_deleting_repeated_and_adjacent_vertices() {
+    use super::RocksdbConfig;
+    use crate::{Datastore, EdgeDirection, EdgeKey, Identifier, SpecificVertexQuery, Vertex};
+    use uuid::Uuid;
+
+    let counted = RocksdbConfig::default()
+        .degree_counters()
+        .temporary()
+        .open("ignored")
+        .unwrap();
+    let scanned = RocksdbConfig::default().temporary().open("ignored").unwrap();
+    let t = Identifier::new("test_type").unwrap();
+    let [a, b, c, d] = [1, 2, 3, 4].map(Uuid::from_u128);
+    let pairs = [(a, b), (b, a), (a, c), (c, a), (b, c), (c, d), (d, a)];
+
+    let build = |datastore: &dyn Datastore, ids: &[Uuid]| {
+        for id in ids {
+            datastore.create_vertex(&Vertex::with_id(*id, t.clone())).unwrap();
+        }
+        for (out_id, in_id) in pairs {
+            datastore.create_edge(&EdgeKey::new(out_id, t.clone(), in_id)).unwrap();
+        }
+    };
+
+    let assert_counts_match = || {
+        for id in [a, b, c, d] {
+            for direction in [EdgeDirection::Outbound, EdgeDirection::Inbound] {
+                assert_eq!(
+                    counted.get_edge_count(id, None, direction).unwrap(),
+                    scanned.get_edge_count(id, None, direction).unwrap(),
+                    "{:?} count of {}",
+                    direction,
+                    id
+                );
+            }
+        }
+    };
+
+    for datastore in [&counted, &scanned] {
+        build(datastore, &[a, b, c, d]);
+        // `a` is repeated, and `a` and `b` are adjacent
+        let q = SpecificVertexQuery::new(vec![a, a, b]);
+        datastore.delete_vertices(q.into()).unwrap();
+    }
+    assert_counts_match();
+
+    // Counters left behind for the deleted vertices would show up once
+    // they're recreated
+    for datastore in [&counted, &scanned] {
+        build(datastore, &[a, b]);
+    }
+    assert_counts_match();
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_clear() {
+    use super::RocksdbDatastore;
+    use crate::{Datastore, EdgeKey, Identifier, RangeVertexQuery, SpecificVertexQuery, VertexQueryExt};
+    use tempfile::tempdir;
+
+    let dir = tempdir().unwrap();
+    let datastore = RocksdbDatastore::new(dir.path(), Some(1)).unwrap();
+    let t = Identifier::new("test_type").unwrap();
+    let name = Identifier::new("test_property").unwrap();
+    let ids: Vec<_> = (0..10)
+        .map(|_| datastore.create_vertex_from_type(t.clone()).unwrap())
+        .collect();
+    for pair in ids.windows(2) {
+        datastore
+            .create_edge(&EdgeKey::new(pair[0], t.clone(), pair[1]))
+            .unwrap();
+    }
+    let q = SpecificVertexQuery::new(ids.clone()).property(name.clone());
+    datastore.set_vertex_properties(q, serde_json::json!(true)).unwrap();
+    datastore.index_property(name.clone()).unwrap();
+
+    datastore.clear().unwrap();
+
+    assert_eq!(datastore.get_vertex_count().unwrap(), 0);
+    let edges = datastore
+        .get_edges(SpecificVertexQuery::new(ids.clone()).outbound().into())
+        .unwrap();
+    assert_eq!(edges.len(), 0);
+    let properties = datastore
+        .get_vertex_properties(SpecificVertexQuery::new(ids).property(name.clone()))
+        .unwrap();
+    assert_eq!(properties.len(), 0);
+
+    // The store is still usable afterwards
+    let id = datastore.create_vertex_from_type(t).unwrap();
+    let vertices = datastore.get_vertices(RangeVertexQuery::new().into()).unwrap();
+    assert_eq!(vertices.len(), 1);
+    assert_eq!(vertices[0].id, id);
+    drop(datastore);
+
+    // Indexes are cleared too, including on reopen
+    let datastore = RocksdbDatastore::new(dir.path(), Some(1)).unwrap();
+    let q = crate::PropertyValueVertexQuery::new(name, serde_json::json!(true));
+    assert!(matches!(
+        datastore.get_vertices(q.into()),
+        Err(crate::Error::NotIndexed)
+    ));
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_create_undirected_edge() {
+    use super::RocksdbDatastore;
+    use crate::{Datastore, EdgeKey, Identifier, SpecificEdgeQuery, SpecificVertexQuery, VertexQueryExt};
+    use tempfile::tempdir;
+
+    let dir = tempdir().unwrap();
+    let datastore = RocksdbDatastore::new(dir.path(), Some(1)).unwrap();
+    let t = Identifier::new("test_type").unwrap();
+    let a_id = datastore.create_vertex_from_type(t.clone()).unwrap();
+    let b_id = datastore.create_vertex_from_type(t.clone()).unwrap();
+    let key = EdgeKey::new(a_id, t.clone(), b_id);
+
+    assert!(datastore.create_undirected_edge(&key).unwrap());
+
+    let neighbors = |id, outbound: bool| -> Vec<_> {
+        let q = SpecificVertexQuery::single(id);
+        let q = if outbound { q.outbound() } else { q.inbound() };
+        datastore
+            .get_edges(q.into())
+            .unwrap()
+            .into_iter()
+            .map(|edge| edge.key)
+            .collect()
+    };
+    assert_eq!(neighbors(a_id, true), vec![key.clone()]);
+    assert_eq!(neighbors(b_id, true), vec![key.reversed()]);
+    assert_eq!(neighbors(a_id, false), vec![key.reversed()]);
+    assert_eq!(neighbors(b_id, false), vec![key.clone()]);
+    let edges = datastore
+        .get_edges(SpecificEdgeQuery::single(key.reversed()).into())
+        .unwrap();
+    assert_eq!(edges.len(), 1);
+
+    // Deleting with either orientation removes both halves
+    assert!(datastore.delete_undirected_edge(&key.reversed()).unwrap());
+    assert!(neighbors(a_id, true).is_empty());
+    assert!(neighbors(b_id, true).is_empty());
+    assert!(!datastore.delete_undirected_edge(&key).unwrap());
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_check_integrity() {
+    use super::{RocksdbConfig, RocksdbDatastore};
+    use crate::rdb::datastore::CF_NAMES;
+    use crate::rdb::managers::{DBRef, EdgeRangeManager, EdgeSeq, VertexPropertyManager};
+    use crate::{Datastore, EdgeKey, Identifier, Json};
+    use chrono::offset::Utc;
+    use rocksdb::{Options, WriteBatch, DB};
+    use std::collections::HashSet;
+    use tempfile::tempdir;
+
+    let dir = tempdir().unwrap();
+    let t = Identifier::new("test_type").unwrap();
+    let name = Identifier::new("test_property").unwrap();
+
+    let (key, missing_key) = {
+        let datastore = RocksdbDatastore::new(dir.path(), Some(1)).unwrap();
+        let a_id = datastore.create_vertex_from_type(t.clone()).unwrap();
+        let b_id = datastore.create_vertex_from_type(t.clone()).unwrap();
+        let key = EdgeKey::new(a_id, t.clone(), b_id);
+        let missing_key = EdgeKey::new(b_id, t.clone(), a_id);
+        datastore.create_edge(&key).unwrap();
+        datastore.create_edge(&missing_key).unwrap();
+        assert!(datastore.check_integrity().unwrap().is_empty());
+        (key, missing_key)
+    };
+
+    // Corrupt the store by writing to column families directly
+    let dangling_datetime = Utc::now();
+    let orphan_id = crate::util::generate_uuid_v1();
+    let missing_datetime = {
+        let db = DB::open_cf(&Options::default(), dir.path(), CF_NAMES).unwrap();
+        let indexed_properties = HashSet::default();
+        let config = RocksdbConfig::default();
+        let edge_seq = EdgeSeq::default();
+        let db_ref = DBRef::new(&db, &indexed_properties, &config, &edge_seq);
+        let edge_range_manager = EdgeRangeManager::new(db_ref);
+        let reversed_edge_range_manager = EdgeRangeManager::new_reversed(db_ref);
+        let missing_datetime = reversed_edge_range_manager
+            .iterate_for_range(missing_key.inbound_id, None, None)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+            .2;
+
+        let mut batch = WriteBatch::default();
+        edge_range_manager
+            .set(&mut batch, key.outbound_id, &t, dangling_datetime, None, key.inbound_id)
+            .unwrap();
+        reversed_edge_range_manager
+            .delete(
+                &mut batch,
+                missing_key.inbound_id,
+                &t,
+                missing_datetime,
+                None,
+                missing_key.outbound_id,
+            )
+            .unwrap();
+        VertexPropertyManager::new(db_ref)
+            .set(&mut batch, orphan_id, &name, &Json::new(serde_json::json!(true)))
+            .unwrap();
+        db.write(batch).unwrap();
+        missing_datetime
+    };
+    assert!(missing_datetime < dangling_datetime);
+
+    let datastore = RocksdbDatastore::new(dir.path(), Some(1)).unwrap();
+    let report = datastore.check_integrity().unwrap();
+    assert_eq!(report.dangling_edge_ranges, vec![(key, dangling_datetime)]);
+    assert!(report.dangling_reversed_edge_ranges.is_empty());
+    assert_eq!(report.missing_edge_ranges, vec![missing_key]);
+    assert_eq!(report.orphaned_vertex_properties, vec![(orphan_id, name)]);
+    assert!(report.orphaned_edge_properties.is_empty());
+
+    // Checking doesn't modify anything, but repairing does
+    assert_eq!(datastore.check_integrity().unwrap(), report);
+    assert_eq!(datastore.repair_integrity().unwrap(), report);
+    assert!(datastore.check_integrity().unwrap().is_empty());
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_check_integrity_of_dangling_edges_and_indexes() {
+    use super::RocksdbConfig;
+    use crate::rdb::datastore::CF_NAMES;
+    use crate::rdb::managers::{merge_degrees, DBRef, EdgeManager, EdgeSeq};
+    use crate::{Datastore, EdgeDirection, EdgeKey, Identifier};
+    use chrono::offset::Utc;
+    use rocksdb::{ColumnFamilyDescriptor, Options, WriteBatch, DB};
+    use std::collections::HashSet;
+    use tempfile::tempdir;
+
+    let dir = tempdir().unwrap();
+    let config = RocksdbConfig::default().degree_counters().edge_type_index();
+    let t = Identifier::new("test_type").unwrap();
+
+    let (a_id, b_id, c_id) = {
+        let datastore = config.clone().open(dir.path()).unwrap();
+        let a_id = datastore.create_vertex_from_type(t.clone()).unwrap();
+        let b_id = datastore.create_vertex_from_type(t.clone()).unwrap();
+        let c_id = datastore.create_vertex_from_type(t.clone()).unwrap();
+        datastore.create_edge(&EdgeKey::new(a_id, t.clone(), b_id)).unwrap();
+        datastore.create_edge(&EdgeKey::new(b_id, t.clone(), c_id)).unwrap();
+        assert!(datastore.check_integrity().unwrap().is_empty());
+        (a_id, b_id, c_id)
+    };
+
+    // Corrupt the store by writing to column families directly. Deleting
+    // just the vertex leaves its edge behind, like an interrupted chunked
+    // delete would.
+    let missing_key = EdgeKey::new(b_id, t.clone(), c_id);
+    let dangling_key = EdgeKey::new(c_id, t.clone(), b_id);
+    let dangling_datetime = Utc::now();
+    {
+        let mut opts = Options::default();
+        opts.set_merge_operator_associative("vertex_degrees", merge_degrees);
+        let cf_descriptors = CF_NAMES
+            .iter()
+            .map(|cf_name| ColumnFamilyDescriptor::new(*cf_name, opts.clone()));
+        let db = DB::open_cf_descriptors(&opts, dir.path(), cf_descriptors).unwrap();
+        let indexed_properties = HashSet::default();
+        let edge_seq = EdgeSeq::default();
+        let db_ref = DBRef::new(&db, &indexed_properties, &config, &edge_seq);
+        let edge_manager = EdgeManager::new(db_ref);
+        let missing_datetime = edge_manager
+            .get(missing_key.outbound_id, &t, missing_key.inbound_id)
+            .unwrap()
+            .unwrap();
+
+        let mut batch = WriteBatch::default();
+        batch.delete_cf(db.cf_handle("vertices:v1").unwrap(), a_id.as_bytes());
+        edge_manager.delete_type_index(
+            &mut batch,
+            missing_key.outbound_id,
+            &t,
+            missing_key.inbound_id,
+            missing_datetime,
+        );
+        edge_manager.set_type_index(
+            &mut batch,
+            dangling_key.outbound_id,
+            &t,
+            dangling_key.inbound_id,
+            dangling_datetime,
+        );
+        edge_manager.set_degrees(&mut batch, c_id, 5, 5);
+        db.write(batch).unwrap();
+    }
+
+    let datastore = config.open(dir.path()).unwrap();
+    let report = datastore.check_integrity().unwrap();
+    assert_eq!(report.dangling_edges, vec![EdgeKey::new(a_id, t.clone(), b_id)]);
+    assert_eq!(
+        report.dangling_type_index_entries,
+        vec![(dangling_key, dangling_datetime)]
+    );
+    assert_eq!(report.missing_type_index_entries, vec![missing_key]);
+    assert_eq!(report.mismatched_degrees, vec![c_id]);
+    assert!(report.dangling_edge_ranges.is_empty());
+    assert!(report.dangling_reversed_edge_ranges.is_empty());
+    assert!(report.missing_edge_ranges.is_empty());
+
+    assert_eq!(datastore.check_integrity().unwrap(), report);
+    assert_eq!(datastore.repair_integrity().unwrap(), report);
+    assert!(datastore.check_integrity().unwrap().is_empty());
+
+    // The dangling edge is gone, along with its share of the counters
+    let count = |id, direction| datastore.get_edge_count(id, None, direction).unwrap();
+    assert_eq!(count(a_id, EdgeDirection::Outbound), 0);
+    assert_eq!(count(b_id, EdgeDirection::Inbound), 0);
+    assert_eq!(count(b_id, EdgeDirection::Outbound), 1);
+    assert_eq!(count(c_id, EdgeDirection::Inbound), 1);
+    assert_eq!(count(c_id, EdgeDirection::Outbound), 0);
+    let edges = datastore.get_edges_by_type(&t, None, 10).unwrap();
+    let keys: Vec<EdgeKey> = edges.into_iter().map(|edge| edge.key).collect();
+    assert_eq!(keys, vec![EdgeKey::new(b_id, t, c_id)]);
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_shard_edge_ranges() {
+    use super::RocksdbConfig;
+    use crate::rdb::datastore::cf_names;
+    use crate::rdb::managers::{shard_cf_name, DBRef, EdgeRangeManager, EdgeSeq};
+    use crate::{Datastore, EdgeKey, Identifier, SpecificVertexQuery, VertexQueryExt};
+    use rocksdb::{IteratorMode, Options, DB};
+    use std::collections::HashSet;
+    use tempfile::tempdir;
+
+    let dir = tempdir().unwrap();
+    let t = Identifier::new("test_type").unwrap();
+    let config = RocksdbConfig::default().edge_range_shards(4);
+
+    let hub_id = {
+        let datastore = config.clone().open(dir.path()).unwrap();
+        let hub_id = datastore.create_vertex_from_type(t.clone()).unwrap();
+        for _ in 0..100 {
+            let id = datastore.create_vertex_from_type(t.clone()).unwrap();
+            datastore.create_edge(&EdgeKey::new(hub_id, t.clone(), id)).unwrap();
+            datastore.create_edge(&EdgeKey::new(id, t.clone(), hub_id)).unwrap();
+        }
+
+        let outbound = datastore
+            .get_edges(SpecificVertexQuery::single(hub_id).outbound().into())
+            .unwrap();
+        let inbound = datastore
+            .get_edges(SpecificVertexQuery::single(hub_id).inbound().t(t.clone()).into())
+            .unwrap();
+        assert_eq!(outbound.len(), 100);
+        assert_eq!(inbound.len(), 100);
+        assert!(datastore.check_integrity().unwrap().is_empty());
+        hub_id
+    };
+
+    // Reopening with a different number of shards fails
+    assert!(RocksdbConfig::default().edge_range_shards(2).open(dir.path()).is_err());
+    assert!(RocksdbConfig::default().edge_range_shards(8).open(dir.path()).is_err());
+
+    let db = DB::open_cf(&Options::default(), dir.path(), cf_names(&config)).unwrap();
+    let indexed_properties = HashSet::default();
+    let edge_seq = EdgeSeq::default();
+    let db_ref = DBRef::new(&db, &indexed_properties, &config, &edge_seq);
+    for reversed in [false, true] {
+        let manager = if reversed {
+            EdgeRangeManager::new_reversed(db_ref)
+        } else {
+            EdgeRangeManager::new(db_ref)
+        };
+        let all: Vec<_> = manager.iterate_for_all().collect::<crate::Result<_>>().unwrap();
+        assert_eq!(all.len(), 200);
+        assert_eq!(
+            all.iter().filter(|(first_id, _, _, _)| *first_id == hub_id).count(),
+            100
+        );
+    }
+
+    // The non-hub vertices are spread across every shard
+    for shard in 0..4 {
+        let cf = db.cf_handle(&shard_cf_name("edge_ranges:v1", shard)).unwrap();
+        assert!(db.iterator_cf(cf, IteratorMode::Start).next().is_some());
+    }
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_read_through_property_cache() {
+    use super::RocksdbConfig;
+    use crate::{BulkInsertItem, Datastore, Identifier, SpecificVertexQuery, VertexQueryExt};
+    use tempfile::tempdir;
+
+    let dir = tempdir().unwrap();
+    let datastore = RocksdbConfig::default()
+        .property_cache_capacity(16)
+        .open(dir.path())
+        .unwrap();
+    let t = Identifier::new("test_vertex_type").unwrap();
+    let name = Identifier::new("test_property").unwrap();
+    let id = datastore.create_vertex_from_type(t).unwrap();
+    let q = || SpecificVertexQuery::single(id).property(name.clone());
+    let read = || -> Vec<serde_json::Value> {
+        datastore
+            .get_vertex_properties(q())
+            .unwrap()
+            .into_iter()
+            .map(|property| property.value)
+            .collect()
+    };
+
+    datastore.set_vertex_properties(q(), serde_json::json!(1)).unwrap();
+    assert_eq!(read(), vec![serde_json::json!(1)]);
+    assert_eq!(read(), vec![serde_json::json!(1)]);
+    datastore.set_vertex_properties(q(), serde_json::json!(2)).unwrap();
+    assert_eq!(read(), vec![serde_json::json!(2)]);
+    datastore
+        .bulk_insert(vec![BulkInsertItem::VertexProperty(
+            id,
+            name.clone(),
+            serde_json::json!(3),
+        )])
+        .unwrap();
+    assert_eq!(read(), vec![serde_json::json!(3)]);
+    datastore.delete_vertex_properties(q()).unwrap();
+    assert!(read().is_empty());
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_cache_property_reads_within_a_batch() {
+    use super::RocksdbConfig;
+    use crate::{Datastore, Identifier, SpecificVertexQuery, VertexQueryExt};
+
+    let datastore = RocksdbConfig::default().temporary().open("ignored").unwrap();
+    let t = Identifier::new("test_vertex_type").unwrap();
+    let name = Identifier::new("test_property").unwrap();
+    let id = datastore.create_vertex_from_type(t).unwrap();
+    let q = || SpecificVertexQuery::single(id).property(name.clone());
+    datastore.set_vertex_properties(q(), serde_json::json!(1)).unwrap();
+
+    datastore
+        .with_batch(|batch| {
+            batch.cache_property_reads(16);
+            assert_eq!(batch.get_vertex_property(id, &name)?, Some(serde_json::json!(1)));
+
+            // Cached reads don't see writes made outside the batch
+            datastore.set_vertex_properties(q(), serde_json::json!(2))?;
+            assert_eq!(batch.get_vertex_property(id, &name)?, Some(serde_json::json!(1)));
+
+            // Setting the property in the batch invalidates it, but the staged
+            // value isn't seen until the batch is applied
+            batch.set_vertex_property(id, &name, serde_json::json!(3))?;
+            assert_eq!(batch.get_vertex_property(id, &name)?, Some(serde_json::json!(2)));
+            Ok(())
+        })
+        .unwrap();
+
+    let properties = datastore.get_vertex_properties(q()).unwrap();
+    assert_eq!(properties[0].value, serde_json::json!(3));
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_open_temporary() {
+    use super::{RocksdbConfig, RocksdbDatastore};
+    use crate::{Datastore, Identifier, SpecificVertexQuery, Vertex};
+
+    let datastore: RocksdbDatastore = RocksdbConfig::default().temporary().open("ignored").unwrap();
+    let path = datastore.path().to_path_buf();
+    assert!(path.exists());
+    assert!(!std::path::Path::new("ignored").exists());
+
+    let vertex = Vertex::new(Identifier::new("test_vertex_type").unwrap());
+    assert!(datastore.create_vertex(&vertex).unwrap());
+    let vertices = datastore
+        .get_vertices(SpecificVertexQuery::single(vertex.id).into())
+        .unwrap();
+    assert_eq!(vertices, vec![vertex]);
+
+    drop(datastore);
+    assert!(!path.exists());
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_compose_config_options() {
+    use super::RocksdbConfig;
+    use crate::{Datastore, Error, Identifier, SpecificVertexQuery, VertexQueryExt};
+    use rocksdb::{Options, DB};
+
+    let datastore = RocksdbConfig::default()
+        .temporary()
+        .edge_range_shards(2)
+        .max_property_bytes(Some(8))
+        .open("ignored")
+        .unwrap();
+    let path = datastore.path().to_path_buf();
+
+    let cf_names = DB::list_cf(&Options::default(), &path).unwrap();
+    assert!(cf_names.iter().any(|cf_name| cf_name == "edge_ranges:v1:1"));
+
+    let id = datastore
+        .create_vertex_from_type(Identifier::new("test_vertex_type").unwrap())
+        .unwrap();
+    let q = SpecificVertexQuery::single(id).property(Identifier::new("test_property").unwrap());
+    match datastore.set_vertex_properties(q, serde_json::json!("too long to store")) {
+        Err(Error::PropertyTooLarge) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    drop(datastore);
+    assert!(!path.exists());
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_get_edges_by_type() {
+    use super::RocksdbConfig;
+    use crate::{Datastore, EdgeKey, Error, Identifier, SpecificVertexQuery};
+
+    let datastore = RocksdbConfig::default()
+        .temporary()
+        .edge_type_index()
+        .open("ignored")
+        .unwrap();
+    let vertex_t = Identifier::new("test_vertex_type").unwrap();
+    let t = Identifier::new("test_edge_type").unwrap();
+    let other_t = Identifier::new("other_edge_type").unwrap();
+
+    let ids: Vec<_> = (0..4)
+        .map(|_| datastore.create_vertex_from_type(vertex_t.clone()).unwrap())
+        .collect();
+    let first_key = EdgeKey::new(ids[0], t.clone(), ids[1]);
+    let second_key = EdgeKey::new(ids[2], t.clone(), ids[3]);
+    let third_key = EdgeKey::new(ids[3], t.clone(), ids[0]);
+    let other_key = EdgeKey::new(ids[1], other_t.clone(), ids[2]);
+    for key in [&first_key, &second_key, &third_key, &other_key] {
+        datastore.create_edge(key).unwrap();
+    }
+
+    // Touching an edge moves it to the front, and deleting its source
+    // removes it
+    assert!(datastore.touch_edge(&first_key).unwrap());
+    datastore
+        .delete_vertices(SpecificVertexQuery::single(ids[2]).into())
+        .unwrap();
+
+    let edges = datastore.get_edges_by_type(&t, None, u32::MAX).unwrap();
+    let keys: Vec<_> = edges.iter().map(|edge| edge.key.clone()).collect();
+    assert_eq!(keys, vec![first_key.clone(), third_key]);
+    let older = datastore
+        .get_edges_by_type(&t, Some(edges[1].created_datetime), 1)
+        .unwrap();
+    assert_eq!(older, vec![edges[1].clone()]);
+
+    assert!(datastore.retype_edge(&first_key, &other_t).unwrap());
+    let edges = datastore.get_edges_by_type(&other_t, None, u32::MAX).unwrap();
+    assert_eq!(edges[0].key, EdgeKey::new(ids[0], other_t, ids[1]));
+    assert_eq!(edges.len(), 1);
+
+    let unindexed = RocksdbConfig::default().temporary().open("ignored").unwrap();
+    match unindexed.get_edges_by_type(&t, None, u32::MAX) {
+        Err(Error::Unsupported) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_count_deleted_vertex_contents() {
+    use super::{DeleteCounts, RocksdbConfig};
+    use crate::{Datastore, EdgeKey, EdgeQueryExt, Identifier, SpecificEdgeQuery, SpecificVertexQuery, VertexQueryExt};
+    use serde_json::json;
+
+    let datastore = RocksdbConfig::default().temporary().open("ignored").unwrap();
+    let t = Identifier::new("test_type").unwrap();
+    let name = Identifier::new("test_property").unwrap();
+    let other_name = Identifier::new("other_test_property").unwrap();
+    let id = datastore.create_vertex_from_type(t.clone()).unwrap();
+    let other_id = datastore.create_vertex_from_type(t.clone()).unwrap();
+
+    for name in [&name, &other_name] {
+        let q = SpecificVertexQuery::single(id).property(name.clone());
+        datastore.set_vertex_properties(q, json!(true)).unwrap();
+    }
+
+    let keys = [
+        EdgeKey::new(id, t.clone(), other_id),
+        EdgeKey::new(other_id, t.clone(), id),
+        EdgeKey::new(id, t.clone(), id),
+    ];
+    for key in &keys {
+        datastore.create_edge(key).unwrap();
+        let q = SpecificEdgeQuery::single(key.clone()).property(name.clone());
+        datastore.set_edge_properties(q, json!(1)).unwrap();
+    }
+
+    assert_eq!(
+        datastore.delete_vertex_counted(id).unwrap(),
+        DeleteCounts {
+            vertex_removed: true,
+            edges_removed: 3,
+            properties_removed: 5,
+        }
+    );
+    assert_eq!(datastore.delete_vertex_counted(id).unwrap(), DeleteCounts::default());
+    assert_eq!(datastore.get_vertex_count().unwrap(), 1);
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_apply_batch_only_on_success() {
+    use super::RocksdbConfig;
+    use crate::{
+        Datastore, EdgeKey, EdgeQueryExt, Error, Identifier, RangeVertexQuery, SpecificEdgeQuery, Vertex,
+        VertexQueryExt,
+    };
+    use serde_json::json;
+
+    let datastore = RocksdbConfig::default()
+        .temporary()
+        .max_property_bytes(Some(8))
+        .open("ignored")
+        .unwrap();
+    let t = Identifier::new("test_type").unwrap();
+    let name = Identifier::new("test_property").unwrap();
+
+    let res = datastore.with_batch(|batch| {
+        let outbound_v = Vertex::new(t.clone());
+        let inbound_v = Vertex::new(t.clone());
+        assert!(batch.create_vertex(&outbound_v)?);
+        assert!(batch.create_vertex(&inbound_v)?);
+        assert!(!batch.create_vertex(&outbound_v)?);
+        let key = EdgeKey::new(outbound_v.id, t.clone(), inbound_v.id);
+        assert!(batch.create_edge(&key)?);
+        batch.set_vertex_property(outbound_v.id, &name, json!(true))?;
+        batch.set_edge_property(&key, &name, json!("too long to store"))
+    });
+    assert!(matches!(res, Err(Error::PropertyTooLarge)));
+    assert_eq!(datastore.get_vertex_count().unwrap(), 0);
+    assert!(datastore
+        .get_vertex_properties(RangeVertexQuery::new().property(name.clone()))
+        .unwrap()
+        .is_empty());
+
+    let outbound_v = Vertex::new(t.clone());
+    let inbound_v = Vertex::new(t.clone());
+    let key = EdgeKey::new(outbound_v.id, t.clone(), inbound_v.id);
+    datastore
+        .with_batch(|batch| {
+            batch.create_vertex(&outbound_v)?;
+            batch.create_vertex(&inbound_v)?;
+            batch.create_edge(&key)?;
+            batch.set_edge_property(&key, &name, json!(1))
+        })
+        .unwrap();
+    assert_eq!(datastore.get_vertex_count().unwrap(), 2);
+    let properties = datastore
+        .get_edge_properties(SpecificEdgeQuery::single(key).property(name))
+        .unwrap();
+    assert_eq!(properties[0].value, json!(1));
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_persist_durable_batches_without_flush_on_drop() {
+    use super::RocksdbConfig;
+    use crate::{Datastore, Identifier, SpecificVertexQuery, Vertex, VertexQueryExt};
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    let dir = tempdir().unwrap();
+    let t = Identifier::new("test_type").unwrap();
+    let name = Identifier::new("test_property").unwrap();
+    let vertex = Vertex::new(t);
+
+    // With the write-ahead log disabled and no flush on drop, only the
+    // durable batch guarantees the writes reach disk
+    for config in [
+        RocksdbConfig::default().flush_on_drop(false),
+        RocksdbConfig::default().manual_flush().flush_on_drop(false),
+    ] {
+        let datastore = config.open(dir.path()).unwrap();
+        datastore
+            .with_durable_batch(|batch| {
+                batch.create_vertex(&vertex)?;
+                batch.set_vertex_property(vertex.id, &name, json!(100))
+            })
+            .unwrap();
+        drop(datastore);
+
+        let datastore = RocksdbConfig::default().open(dir.path()).unwrap();
+        let q = SpecificVertexQuery::single(vertex.id);
+        assert_eq!(datastore.get_vertices(q.clone().into()).unwrap(), vec![vertex.clone()]);
+        let properties = datastore.get_vertex_properties(q.property(name.clone())).unwrap();
+        assert_eq!(properties[0].value, json!(100));
+        datastore.clear().unwrap();
+    }
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_report_space_amplification() {
+    use super::RocksdbConfig;
+    use crate::{Datastore, Identifier, RangeVertexQuery, SpecificVertexQuery, Vertex};
+    use tempfile::tempdir;
+
+    let dir = tempdir().unwrap();
+    let config = RocksdbConfig::default().compression(false);
+    let t = Identifier::new("test_type").unwrap();
+    let vertices: Vec<Vertex> = (0..10_000).map(|_| Vertex::new(t.clone())).collect();
+
+    // Each open is dropped to flush a table file without compacting it
+    {
+        let datastore = config.clone().open(dir.path()).unwrap();
+        for vertex in &vertices {
+            datastore.create_vertex(vertex).unwrap();
+        }
+    }
+    {
+        let datastore = config.clone().open(dir.path()).unwrap();
+        let ids: Vec<_> = vertices[100..].iter().map(|vertex| vertex.id).collect();
+        datastore.delete_vertices(SpecificVertexQuery::new(ids).into()).unwrap();
+    }
+
+    let datastore = config.open(dir.path()).unwrap();
+    let report = datastore.maintenance_report().unwrap();
+    assert!(report.should_rebuild());
+    assert!(report.size_on_disk > report.live_data_size);
+
+    datastore.sync().unwrap();
+    let compacted_report = datastore.maintenance_report().unwrap();
+    assert_eq!(compacted_report.live_data_size, report.live_data_size);
+    assert!(compacted_report.size_on_disk < report.size_on_disk);
+    assert!(compacted_report.space_amplification < report.space_amplification);
+    assert_eq!(
+        datastore.get_vertices(RangeVertexQuery::new().into()).unwrap().len(),
+        100
+    );
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_list_edges_in_write_order_with_sequence_numbers() {
+    use super::RocksdbConfig;
+    use crate::{Datastore, EdgeKey, Identifier, SpecificVertexQuery, VertexQueryExt};
+    use tempfile::tempdir;
+
+    let dir = tempdir().unwrap();
+    let config = RocksdbConfig::default().edge_sequence_numbers();
+    let t = Identifier::new("test_type").unwrap();
+    let outbound_ids = |datastore: &super::RocksdbDatastore, id| -> Vec<_> {
+        let q = SpecificVertexQuery::single(id).inbound();
+        datastore
+            .get_edges(q.into())
+            .unwrap()
+            .into_iter()
+            .map(|edge| edge.key.outbound_id)
+            .collect()
+    };
+
+    let (hub_id, mut spoke_ids) = {
+        let datastore = config.clone().open(dir.path()).unwrap();
+        let hub_id = datastore.create_vertex_from_type(t.clone()).unwrap();
+        let mut spoke_ids = Vec::new();
+        for _ in 0..50 {
+            let id = datastore.create_vertex_from_type(t.clone()).unwrap();
+            assert!(datastore.create_edge(&EdgeKey::new(id, t.clone(), hub_id)).unwrap());
+            spoke_ids.push(id);
+        }
+        spoke_ids.reverse();
+        assert_eq!(outbound_ids(&datastore, hub_id), spoke_ids);
+        (hub_id, spoke_ids)
+    };
+
+    // The counter carries on after reopening
+    let datastore = config.open(dir.path()).unwrap();
+    let id = datastore.create_vertex_from_type(t.clone()).unwrap();
+    assert!(datastore.create_edge(&EdgeKey::new(id, t.clone(), hub_id)).unwrap());
+    spoke_ids.insert(0, id);
+    assert_eq!(outbound_ids(&datastore, hub_id), spoke_ids);
+    assert!(datastore.check_integrity().unwrap().is_empty());
+
+    datastore
+        .delete_vertices(SpecificVertexQuery::new(spoke_ids[..10].to_vec()).into())
+        .unwrap();
+    assert_eq!(outbound_ids(&datastore, hub_id), spoke_ids[10..]);
+    assert!(datastore.check_integrity().unwrap().is_empty());
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_reject_mismatched_edge_settings() {
+    use super::RocksdbConfig;
+    use crate::rdb::datastore::cf_names;
+    use crate::{Datastore, EdgeKey, Error, Identifier};
+    use rocksdb::{Options, DB};
+    use tempfile::tempdir;
+
+    let t = Identifier::new("test_type").unwrap();
+    let settings = [
+        (
+            "edge_sequence_numbers",
+            RocksdbConfig::default().edge_sequence_numbers(),
+        ),
+        ("degree_counters", RocksdbConfig::default().degree_counters()),
+        ("edge_type_index", RocksdbConfig::default().edge_type_index()),
+    ];
+
+    for (name, enabled) in settings {
+        let disabled = RocksdbConfig::default();
+        for (created_with, opened_with) in [(enabled.clone(), disabled.clone()), (disabled, enabled.clone())] {
+            let dir = tempdir().unwrap();
+            {
+                let datastore = created_with.clone().open(dir.path()).unwrap();
+                let out_id = datastore.create_vertex_from_type(t.clone()).unwrap();
+                let in_id = datastore.create_vertex_from_type(t.clone()).unwrap();
+                datastore.create_edge(&EdgeKey::new(out_id, t.clone(), in_id)).unwrap();
+            }
+
+            assert!(
+                matches!(opened_with.clone().open(dir.path()), Err(Error::Datastore(_))),
+                "opened with a different {}",
+                name
+            );
+            created_with.clone().open(dir.path()).unwrap();
+
+            // Databases created before the setting was recorded are still
+            // checked against what they've stored
+            {
+                let db = DB::open_cf(&Options::default(), dir.path(), cf_names(&created_with)).unwrap();
+                db.delete_cf(db.cf_handle("metadata:v1").unwrap(), name).unwrap();
+            }
+            assert!(
+                matches!(opened_with.clone().open(dir.path()), Err(Error::Datastore(_))),
+                "opened without a recorded {}",
+                name
+            );
+            created_with.open(dir.path()).unwrap();
+        }
+    }
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_retry_opening_locked_database() {
+    use super::RocksdbConfig;
+    use std::thread;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    let dir = tempdir().unwrap();
+    let datastore = RocksdbConfig::default().open(dir.path()).unwrap();
+
+    // Without retries, the lock held by the first handle fails the open
+    assert!(RocksdbConfig::default().open(dir.path()).is_err());
+    assert!(RocksdbConfig::default()
+        .open_retry(2, Duration::from_millis(1))
+        .open(dir.path())
+        .is_err());
+
+    let releaser = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        drop(datastore);
+    });
+    RocksdbConfig::default()
+        .open_retry(10, Duration::from_millis(10))
+        .open(dir.path())
+        .unwrap();
+    releaser.join().unwrap();
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_get_vertices_with_edges() {
+    use super::RocksdbConfig;
+    use crate::{Datastore, EdgeKey, Identifier};
+    use std::collections::HashSet;
+
+    let datastore = RocksdbConfig::default().temporary().open("ignored").unwrap();
+    let t = Identifier::new("test_type").unwrap();
+    let other_t = Identifier::new("other_test_type").unwrap();
+    let a_id = datastore.create_vertex_from_type(t.clone()).unwrap();
+    let b_id = datastore.create_vertex_from_type(t.clone()).unwrap();
+    let c_id = datastore.create_vertex_from_type(other_t.clone()).unwrap();
+    let keys = [
+        EdgeKey::new(a_id, t.clone(), b_id),
+        EdgeKey::new(a_id, other_t.clone(), c_id),
+        EdgeKey::new(c_id, t.clone(), a_id),
+        EdgeKey::new(c_id, t.clone(), c_id),
+    ];
+    for key in &keys {
+        datastore.create_edge(key).unwrap();
+    }
+
+    let edge_keys = |edges: &[crate::Edge]| -> HashSet<EdgeKey> { edges.iter().map(|edge| edge.key.clone()).collect() };
+
+    let all = datastore.get_vertices_with_edges(None, None, u32::MAX).unwrap();
+    assert_eq!(all.len(), 3);
+    for (vertex, edges) in &all {
+        let expected: HashSet<EdgeKey> = keys
+            .iter()
+            .filter(|key| key.outbound_id == vertex.id)
+            .cloned()
+            .collect();
+        assert_eq!(edge_keys(edges), expected);
+    }
+
+    let typed = datastore.get_vertices_with_edges(Some(&t), None, u32::MAX).unwrap();
+    let typed_ids: Vec<_> = typed.iter().map(|(vertex, _)| vertex.id).collect();
+    let mut expected_ids = vec![a_id, b_id];
+    expected_ids.sort();
+    assert_eq!(typed_ids, expected_ids);
+    assert!(typed
+        .iter()
+        .any(|(vertex, edges)| vertex.id == b_id && edges.is_empty()));
+
+    // Each page starts after the last vertex of the one before
+    let mut paged = Vec::new();
+    let mut start_id = None;
+    loop {
+        let page = datastore.get_vertices_with_edges(None, start_id, 1).unwrap();
+        match page.last() {
+            Some((vertex, _)) => start_id = Some(vertex.id),
+            None => break,
+        }
+        paged.extend(page);
+        assert!(paged.len() <= all.len(), "paging repeated a vertex");
+    }
+    assert_eq!(paged, all);
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_find_vertices_by_property_range() {
+    use super::RocksdbConfig;
+    use crate::{Datastore, Error, Identifier, SpecificVertexQuery, VertexQueryExt};
+    use serde_json::json;
+
+    let datastore = RocksdbConfig::default().temporary().open("ignored").unwrap();
+    let t = Identifier::new("test_type").unwrap();
+    let name = Identifier::new("age").unwrap();
+    datastore.index_property(name.clone()).unwrap();
+
+    let values = [
+        json!(30),
+        json!(10),
+        json!(18),
+        json!(-5),
+        json!(25),
+        json!(18.5),
+        json!(31),
+        json!("20"),
+    ];
+    let ids: Vec<_> = values
+        .iter()
+        .map(|value| {
+            let id = datastore.create_vertex_from_type(t.clone()).unwrap();
+            let q = SpecificVertexQuery::single(id).property(name.clone());
+            datastore.set_vertex_properties(q, value.clone()).unwrap();
+            id
+        })
+        .collect();
+    datastore.create_vertex_from_type(t).unwrap();
+
+    // Both bounds are inclusive, and results are ordered by value
+    let found = datastore
+        .find_vertices_by_property_range(&name, &json!(18), &json!(30))
+        .unwrap();
+    assert_eq!(found, vec![ids[2], ids[5], ids[4], ids[0]]);
+    let found = datastore
+        .find_vertices_by_property_range(&name, &json!(-10.5), &json!(10))
+        .unwrap();
+    assert_eq!(found, vec![ids[3], ids[1]]);
+
+    // Empty ranges
+    let found = datastore
+        .find_vertices_by_property_range(&name, &json!(40), &json!(50))
+        .unwrap();
+    assert!(found.is_empty());
+    let found = datastore
+        .find_vertices_by_property_range(&name, &json!(30), &json!(18))
+        .unwrap();
+    assert!(found.is_empty());
+
+    // Changing or deleting a value updates the index
+    let q = SpecificVertexQuery::single(ids[4]).property(name.clone());
+    datastore.set_vertex_properties(q, json!(100)).unwrap();
+    datastore
+        .delete_vertices(SpecificVertexQuery::single(ids[0]).into())
+        .unwrap();
+    let found = datastore
+        .find_vertices_by_property_range(&name, &json!(18), &json!(100))
+        .unwrap();
+    assert_eq!(found, vec![ids[2], ids[5], ids[6], ids[4]]);
+
+    assert!(matches!(
+        datastore.find_vertices_by_property_range(&name, &json!("18"), &json!(30)),
+        Err(Error::TypeMismatch)
+    ));
+    let unindexed = Identifier::new("unindexed").unwrap();
+    assert!(matches!(
+        datastore.find_vertices_by_property_range(&unindexed, &json!(18), &json!(30)),
+        Err(Error::NotIndexed)
+    ));
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn should_index_numbers_of_existing_databases_on_open() {
+    use super::{RocksdbConfig, RocksdbDatastore};
+    use crate::rdb::datastore::CF_NAMES;
+    use crate::{Datastore, Identifier, SpecificVertexQuery, VertexQueryExt};
+    use rocksdb::{Options, DB};
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    let dir = tempdir().unwrap();
+    let t = Identifier::new("test_type").unwrap();
+    let name = Identifier::new("age").unwrap();
+
+    let id = {
+        let datastore = RocksdbDatastore::new(dir.path(), Some(1)).unwrap();
+        datastore.index_property(name.clone()).unwrap();
+        let id = datastore.create_vertex_from_type(t).unwrap();
+        let q = SpecificVertexQuery::single(id).property(name.clone());
+        datastore.set_vertex_properties(q, json!(20)).unwrap();
+        id
+    };
+
+    // Drop the number index, as if the database predated it
+    {
+        let mut db = DB::open_cf(&Options::default(), dir.path(), CF_NAMES).unwrap();
+        db.drop_cf("vertex_property_numbers:v1").unwrap();
+    }
+
+    let datastore = RocksdbConfig::default().open(dir.path()).unwrap();
+    let found = datastore
+        .find_vertices_by_property_range(&name, &json!(18), &json!(30))
+        .unwrap();
+    assert_eq!(found, vec![id]);
+}
@@ -1,6 +1,6 @@
 use crate::{
-    BulkInsertItem, Datastore, EdgeKey, EdgeQueryExt, Identifier, SpecificEdgeQuery, SpecificVertexQuery, Vertex,
-    VertexQueryExt,
+    BulkInsertItem, BulkValidationError, Datastore, EdgeKey, EdgeQueryExt, Identifier, SpecificEdgeQuery,
+    SpecificVertexQuery, Vertex, VertexQueryExt,
 };
 
 use chrono::offset::Utc;
@@ -118,3 +118,59 @@ pub fn should_bulk_insert_an_invalid_edge<D: Datastore>(datastore: &D) {
     let items = vec![BulkInsertItem::Edge(EdgeKey::new(v2.id, edge_t, v1.id))];
     assert!(datastore.bulk_insert(items).is_ok());
 }
+
+pub fn should_validate_a_clean_bulk_insert<D: Datastore>(datastore: &D) {
+    let vertex_t = Identifier::new("test_vertex_type").unwrap();
+    let existing_v = Vertex::new(vertex_t.clone());
+    datastore.create_vertex(&existing_v).unwrap();
+
+    // Edges may reference both existing vertices, and vertices inserted in
+    // the same request
+    let new_v = Vertex::new(vertex_t);
+    let key = EdgeKey::new(existing_v.id, Identifier::new("test_edge_type").unwrap(), new_v.id);
+    let name = Identifier::new("test_property").unwrap();
+    let items = vec![
+        BulkInsertItem::Vertex(new_v.clone()),
+        BulkInsertItem::Edge(key.clone()),
+        BulkInsertItem::VertexProperty(new_v.id, name.clone(), serde_json::Value::Bool(true)),
+        BulkInsertItem::EdgeProperty(key, name, serde_json::Value::Bool(true)),
+    ];
+
+    assert_eq!(datastore.validate_bulk_insert(&items).unwrap(), vec![]);
+
+    // Validating shouldn't have written anything
+    let vertices = datastore
+        .get_vertices(SpecificVertexQuery::single(new_v.id).into())
+        .unwrap();
+    assert_eq!(vertices.len(), 0);
+}
+
+pub fn should_validate_a_bulk_insert_with_problems<D: Datastore>(datastore: &D) {
+    let vertex_t = Identifier::new("test_vertex_type").unwrap();
+    let existing_v = Vertex::new(vertex_t.clone());
+    datastore.create_vertex(&existing_v).unwrap();
+
+    let missing_v = Vertex::new(vertex_t);
+    let edge_t = Identifier::new("test_edge_type").unwrap();
+    let dangling_key = EdgeKey::new(existing_v.id, edge_t.clone(), missing_v.id);
+    let long_t = unsafe { Identifier::new_unchecked("a".repeat(256)) };
+    let items = vec![
+        BulkInsertItem::Edge(dangling_key),
+        BulkInsertItem::Vertex(Vertex::with_id(missing_v.id, long_t.clone())),
+    ];
+
+    assert_eq!(
+        datastore.validate_bulk_insert(&items[..1]).unwrap(),
+        vec![BulkValidationError::MissingVertex {
+            index: 0,
+            id: missing_v.id
+        }]
+    );
+    assert_eq!(
+        datastore.validate_bulk_insert(&items).unwrap(),
+        vec![BulkValidationError::InvalidIdentifier {
+            index: 1,
+            identifier: long_t
+        }]
+    );
+}
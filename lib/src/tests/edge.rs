@@ -1,4 +1,6 @@
 use std::collections::HashSet;
+use std::thread;
+use std::time::Duration as StdDuration;
 
 use super::util::{create_edge_from, create_edges, create_time_range_queryable_edges};
 use crate::{
@@ -6,7 +8,7 @@ use crate::{
 };
 
 use chrono::offset::Utc;
-use chrono::Timelike;
+use chrono::{Duration, Timelike};
 use uuid::Uuid;
 
 pub fn should_get_a_valid_edge<D: Datastore>(datastore: &D) {
@@ -333,6 +335,53 @@ pub fn should_get_edges_piped<D: Datastore>(datastore: &D) {
     );
 }
 
+pub fn should_not_leak_edges_across_an_empty_type<D: Datastore>(datastore: &D) {
+    let vertex_t = models::Identifier::new("test_vertex_type").unwrap();
+    let outbound_v = models::Vertex::new(vertex_t.clone());
+    let inbound_v = models::Vertex::new(vertex_t);
+    datastore.create_vertex(&outbound_v).unwrap();
+    datastore.create_vertex(&inbound_v).unwrap();
+
+    // An empty type is a prefix of every other type's name, so make sure
+    // edges of other types don't show up when filtering on it, and vice versa
+    let empty_t = models::Identifier::default();
+    let short_t = models::Identifier::new("a").unwrap();
+    let long_t = models::Identifier::new("test_edge_type").unwrap();
+    for t in &[empty_t.clone(), short_t.clone(), long_t.clone()] {
+        let key = models::EdgeKey::new(outbound_v.id, t.clone(), inbound_v.id);
+        datastore.create_edge(&key).unwrap();
+    }
+
+    for t in &[empty_t, short_t, long_t] {
+        let outbound_range = datastore
+            .get_edges(
+                SpecificVertexQuery::single(outbound_v.id)
+                    .outbound()
+                    .t(t.clone())
+                    .into(),
+            )
+            .unwrap();
+        assert_eq!(outbound_range.len(), 1);
+        assert_eq!(&outbound_range[0].key.t, t);
+
+        let inbound_range = datastore
+            .get_edges(SpecificVertexQuery::single(inbound_v.id).inbound().t(t.clone()).into())
+            .unwrap();
+        assert_eq!(inbound_range.len(), 1);
+        assert_eq!(&inbound_range[0].key.t, t);
+
+        let count = datastore
+            .get_edge_count(outbound_v.id, Some(t), EdgeDirection::Outbound)
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    let count = datastore
+        .get_edge_count(outbound_v.id, None, EdgeDirection::Outbound)
+        .unwrap();
+    assert_eq!(count, 3);
+}
+
 fn check_edge_range(range: &[models::Edge], expected_outbound_id: Uuid, expected_length: usize) {
     assert_eq!(range.len(), expected_length);
     let mut covered_ids: HashSet<Uuid> = HashSet::new();
@@ -345,3 +394,50 @@ fn check_edge_range(range: &[models::Edge], expected_outbound_id: Uuid, expected
         covered_ids.insert(edge.key.inbound_id);
     }
 }
+
+pub fn should_get_recent_edges<D: Datastore>(datastore: &D) {
+    let vertex_t = models::Identifier::new("test_vertex_type").unwrap();
+    let old_t = models::Identifier::new("test_old_edge_type").unwrap();
+    let new_t = models::Identifier::new("test_new_edge_type").unwrap();
+    let vertex_ids: Vec<Uuid> = (0..3)
+        .map(|_| datastore.create_vertex_from_type(vertex_t.clone()).unwrap())
+        .collect();
+    let create = |t: &models::Identifier| -> Vec<EdgeKey> {
+        vertex_ids
+            .iter()
+            .map(|id| {
+                let key = EdgeKey::new(*id, t.clone(), vertex_ids[0]);
+                datastore.create_edge(&key).unwrap();
+                key
+            })
+            .collect()
+    };
+
+    let old_keys = create(&old_t);
+    thread::sleep(StdDuration::from_millis(100));
+    let cutoff = Utc::now();
+    let new_keys = create(&new_t);
+    create(&old_t);
+
+    // The window starts halfway through the sleep, so it catches only the
+    // edges created after it, plus the just-updated old edges
+    let within = (Utc::now() - cutoff) + Duration::milliseconds(50);
+    let recent: HashSet<EdgeKey> = datastore
+        .get_recent_edges(None, within, u32::MAX)
+        .unwrap()
+        .into_iter()
+        .map(|edge| edge.key)
+        .collect();
+    let expected: HashSet<EdgeKey> = new_keys.iter().chain(old_keys.iter()).cloned().collect();
+    assert_eq!(recent, expected);
+
+    let recent_typed = datastore.get_recent_edges(Some(&new_t), within, u32::MAX).unwrap();
+    assert_eq!(recent_typed.len(), 3);
+    assert!(recent_typed.iter().all(|edge| edge.key.t == new_t));
+
+    assert_eq!(datastore.get_recent_edges(None, within, 2).unwrap().len(), 2);
+    assert!(datastore
+        .get_recent_edges(Some(&new_t), -Duration::hours(1), u32::MAX)
+        .unwrap()
+        .is_empty());
+}
@@ -21,18 +21,26 @@ macro_rules! full_test_impl {
         define_test!(should_bulk_insert, $code);
         define_test!(should_bulk_insert_a_redundant_vertex, $code);
         define_test!(should_bulk_insert_an_invalid_edge, $code);
+        define_test!(should_validate_a_clean_bulk_insert, $code);
+        define_test!(should_validate_a_bulk_insert_with_problems, $code);
 
         // Vertices
         define_test!(should_create_vertex_from_type, $code);
         define_test!(should_get_range_vertices, $code);
+        define_test!(should_get_vertices_in_a_consistent_order, $code);
         define_test!(should_get_no_vertices_with_zero_limit, $code);
         define_test!(should_get_range_vertices_out_of_range, $code);
         define_test!(should_get_no_vertices_with_type_filter, $code);
+        define_test!(should_get_no_vertices_with_empty_id_range, $code);
+        define_test!(should_get_vertices_with_partial_id_range, $code);
+        define_test!(should_get_vertices_after_start_id, $code);
+        define_test!(should_get_vertices_with_full_id_range, $code);
         define_test!(should_get_single_vertex, $code);
         define_test!(should_get_single_vertex_nonexisting, $code);
         define_test!(should_get_vertices, $code);
         define_test!(should_get_vertices_piped, $code);
         define_test!(should_get_a_vertex_count, $code);
+        define_test!(should_check_vertices_exist, $code);
         define_test!(should_delete_a_valid_outbound_vertex, $code);
         define_test!(should_delete_a_valid_inbound_vertex, $code);
         define_test!(should_not_delete_an_invalid_vertex, $code);
@@ -57,6 +65,8 @@ macro_rules! full_test_impl {
         define_test!(should_get_no_edges_for_reversed_time, $code);
         define_test!(should_get_edges, $code);
         define_test!(should_get_edges_piped, $code);
+        define_test!(should_not_leak_edges_across_an_empty_type, $code);
+        define_test!(should_get_recent_edges, $code);
 
         // Indexing
         define_test!(should_not_query_unindexed_vertex_property, $code);
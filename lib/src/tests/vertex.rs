@@ -30,6 +30,33 @@ pub fn should_get_range_vertices<D: Datastore>(datastore: &D) {
     }
 }
 
+pub fn should_get_vertices_in_a_consistent_order<D: Datastore>(datastore: &D) {
+    let t = models::Identifier::new("test_vertex_type").unwrap();
+    let ids: Vec<Uuid> = [7u128, 3, 9, 1, 5].iter().map(|i| Uuid::from_u128(*i)).collect();
+    for id in &ids {
+        datastore
+            .create_vertex(&models::Vertex::with_id(*id, t.clone()))
+            .unwrap();
+    }
+
+    let range = datastore
+        .get_vertices(RangeVertexQuery::new().limit(ids.len() as u32).into())
+        .unwrap();
+    let mut sorted_ids = ids.clone();
+    sorted_ids.sort();
+    assert_eq!(
+        range.into_iter().map(|vertex| vertex.id).collect::<Vec<_>>(),
+        sorted_ids
+    );
+
+    let mut specific_ids = ids.clone();
+    specific_ids.insert(2, Uuid::from_u128(2));
+    let specific = datastore
+        .get_vertices(SpecificVertexQuery::new(specific_ids).into())
+        .unwrap();
+    assert_eq!(specific.into_iter().map(|vertex| vertex.id).collect::<Vec<_>>(), ids);
+}
+
 pub fn should_get_no_vertices_with_zero_limit<D: Datastore>(datastore: &D) {
     create_vertices(datastore);
     let range = datastore.get_vertices(RangeVertexQuery::new().limit(0).into()).unwrap();
@@ -57,6 +84,62 @@ pub fn should_get_no_vertices_with_type_filter<D: Datastore>(datastore: &D) {
     assert_eq!(range.len(), 0);
 }
 
+pub fn should_get_no_vertices_with_empty_id_range<D: Datastore>(datastore: &D) {
+    create_sequential_vertices(datastore);
+    let range = datastore
+        .get_vertices(RangeVertexQuery::new().high_id(Uuid::from_u128(1)).into())
+        .unwrap();
+    assert_eq!(range.len(), 0);
+    let range = datastore
+        .get_vertices(
+            RangeVertexQuery::new()
+                .start_id(Uuid::from_u128(8))
+                .high_id(Uuid::from_u128(8))
+                .into(),
+        )
+        .unwrap();
+    assert_eq!(range.len(), 0);
+}
+
+pub fn should_get_vertices_with_partial_id_range<D: Datastore>(datastore: &D) {
+    create_sequential_vertices(datastore);
+    let range = datastore
+        .get_vertices(RangeVertexQuery::new().high_id(Uuid::from_u128(6)).into())
+        .unwrap();
+    let ids: Vec<Uuid> = range.into_iter().map(|v| v.id).collect();
+    assert_eq!(ids, (1..6).map(Uuid::from_u128).collect::<Vec<Uuid>>());
+}
+
+pub fn should_get_vertices_after_start_id<D: Datastore>(datastore: &D) {
+    create_sequential_vertices(datastore);
+    let range = datastore
+        .get_vertices(RangeVertexQuery::new().start_id(Uuid::from_u128(3)).into())
+        .unwrap();
+    let ids: Vec<Uuid> = range.into_iter().map(|v| v.id).collect();
+    assert_eq!(ids, (4..=10).map(Uuid::from_u128).collect::<Vec<Uuid>>());
+
+    // The start ID doesn't have to exist
+    let range = datastore
+        .get_vertices(
+            RangeVertexQuery::new()
+                .start_id(Uuid::from_u128(0))
+                .high_id(Uuid::from_u128(3))
+                .into(),
+        )
+        .unwrap();
+    let ids: Vec<Uuid> = range.into_iter().map(|v| v.id).collect();
+    assert_eq!(ids, vec![Uuid::from_u128(1), Uuid::from_u128(2)]);
+}
+
+pub fn should_get_vertices_with_full_id_range<D: Datastore>(datastore: &D) {
+    create_sequential_vertices(datastore);
+    let range = datastore
+        .get_vertices(RangeVertexQuery::new().high_id(Uuid::from_u128(100)).into())
+        .unwrap();
+    let ids: Vec<Uuid> = range.into_iter().map(|v| v.id).collect();
+    assert_eq!(ids, (1..=10).map(Uuid::from_u128).collect::<Vec<Uuid>>());
+}
+
 pub fn should_get_single_vertex<D: Datastore>(datastore: &D) {
     let vertex_t = models::Identifier::new("test_vertex_type").unwrap();
     let vertex = models::Vertex::new(vertex_t);
@@ -199,6 +282,26 @@ pub fn should_get_a_vertex_count<D: Datastore>(datastore: &D) {
     assert!(count >= 1);
 }
 
+pub fn should_check_vertices_exist<D: Datastore>(datastore: &D) {
+    let inserted_ids = create_vertices(datastore);
+    let missing_ids = [
+        Uuid::default(),
+        models::Vertex::new(models::Identifier::new("test_vertex_type").unwrap()).id,
+    ];
+    let ids = vec![
+        missing_ids[0],
+        inserted_ids[2],
+        inserted_ids[0],
+        missing_ids[1],
+        inserted_ids[2],
+    ];
+    assert_eq!(
+        datastore.vertices_exist(&ids).unwrap(),
+        vec![false, true, true, false, true]
+    );
+    assert_eq!(datastore.vertices_exist(&[]).unwrap(), Vec::<bool>::new());
+}
+
 fn create_vertices<D: Datastore>(datastore: &D) -> Vec<Uuid> {
     let t = models::Identifier::new("test_vertex_type").unwrap();
 
@@ -218,3 +321,11 @@ fn create_vertices<D: Datastore>(datastore: &D) -> Vec<Uuid> {
     vertex_ids.sort();
     vertex_ids
 }
+
+fn create_sequential_vertices<D: Datastore>(datastore: &D) {
+    let t = models::Identifier::new("test_vertex_type").unwrap();
+    for i in 1..=10 {
+        let vertex = models::Vertex::with_id(Uuid::from_u128(i), t.clone());
+        datastore.create_vertex(&vertex).unwrap();
+    }
+}
@@ -1,6 +1,9 @@
 use crate::errors::{Error, Result};
 use crate::models;
 use crate::models::{EdgeQueryExt, VertexQueryExt};
+use chrono::offset::Utc;
+use chrono::Duration;
+use std::collections::HashSet;
 use std::vec::Vec;
 use uuid::Uuid;
 
@@ -56,10 +59,31 @@ pub trait Datastore {
 
     /// Gets a range of vertices specified by a query.
     ///
+    /// Every datastore returns the same vertices, in the same order, for
+    /// range and specific queries: range queries return vertices in
+    /// ascending order of ID, starting after `start_id` if it's set, and
+    /// specific queries return them in the order their IDs were given,
+    /// skipping any that don't exist. The order of vertices returned
+    /// by piped queries is unspecified.
+    ///
     /// # Arguments
     /// * `q`: The query to run.
     fn get_vertices(&self, q: models::VertexQuery) -> Result<Vec<models::Vertex>>;
 
+    /// Checks which of a set of vertices exist. Returns whether each vertex
+    /// exists, in the same order as `ids`.
+    ///
+    /// # Arguments
+    /// * `ids`: The IDs of the vertices to check.
+    fn vertices_exist(&self, ids: &[Uuid]) -> Result<Vec<bool>> {
+        let existing_ids: HashSet<Uuid> = self
+            .get_vertices(models::SpecificVertexQuery::new(ids.to_vec()).into())?
+            .into_iter()
+            .map(|vertex| vertex.id)
+            .collect();
+        Ok(ids.iter().map(|id| existing_ids.contains(id)).collect())
+    }
+
     /// Deletes existing vertices specified by a query.
     ///
     /// # Arguments
@@ -99,6 +123,34 @@ pub trait Datastore {
     fn get_edge_count(&self, id: Uuid, t: Option<&models::Identifier>, direction: models::EdgeDirection)
         -> Result<u64>;
 
+    /// Gets edges updated within a window of time ending now, across every
+    /// vertex. This pipes every vertex into an edge query, so each vertex is
+    /// read; datastores with datetime-ordered edge ranges then only scan the
+    /// recent end of each vertex's edges.
+    ///
+    /// # Arguments
+    /// * `t`: Only get edges of a specified type.
+    /// * `within`: How long ago the oldest returned edge may have been
+    ///   updated.
+    /// * `limit`: Limits the number of edges to get.
+    fn get_recent_edges(
+        &self,
+        t: Option<&models::Identifier>,
+        within: Duration,
+        limit: u32,
+    ) -> Result<Vec<models::Edge>> {
+        let now = Utc::now();
+        let mut q = models::RangeVertexQuery::new()
+            .outbound()
+            .low(now - within)
+            .high(now)
+            .limit(limit);
+        if let Some(t) = t {
+            q = q.t(t.clone());
+        }
+        self.get_edges(q.into())
+    }
+
     /// Gets vertex properties.
     ///
     /// # Arguments
@@ -176,6 +228,97 @@ pub trait Datastore {
         Ok(())
     }
 
+    /// Checks a bulk insert request for problems without writing anything.
+    /// Every problem found is returned, rather than just the first; if the
+    /// returned list is empty, the request is valid. References to vertices
+    /// and edges that are inserted as part of the same request are
+    /// considered valid.
+    ///
+    /// # Arguments
+    /// * `items`: The items to validate.
+    fn validate_bulk_insert(&self, items: &[models::BulkInsertItem]) -> Result<Vec<models::BulkValidationError>> {
+        let mut inserted_vertex_ids = HashSet::new();
+        let mut inserted_edge_keys = HashSet::new();
+        for item in items {
+            match item {
+                models::BulkInsertItem::Vertex(vertex) => {
+                    inserted_vertex_ids.insert(vertex.id);
+                }
+                models::BulkInsertItem::Edge(edge_key) => {
+                    inserted_edge_keys.insert(edge_key.clone());
+                }
+                _ => {}
+            }
+        }
+
+        let mut referenced_vertex_ids: Vec<Uuid> = items
+            .iter()
+            .flat_map(|item| match item {
+                models::BulkInsertItem::Edge(edge_key) => vec![edge_key.outbound_id, edge_key.inbound_id],
+                models::BulkInsertItem::VertexProperty(id, _, _) => vec![*id],
+                _ => vec![],
+            })
+            .filter(|id| !inserted_vertex_ids.contains(id))
+            .collect();
+        referenced_vertex_ids.sort_unstable();
+        referenced_vertex_ids.dedup();
+        let existing_vertex_ids: HashSet<Uuid> = referenced_vertex_ids
+            .iter()
+            .zip(self.vertices_exist(&referenced_vertex_ids)?)
+            .filter_map(|(id, exists)| if exists { Some(*id) } else { None })
+            .collect();
+
+        let vertex_exists = |id: Uuid| inserted_vertex_ids.contains(&id) || existing_vertex_ids.contains(&id);
+        let edge_exists = |edge_key: &models::EdgeKey| -> Result<bool> {
+            Ok(inserted_edge_keys.contains(edge_key)
+                || !self
+                    .get_edges(models::SpecificEdgeQuery::single(edge_key.clone()).into())?
+                    .is_empty())
+        };
+
+        let mut errors = Vec::new();
+
+        for (index, item) in items.iter().enumerate() {
+            let (identifiers, vertex_ids, edge_key) = match item {
+                models::BulkInsertItem::Vertex(vertex) => (vec![&vertex.t], vec![], None),
+                models::BulkInsertItem::Edge(edge_key) => {
+                    (vec![&edge_key.t], vec![edge_key.outbound_id, edge_key.inbound_id], None)
+                }
+                models::BulkInsertItem::VertexProperty(id, name, _) => (vec![name], vec![*id], None),
+                models::BulkInsertItem::EdgeProperty(edge_key, name, _) => {
+                    (vec![&edge_key.t, name], vec![], Some(edge_key))
+                }
+            };
+
+            for identifier in identifiers {
+                // Identifiers can bypass validation, e.g. when deserialized
+                if models::Identifier::new(identifier.as_str()).is_err() {
+                    errors.push(models::BulkValidationError::InvalidIdentifier {
+                        index,
+                        identifier: identifier.clone(),
+                    });
+                }
+            }
+
+            for id in vertex_ids {
+                if !vertex_exists(id) {
+                    errors.push(models::BulkValidationError::MissingVertex { index, id });
+                }
+            }
+
+            if let Some(edge_key) = edge_key {
+                if !edge_exists(edge_key)? {
+                    errors.push(models::BulkValidationError::MissingEdge {
+                        index,
+                        key: edge_key.clone(),
+                    });
+                }
+            }
+        }
+
+        Ok(errors)
+    }
+
     // Enables indexing on a specified property. When indexing is enabled on a
     // property, it's possible to query on its presence and values.
     //
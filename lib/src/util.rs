@@ -4,6 +4,7 @@
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
 use std::io::{Cursor, Error as IoError, Read, Write};
+use std::string::FromUtf8Error;
 use std::{i32, i64, str, u8};
 
 use crate::errors::{ValidationError, ValidationResult};
@@ -30,12 +31,32 @@ lazy_static! {
 
 /// A byte-serializable value, frequently employed in the keys of key/value
 /// store.
+///
+/// Range queries rely on the byte ordering of built keys, so each component
+/// is encoded such that comparing the bytes compares the values as
+/// described on each variant.
 pub enum Component<'a> {
+    /// Ordered by the UUID's raw 16 bytes.
     Uuid(Uuid),
+    /// Ordered by the string's bytes. Since it isn't delimited, this must be
+    /// the last component of a key.
     FixedLengthString(&'a str),
+    /// Ordered by length, then by bytes. The length prefix means no
+    /// identifier's encoding is a prefix of another's, so keys sharing an
+    /// identifier are contiguous, and ordered by the components after it.
     Identifier(&'a models::Identifier),
+    /// Ordered from the latest to the earliest datetime, so that ranges
+    /// iterate newest first. Datetimes after `MAX_DATETIME`, or more than
+    /// `i64::MAX` nanoseconds before it, are clamped to those bounds.
     DateTime(DateTime<Utc>),
+    /// Ordered from the highest to the lowest number, so that, like
+    /// datetimes, later sequence numbers come first.
+    Sequence(u64),
+    /// Ordered by a hash of the value, so only equality is meaningful.
     Json(&'a models::Json),
+    /// Ordered from the lowest to the highest number. Negative and positive
+    /// zero are encoded the same. NaN must not be used.
+    Number(f64),
 }
 
 impl<'a> Component<'a> {
@@ -50,7 +71,9 @@ impl<'a> Component<'a> {
             Component::FixedLengthString(s) => s.len(),
             Component::Identifier(t) => t.0.len() + 1,
             Component::DateTime(_) => 8,
+            Component::Sequence(_) => 8,
             Component::Json(_) => 8,
+            Component::Number(_) => 8,
         }
     }
 
@@ -64,14 +87,24 @@ impl<'a> Component<'a> {
             }
             Component::DateTime(datetime) => {
                 let time_to_end = nanos_since_epoch(&MAX_DATETIME) - nanos_since_epoch(&datetime);
-                cursor.write_u64::<BigEndian>(time_to_end)
+                cursor.write_u64::<BigEndian>(time_to_end.clamp(0, i128::from(i64::MAX)) as u64)
             }
+            Component::Sequence(seq) => cursor.write_u64::<BigEndian>(u64::MAX - seq),
             Component::Json(json) => {
                 let mut hasher = DefaultHasher::new();
                 json.hash(&mut hasher);
                 let hash = hasher.finish();
                 cursor.write_u64::<BigEndian>(hash)
             }
+            Component::Number(n) => {
+                debug_assert!(!n.is_nan());
+                // Adding zero turns negative zero into positive zero. Then,
+                // flipping the sign bit of positive numbers, and every bit of
+                // negative ones, orders the bits like the numbers.
+                let bits = (n + 0.0).to_bits();
+                let bits = if bits >> 63 == 0 { bits | (1 << 63) } else { !bits };
+                cursor.write_u64::<BigEndian>(bits)
+            }
         }
     }
 }
@@ -97,9 +130,9 @@ pub fn build(components: &[Component]) -> Vec<u8> {
 ///
 /// # Arguments
 /// * `datetime`: The datetime to convert.
-fn nanos_since_epoch(datetime: &DateTime<Utc>) -> u64 {
-    let timestamp = datetime.timestamp() as u64;
-    let nanoseconds = u64::from(datetime.timestamp_subsec_nanos());
+fn nanos_since_epoch(datetime: &DateTime<Utc>) -> i128 {
+    let timestamp = i128::from(datetime.timestamp());
+    let nanoseconds = i128::from(datetime.timestamp_subsec_nanos());
     timestamp * 1_000_000_000 + nanoseconds
 }
 
@@ -133,14 +166,15 @@ pub fn read_identifier<T: AsRef<[u8]>>(cursor: &mut Cursor<T>) -> models::Identi
     }
 }
 
-/// Reads a fixed-length string from bytes.
+/// Reads a fixed-length string from bytes. Returns an error if the bytes
+/// aren't valid UTF-8.
 ///
 /// # Arguments
 /// * `cursor`: The bytes to read from.
-pub fn read_fixed_length_string<T: AsRef<[u8]>>(cursor: &mut Cursor<T>) -> String {
-    let mut buf = String::new();
-    cursor.read_to_string(&mut buf).unwrap();
-    buf
+pub fn read_fixed_length_string<T: AsRef<[u8]>>(cursor: &mut Cursor<T>) -> Result<String, FromUtf8Error> {
+    let mut buf = Vec::new();
+    cursor.read_to_end(&mut buf).unwrap();
+    String::from_utf8(buf)
 }
 
 /// Reads a datetime from bytes.
@@ -153,6 +187,24 @@ pub fn read_datetime<T: AsRef<[u8]>>(cursor: &mut Cursor<T>) -> DateTime<Utc> {
     *MAX_DATETIME - Duration::nanoseconds(time_to_end as i64)
 }
 
+/// Reads a sequence number from bytes.
+///
+/// # Arguments
+/// * `cursor`: The bytes to read from.
+pub fn read_sequence<T: AsRef<[u8]>>(cursor: &mut Cursor<T>) -> Result<u64, IoError> {
+    Ok(u64::MAX - cursor.read_u64::<BigEndian>()?)
+}
+
+/// Reads a number from bytes.
+///
+/// # Arguments
+/// * `cursor`: The bytes to read from.
+pub fn read_number<T: AsRef<[u8]>>(cursor: &mut Cursor<T>) -> f64 {
+    let bits = cursor.read_u64::<BigEndian>().unwrap();
+    let bits = if bits >> 63 == 1 { bits & !(1 << 63) } else { !bits };
+    f64::from_bits(bits)
+}
+
 pub fn read_u64<T: AsRef<[u8]>>(cursor: &mut Cursor<T>) -> u64 {
     cursor.read_u64::<BigEndian>().unwrap()
 }
@@ -191,17 +243,151 @@ pub fn next_uuid(uuid: Uuid) -> ValidationResult<Uuid> {
 
 #[cfg(test)]
 mod tests {
-    use super::{generate_uuid_v1, nanos_since_epoch, next_uuid};
-    use chrono::{DateTime, NaiveDateTime, Utc};
+    use super::{
+        build, generate_uuid_v1, nanos_since_epoch, next_uuid, read_datetime, read_identifier, read_number,
+        read_sequence, read_uuid, Component, MAX_DATETIME,
+    };
+    use crate::models::Identifier;
+    use chrono::{DateTime, Duration, NaiveDateTime, Utc};
     use core::str::FromStr;
+    use std::io::Cursor;
     use uuid::Uuid;
 
+    // A small xorshift generator, so the ordering tests cover many inputs
+    // while staying reproducible
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn datetime(&mut self) -> DateTime<Utc> {
+            // Spans well before the epoch through `MAX_DATETIME`
+            let nanos = (self.next() % (i64::MAX as u64)) as i64;
+            *MAX_DATETIME - Duration::nanoseconds(nanos)
+        }
+
+        fn uuid(&mut self) -> Uuid {
+            let bits = (u128::from(self.next()) << 64) | u128::from(self.next());
+            Uuid::from_u128(bits)
+        }
+
+        fn identifier(&mut self) -> Identifier {
+            let len = (self.next() % 4) as usize;
+            let s: String = (0..len).map(|_| (b'a' + (self.next() % 3) as u8) as char).collect();
+            Identifier::new(s).unwrap()
+        }
+    }
+
     #[test]
     fn should_generate_nanos_since_epoch() {
         let datetime = DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(61, 62), Utc);
         assert_eq!(nanos_since_epoch(&datetime), 61000000062);
     }
 
+    #[test]
+    fn should_order_datetimes_newest_first() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..10_000 {
+            let (a, b) = (rng.datetime(), rng.datetime());
+            let (a_bytes, b_bytes) = (build(&[Component::DateTime(a)]), build(&[Component::DateTime(b)]));
+            assert_eq!(a_bytes.cmp(&b_bytes), b.cmp(&a));
+            assert_eq!(read_datetime(&mut Cursor::new(a_bytes)), a);
+        }
+    }
+
+    #[test]
+    fn should_clamp_out_of_range_datetimes() {
+        let after_max = *MAX_DATETIME + Duration::seconds(1);
+        assert_eq!(
+            build(&[Component::DateTime(after_max)]),
+            build(&[Component::DateTime(*MAX_DATETIME)])
+        );
+
+        let earliest = *MAX_DATETIME - Duration::nanoseconds(i64::MAX);
+        let before_earliest = earliest - Duration::days(1);
+        assert_eq!(
+            build(&[Component::DateTime(before_earliest)]),
+            build(&[Component::DateTime(earliest)])
+        );
+    }
+
+    #[test]
+    fn should_order_sequences_highest_first() {
+        let mut rng = Rng(0x6a09_e667_f3bc_c908);
+        for _ in 0..10_000 {
+            let (a, b) = (rng.next(), rng.next());
+            let (a_bytes, b_bytes) = (build(&[Component::Sequence(a)]), build(&[Component::Sequence(b)]));
+            assert_eq!(a_bytes.cmp(&b_bytes), b.cmp(&a));
+            assert_eq!(read_sequence(&mut Cursor::new(a_bytes)).unwrap(), a);
+        }
+    }
+
+    #[test]
+    fn should_order_numbers_lowest_first() {
+        let mut rng = Rng(0xbb67_ae85_84ca_a73b);
+        let mut numbers = vec![
+            0.0,
+            -0.0,
+            1.0,
+            -1.0,
+            f64::MIN,
+            f64::MAX,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+        ];
+        while numbers.len() < 10_000 {
+            let n = f64::from_bits(rng.next());
+            if !n.is_nan() {
+                numbers.push(n);
+                numbers.push((rng.next() % 1000) as f64 - 500.0);
+            }
+        }
+
+        for pair in numbers.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            let (a_bytes, b_bytes) = (build(&[Component::Number(a)]), build(&[Component::Number(b)]));
+            assert_eq!(Some(a_bytes.cmp(&b_bytes)), a.partial_cmp(&b));
+            assert_eq!(read_number(&mut Cursor::new(a_bytes)), a);
+        }
+    }
+
+    #[test]
+    fn should_order_uuids_by_bytes() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..10_000 {
+            let (a, b) = (rng.uuid(), rng.uuid());
+            let (a_bytes, b_bytes) = (build(&[Component::Uuid(a)]), build(&[Component::Uuid(b)]));
+            assert_eq!(a_bytes.cmp(&b_bytes), a.as_bytes().cmp(b.as_bytes()));
+            assert_eq!(read_uuid(&mut Cursor::new(a_bytes)), a);
+        }
+    }
+
+    #[test]
+    fn should_order_by_identifier_then_following_components() {
+        let mut rng = Rng(0xd1b5_4a32_d192_ed03);
+        for _ in 0..10_000 {
+            let (a_t, b_t) = (rng.identifier(), rng.identifier());
+            let (a_dt, b_dt) = (rng.datetime(), rng.datetime());
+            let a_bytes = build(&[Component::Identifier(&a_t), Component::DateTime(a_dt)]);
+            let b_bytes = build(&[Component::Identifier(&b_t), Component::DateTime(b_dt)]);
+
+            let expected = (a_t.as_str().len(), a_t.as_str(), b_dt).cmp(&(b_t.as_str().len(), b_t.as_str(), a_dt));
+            assert_eq!(a_bytes.cmp(&b_bytes), expected);
+
+            let a_prefix = build(&[Component::Identifier(&a_t)]);
+            assert_eq!(b_bytes.starts_with(&a_prefix), a_t == b_t);
+
+            let mut cursor = Cursor::new(a_bytes);
+            assert_eq!(read_identifier(&mut cursor), a_t);
+            assert_eq!(read_datetime(&mut cursor), a_dt);
+        }
+    }
+
     #[test]
     fn should_generate_new_uuid_v1() {
         let first = generate_uuid_v1();
@@ -15,6 +15,11 @@ pub enum Error {
     // When the input argument is valid JSON, but invalid for plugin-specific
     // reasons.
     InvalidArgument(String),
+    /// A map operation failed on a vertex.
+    Map {
+        id: uuid::Uuid,
+        source: Box<Error>,
+    },
     /// Any other kind of error.
     Other(Box<dyn StdError + Send + Sync>),
 }
@@ -25,6 +30,7 @@ impl StdError for Error {
             Error::Json(ref err) => Some(&*err),
             Error::IndraDB(ref err) => Some(&*err),
             Error::Other(ref err) => Some(&**err),
+            Error::Map { ref source, .. } => Some(&**source),
             _ => None,
         }
     }
@@ -37,6 +43,7 @@ impl fmt::Display for Error {
             Error::IndraDB(ref err) => write!(f, "IndraDB error: {}", err),
             Error::InvalidArgument(ref msg) => write!(f, "{}", msg),
             Error::Other(ref err) => write!(f, "{}", err),
+            Error::Map { ref id, ref source } => write!(f, "failed to map vertex {}: {}", id, source),
         }
     }
 }
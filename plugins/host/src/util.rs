@@ -1,12 +1,35 @@
-use std::cmp::max;
-use std::sync::{Arc, Mutex};
+use std::cmp::{max, min};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::sync::{Arc, Condvar, Mutex};
 
 use crate::errors::Error;
 
+use indradb::VertexQueryExt;
 use threadpool::ThreadPool;
 
 const DEFAULT_NUM_THREADS: usize = 8;
 const DEFAULT_QUERY_LIMIT: u32 = u16::max_value() as u32;
+const DEFAULT_MAX_INFLIGHT_MAPS: usize = DEFAULT_QUERY_LIMIT as usize;
+
+/// The largest number of vertices pulled at a time, regardless of what a
+/// mapper's `query_limit` requests. Each pull is held in memory in full, so
+/// larger pages mostly cost memory rather than saving time.
+pub const MAX_QUERY_LIMIT: u32 = 1_048_576;
+
+/// How pulled vertices are divided into tasks for the thread pool.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Partitioning {
+    /// Each vertex is mapped in its own task, so that threads pick up work
+    /// one vertex at a time.
+    PerVertex,
+    /// Each pulled page of vertices is split into one bucket per thread by a
+    /// hash of the vertex ID, and each bucket is mapped in a single task.
+    /// This cuts per-vertex scheduling overhead and spreads expensive
+    /// vertices across threads statistically. It changes how work is
+    /// divided, but not which vertices are mapped.
+    Hashed,
+}
 
 /// Trait for running an operation on all vertices in a datastore.
 pub trait VertexMapper: Send + Sync + 'static {
@@ -14,16 +37,305 @@ pub trait VertexMapper: Send + Sync + 'static {
     fn num_threads(&self) -> usize {
         DEFAULT_NUM_THREADS
     }
-    /// How many vertices to pull at a time.
+    /// How many vertices to pull at a time. This is capped to
+    /// `MAX_QUERY_LIMIT`.
     fn query_limit(&self) -> u32 {
         DEFAULT_QUERY_LIMIT
     }
+    /// The maximum number of map operations that can be pending before
+    /// pulling more vertices. Once this many are pending, vertex pulling is
+    /// paused until the thread pool catches up.
+    fn max_inflight_maps(&self) -> usize {
+        DEFAULT_MAX_INFLIGHT_MAPS
+    }
     /// If specified, only vertices of the specified type will be mapped.
     fn t_filter(&self) -> Option<indradb::Identifier> {
         None
     }
+    /// If specified, only vertices with IDs in the range `[start_id, end_id)`
+    /// will be mapped. This allows for partitioning a job by vertex ID.
+    fn id_range(&self) -> Option<(uuid::Uuid, uuid::Uuid)> {
+        None
+    }
+    /// If specified, at most this many vertices are mapped, after which the
+    /// operation stops and finalizes as if it had reached the end. This is
+    /// useful for sampling or quick estimates over large graphs. Vertices
+    /// are mapped in order of ID, so this isn't a random sample.
+    fn max_vertices(&self) -> Option<u64> {
+        None
+    }
+    /// How pulled vertices are divided into tasks. By default, each vertex
+    /// is its own task.
+    fn partitioning(&self) -> Partitioning {
+        Partitioning::PerVertex
+    }
+    /// Properties to fetch alongside the vertices. If non-empty, the
+    /// properties are read in batches for each pulled set of vertices, and
+    /// passed to `map_with_properties` rather than `map`.
+    fn prefetch_properties(&self) -> Vec<indradb::Identifier> {
+        Vec::new()
+    }
+    /// Whether errors from map operations should be wrapped in `Error::Map`,
+    /// which names the vertex that was being mapped. By default, errors are
+    /// returned as-is.
+    fn keyed_errors(&self) -> bool {
+        false
+    }
     /// The map operation.
     fn map(&self, vertex: indradb::Vertex) -> Result<(), Error>;
+    /// The map operation when `prefetch_properties` is non-empty. Only
+    /// prefetched properties that are set on the vertex are included. By
+    /// default, this ignores the properties and calls `map`.
+    fn map_with_properties(&self, vertex_properties: indradb::VertexProperties) -> Result<(), Error> {
+        self.map(vertex_properties.vertex)
+    }
+    /// If specified, a checkpoint is taken after every this many pulls of
+    /// vertices. Taking a checkpoint waits for all pending map operations to
+    /// finish, and then calls `checkpoint`.
+    fn checkpoint_every(&self) -> Option<u32> {
+        None
+    }
+    /// Called on the calling thread when a checkpoint is taken. Every vertex
+    /// up to and including `last_id` has been mapped by this point, so the
+    /// mapper can persist its partial results alongside `last_id`, and later
+    /// pick up from there via `map_resume`. This only produces the same
+    /// results as an uninterrupted run if the mapper's aggregation is
+    /// associative. By default, this does nothing.
+    fn checkpoint(&self, _last_id: uuid::Uuid) {}
+    /// Called once on the calling thread after every vertex has been mapped
+    /// successfully. This can be used to write computed results back to the
+    /// datastore. By default, this does nothing.
+    fn finalize(&self, _datastore: &(dyn indradb::Datastore + Send + Sync + 'static)) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// A mapper that collects the distinct values emitted for each vertex. Values
+/// are deduplicated as they're emitted, so the cost of building the set is
+/// linear in the number of emitted values.
+pub struct DistinctValues<F> {
+    emit: F,
+    values: Mutex<HashSet<String>>,
+}
+
+impl<F> DistinctValues<F>
+where
+    F: Fn(indradb::Vertex) -> Result<Vec<serde_json::Value>, Error> + Send + Sync + 'static,
+{
+    /// Creates a new distinct values mapper.
+    ///
+    /// # Arguments
+    /// * `emit`: Returns the values to collect for a vertex.
+    pub fn new(emit: F) -> Self {
+        Self {
+            emit,
+            values: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Gets the distinct values collected so far, in no particular order.
+    pub fn values(&self) -> Vec<serde_json::Value> {
+        let values = self.values.lock().unwrap();
+        // Serialized values always deserialize again
+        values
+            .iter()
+            .map(|value| serde_json::from_str(value).unwrap())
+            .collect()
+    }
+}
+
+impl<F> VertexMapper for DistinctValues<F>
+where
+    F: Fn(indradb::Vertex) -> Result<Vec<serde_json::Value>, Error> + Send + Sync + 'static,
+{
+    fn map(&self, vertex: indradb::Vertex) -> Result<(), Error> {
+        let emitted = (self.emit)(vertex)?;
+        // JSON values aren't hashable, so they're keyed by their serialized
+        // form. Object keys are sorted, so equal values serialize equally.
+        let mut values = self.values.lock().unwrap();
+        for value in emitted {
+            values.insert(value.to_string());
+        }
+        Ok(())
+    }
+}
+
+/// A mapper that groups the values emitted for each vertex by key, and
+/// reduces each group independently once mapping is done. This is useful for
+/// group-by aggregations, e.g. counting vertices per type. Values can be of
+/// any type, so in-process aggregations don't need to go through JSON.
+pub struct GroupBy<F, R, V = serde_json::Value> {
+    map_keyed: F,
+    reduce_keyed: R,
+    combine: Option<Combiner<V>>,
+    groups: Mutex<HashMap<String, Vec<V>>>,
+}
+
+type Combiner<V> = Box<dyn Fn(V, V) -> Result<V, Error> + Send + Sync + 'static>;
+
+/// A group-by mapper over JSON values.
+pub type JsonGroupBy<F, R> = GroupBy<F, R, serde_json::Value>;
+
+impl<F, R, V> GroupBy<F, R, V>
+where
+    F: Fn(indradb::Vertex) -> Result<(String, V), Error> + Send + Sync + 'static,
+    R: Fn(&str, Vec<V>) -> Result<V, Error> + Send + Sync + 'static,
+    V: Send + 'static,
+{
+    /// Creates a new group-by mapper.
+    ///
+    /// # Arguments
+    /// * `map_keyed`: Returns the group key and value for a vertex.
+    /// * `reduce_keyed`: Reduces the values of a group into one value. The
+    ///   values are in no particular order.
+    pub fn new(map_keyed: F, reduce_keyed: R) -> Self {
+        Self {
+            map_keyed,
+            reduce_keyed,
+            combine: None,
+            groups: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets a combiner, which folds each value into its group's value as
+    /// it's mapped, on the thread that mapped it. Each group then holds a
+    /// single pre-aggregated value rather than every mapped value, so
+    /// `reduce_keyed` is only passed one value per group. Values are
+    /// combined in no particular order, so the combiner must be associative
+    /// and commutative, and `reduce_keyed` must accept combined values, e.g.
+    /// summing rather than counting them. By default, values aren't
+    /// combined.
+    ///
+    /// # Arguments
+    /// * `combine`: Combines two values of a group into one.
+    pub fn combine<C>(self, combine: C) -> Self
+    where
+        C: Fn(V, V) -> Result<V, Error> + Send + Sync + 'static,
+    {
+        Self {
+            combine: Some(Box::new(combine)),
+            ..self
+        }
+    }
+
+    /// Reduces the groups collected so far, returning each group's reduced
+    /// value by key. This takes the collected values, so the groups are
+    /// empty afterwards.
+    pub fn reduce(&self) -> Result<HashMap<String, V>, Error> {
+        let groups = std::mem::take(&mut *self.groups.lock().unwrap());
+        groups
+            .into_iter()
+            .map(|(key, values)| {
+                let value = (self.reduce_keyed)(&key, values)?;
+                Ok((key, value))
+            })
+            .collect()
+    }
+}
+
+impl<F, R> JsonGroupBy<F, R>
+where
+    F: Fn(indradb::Vertex) -> Result<(String, serde_json::Value), Error> + Send + Sync + 'static,
+    R: Fn(&str, Vec<serde_json::Value>) -> Result<serde_json::Value, Error> + Send + Sync + 'static,
+{
+    /// Like `reduce`, but returns the reduced values as a JSON object.
+    pub fn reduce_json(&self) -> Result<serde_json::Map<String, serde_json::Value>, Error> {
+        Ok(self.reduce()?.into_iter().collect())
+    }
+}
+
+/// A value along with the weight it carries, for aggregations whose partial
+/// results have to be combined in proportion to how much each one covers.
+/// For example, the mean of some values is weighted by how many values
+/// there are, so that combining the means of unevenly sized groups of
+/// values still gives the mean of all of them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Weighted<T> {
+    /// The value.
+    pub value: T,
+    /// The weight of the value.
+    pub weight: f64,
+}
+
+impl<T> Weighted<T> {
+    /// Creates a new weighted value.
+    ///
+    /// # Arguments
+    /// * `value`: The value.
+    /// * `weight`: The weight of the value.
+    pub fn new(value: T, weight: f64) -> Self {
+        Self { value, weight }
+    }
+}
+
+impl Weighted<f64> {
+    /// Combines two weighted means into the mean of everything they cover,
+    /// weighted by their combined weight. If the combined weight is zero,
+    /// the mean is zero.
+    ///
+    /// # Arguments
+    /// * `other`: The other weighted mean.
+    pub fn combine_mean(self, other: Self) -> Self {
+        let weight = self.weight + other.weight;
+        if weight == 0.0 {
+            return Self::new(0.0, 0.0);
+        }
+        Self::new((self.value * self.weight + other.value * other.weight) / weight, weight)
+    }
+
+    /// Combines weighted means into the mean of everything they cover. If
+    /// the total weight is zero, the mean is zero.
+    ///
+    /// # Arguments
+    /// * `means`: The weighted means.
+    pub fn mean<I: IntoIterator<Item = Self>>(means: I) -> Self {
+        means.into_iter().fold(Self::new(0.0, 0.0), Self::combine_mean)
+    }
+}
+
+/// A group-by mapper over weighted values.
+pub type WeightedGroupBy<F, R, V> = GroupBy<F, R, Weighted<V>>;
+
+/// The reducer of a weighted mean group-by mapper.
+pub type WeightedMeanReducer = fn(&str, Vec<Weighted<f64>>) -> Result<Weighted<f64>, Error>;
+
+impl<F> WeightedGroupBy<F, WeightedMeanReducer, f64>
+where
+    F: Fn(indradb::Vertex) -> Result<(String, Weighted<f64>), Error> + Send + Sync + 'static,
+{
+    /// Creates a group-by mapper that computes the weighted mean of each
+    /// group. Values are combined as they're mapped, so each group only
+    /// holds a single running mean.
+    ///
+    /// # Arguments
+    /// * `map_keyed`: Returns the group key and weighted value for a vertex.
+    ///   To compute a plain mean, give every value a weight of 1.
+    pub fn weighted_mean(map_keyed: F) -> Self {
+        let reduce_keyed: WeightedMeanReducer = |_, means| Ok(Weighted::mean(means));
+        Self::new(map_keyed, reduce_keyed).combine(|a: Weighted<f64>, b| Ok(a.combine_mean(b)))
+    }
+}
+
+impl<F, R, V> VertexMapper for GroupBy<F, R, V>
+where
+    F: Fn(indradb::Vertex) -> Result<(String, V), Error> + Send + Sync + 'static,
+    R: Fn(&str, Vec<V>) -> Result<V, Error> + Send + Sync + 'static,
+    V: Send + 'static,
+{
+    fn map(&self, vertex: indradb::Vertex) -> Result<(), Error> {
+        let (key, value) = (self.map_keyed)(vertex)?;
+        let mut groups = self.groups.lock().unwrap();
+        let values = groups.entry(key).or_default();
+        let value = match (&self.combine, values.pop()) {
+            (Some(combine), Some(existing)) => combine(existing, value)?,
+            (_, existing) => {
+                values.extend(existing);
+                value
+            }
+        };
+        values.push(value);
+        Ok(())
+    }
 }
 
 /// Runs an operation on all vertices in the datastore.
@@ -36,23 +348,318 @@ pub fn map<M: VertexMapper>(
     datastore: Arc<dyn indradb::Datastore + Send + Sync + 'static>,
 ) -> Result<(), Error> {
     let pool = ThreadPool::new(max(mapper.num_threads(), 1));
-    let query_limit = max(mapper.query_limit(), 1);
-    let t_filter = mapper.t_filter();
+    map_from(mapper, datastore, None, &pool)
+}
+
+/// Runs an operation on all vertices in the datastore, using an existing
+/// thread pool rather than creating one. The pool may be shared with other
+/// work: this waits for its own map operations to finish, rather than
+/// joining the pool. `num_threads` is ignored.
+///
+/// # Arguments
+/// * `mapper`: Specified options and the map operation to run.
+/// * `datastore`: The datastore.
+/// * `pool`: The thread pool to run map operations on.
+pub fn map_with_pool<M: VertexMapper>(
+    mapper: Arc<M>,
+    datastore: Arc<dyn indradb::Datastore + Send + Sync + 'static>,
+    pool: &ThreadPool,
+) -> Result<(), Error> {
+    map_from(mapper, datastore, None, pool)
+}
+
+/// Resumes an operation on all vertices in the datastore from a checkpoint.
+/// The mapper should be seeded with the partial results saved at the
+/// checkpoint.
+///
+/// # Arguments
+/// * `mapper`: Specified options and the map operation to run.
+/// * `datastore`: The datastore.
+/// * `from`: The `last_id` of the checkpoint to resume from.
+pub fn map_resume<M: VertexMapper>(
+    mapper: Arc<M>,
+    datastore: Arc<dyn indradb::Datastore + Send + Sync + 'static>,
+    from: uuid::Uuid,
+) -> Result<(), Error> {
+    let pool = ThreadPool::new(max(mapper.num_threads(), 1));
+    map_from(mapper, datastore, Some(from), &pool)
+}
+
+// Tracks the map operations submitted by a single call, so that the call can
+// wait on its own operations without joining a possibly shared pool.
+#[derive(Default)]
+struct Inflight {
+    count: Mutex<usize>,
+    changed: Condvar,
+}
+
+impl Inflight {
+    fn start(self: &Arc<Self>, count: usize) -> InflightGuard {
+        *self.count.lock().unwrap() += count;
+        InflightGuard(self.clone(), count)
+    }
+
+    fn wait_below(&self, limit: usize, last_err: &Mutex<Option<Error>>) {
+        let mut count = self.count.lock().unwrap();
+        while *count >= limit && last_err.lock().unwrap().is_none() {
+            count = self.changed.wait(count).unwrap();
+        }
+    }
+
+    fn wait_idle(&self) {
+        let mut count = self.count.lock().unwrap();
+        while *count > 0 {
+            count = self.changed.wait(count).unwrap();
+        }
+    }
+}
+
+// Marks map operations as finished when dropped, including if a map
+// operation panics.
+struct InflightGuard(Arc<Inflight>, usize);
+
+impl Drop for InflightGuard {
+    fn drop(&mut self) {
+        *self.0.count.lock().unwrap() -= self.1;
+        self.0.changed.notify_all();
+    }
+}
+
+// Submits pulled vertices to the pool according to the mapper's
+// partitioning.
+struct Dispatcher<'a, M: VertexMapper> {
+    mapper: &'a Arc<M>,
+    pool: &'a ThreadPool,
+    inflight: &'a Arc<Inflight>,
+    last_err: &'a Arc<Mutex<Option<Error>>>,
+    partitioning: Partitioning,
+}
+
+impl<'a, M: VertexMapper> Dispatcher<'a, M> {
+    fn dispatch<T: Send + 'static>(
+        &self,
+        items: Vec<T>,
+        id: fn(&T) -> uuid::Uuid,
+        map: fn(&M, T) -> Result<(), Error>,
+    ) {
+        match self.partitioning {
+            Partitioning::PerVertex => {
+                for item in items {
+                    self.execute(vec![item], id, map);
+                }
+            }
+            Partitioning::Hashed => {
+                let num_buckets = max(self.pool.max_count(), 1);
+                let mut buckets: Vec<Vec<T>> = (0..num_buckets).map(|_| Vec::new()).collect();
+                for item in items {
+                    buckets[bucket(id(&item), num_buckets)].push(item);
+                }
+                for bucket in buckets.into_iter().filter(|bucket| !bucket.is_empty()) {
+                    self.execute(bucket, id, map);
+                }
+            }
+        }
+    }
+
+    fn execute<T: Send + 'static>(&self, items: Vec<T>, id: fn(&T) -> uuid::Uuid, map: fn(&M, T) -> Result<(), Error>) {
+        let mapper = self.mapper.clone();
+        let last_err = self.last_err.clone();
+        let guard = self.inflight.start(items.len());
+        self.pool.execute(move || {
+            let _guard = guard;
+            for item in items {
+                if last_err.lock().unwrap().is_some() {
+                    return;
+                }
+                let item_id = id(&item);
+                if let Err(err) = map(&mapper, item) {
+                    record_error(&last_err, keyed_error(&*mapper, item_id, err));
+                }
+            }
+        });
+    }
+}
+
+// Most bits of a v1 UUID rarely vary, so the halves are folded together and
+// mixed with the splitmix64 finalizer before picking a bucket.
+fn bucket(id: uuid::Uuid, num_buckets: usize) -> usize {
+    let bits = id.as_u128();
+    let mut h = (bits >> 64) as u64 ^ bits as u64;
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    h ^= h >> 31;
+    (h % num_buckets as u64) as usize
+}
+
+// Pulls vertices a page at a time, according to a mapper's options.
+struct Pager {
+    query_limit: u32,
+    t_filter: Option<indradb::Identifier>,
+    high_id: Option<uuid::Uuid>,
+    max_vertices: Option<u64>,
+    last_id: Option<uuid::Uuid>,
+    num_vertices: u64,
+    is_done: bool,
+}
+
+impl Pager {
+    fn new<M: VertexMapper>(mapper: &M, from: Option<uuid::Uuid>) -> Self {
+        let id_range = mapper.id_range();
+        // Range queries start after `start_id`, so to include the start of
+        // the ID range, start after the ID before it. There's no ID to start
+        // after if the range starts at the lowest one.
+        let before_start_id =
+            id_range.and_then(|(start_id, _)| start_id.as_u128().checked_sub(1).map(uuid::Uuid::from_u128));
+        Pager {
+            query_limit: effective_query_limit(mapper.query_limit()),
+            t_filter: mapper.t_filter(),
+            high_id: id_range.map(|(_, end_id)| end_id),
+            max_vertices: mapper.max_vertices(),
+            last_id: from.or(before_start_id),
+            num_vertices: 0,
+            is_done: false,
+        }
+    }
+
+    fn for_type(t_filter: Option<indradb::Identifier>) -> Self {
+        Pager {
+            query_limit: DEFAULT_QUERY_LIMIT,
+            t_filter,
+            high_id: None,
+            max_vertices: None,
+            last_id: None,
+            num_vertices: 0,
+            is_done: false,
+        }
+    }
+
+    fn pull(
+        &mut self,
+        datastore: &(dyn indradb::Datastore + Send + Sync + 'static),
+    ) -> Result<Vec<indradb::Vertex>, indradb::Error> {
+        // Don't pull more vertices than are left to map
+        let limit = match self.max_vertices {
+            Some(max_vertices) => min(u64::from(self.query_limit), max_vertices - self.num_vertices) as u32,
+            None => self.query_limit,
+        };
+
+        let q = indradb::RangeVertexQuery {
+            limit,
+            t: self.t_filter.clone(),
+            start_id: self.last_id,
+            high_id: self.high_id,
+        };
+
+        let vertices = datastore.get_vertices(q.into())?;
+
+        let is_exhausted = vertices.len() < limit as usize;
+
+        self.num_vertices += vertices.len() as u64;
+        self.is_done = is_exhausted || self.max_vertices == Some(self.num_vertices);
+        if let Some(last_vertex) = vertices.last() {
+            self.last_id = Some(last_vertex.id);
+        }
+
+        Ok(vertices)
+    }
+}
+
+/// Counts the vertices that an operation would run on, without running it.
+/// Vertices are pulled the same way as in `map`, so `t_filter`, `id_range`,
+/// `max_vertices` and `query_limit` are honored, but neither `map` nor
+/// `finalize` are called.
+///
+/// # Arguments
+/// * `mapper`: Specified options.
+/// * `datastore`: The datastore.
+pub fn count<M: VertexMapper>(
+    mapper: &M,
+    datastore: &(dyn indradb::Datastore + Send + Sync + 'static),
+) -> Result<u64, Error> {
+    let mut pager = Pager::new(mapper, None);
+    while !pager.is_done {
+        pager.pull(datastore)?;
+    }
+    Ok(pager.num_vertices)
+}
+
+/// Writes every vertex as a line of JSON, with its properties inline, e.g.
+/// `{"id":"...","type":"user","properties":{"name":"..."}}`. Vertices are
+/// pulled a page at a time, so memory use doesn't grow with the size of the
+/// datastore. Returns how many vertices were written.
+///
+/// # Arguments
+/// * `datastore`: The datastore.
+/// * `writer`: Where to write the vertices.
+/// * `t_filter`: If specified, only vertices of this type are written.
+pub fn export_ndjson<W: Write>(
+    datastore: &(dyn indradb::Datastore + Send + Sync + 'static),
+    mut writer: W,
+    t_filter: Option<indradb::Identifier>,
+) -> Result<u64, Error> {
+    let mut pager = Pager::for_type(t_filter);
+    while !pager.is_done {
+        let vertices = pager.pull(datastore)?;
+        if vertices.is_empty() {
+            continue;
+        }
+
+        let ids: Vec<uuid::Uuid> = vertices.iter().map(|vertex| vertex.id).collect();
+        let mut props: HashMap<uuid::Uuid, Vec<indradb::NamedProperty>> = datastore
+            .get_all_vertex_properties(indradb::SpecificVertexQuery::new(ids).into())?
+            .into_iter()
+            .map(|vertex_properties| (vertex_properties.vertex.id, vertex_properties.props))
+            .collect();
+
+        for vertex in vertices {
+            let properties: serde_json::Map<String, serde_json::Value> = props
+                .remove(&vertex.id)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|property| (property.name.into_string(), property.value))
+                .collect();
+            let line = serde_json::json!({
+                "id": vertex.id,
+                "type": vertex.t,
+                "properties": properties,
+            });
+            serde_json::to_writer(&mut writer, &line)?;
+            writer.write_all(b"\n").map_err(|err| Error::Other(Box::new(err)))?;
+        }
+    }
+
+    writer.flush().map_err(|err| Error::Other(Box::new(err)))?;
+    Ok(pager.num_vertices)
+}
+
+fn map_from<M: VertexMapper>(
+    mapper: Arc<M>,
+    datastore: Arc<dyn indradb::Datastore + Send + Sync + 'static>,
+    from: Option<uuid::Uuid>,
+    pool: &ThreadPool,
+) -> Result<(), Error> {
+    let max_inflight_maps = max(mapper.max_inflight_maps(), 1);
+    let prefetch_properties = mapper.prefetch_properties();
     let last_err: Arc<Mutex<Option<Error>>> = Arc::new(Mutex::new(None));
-    let mut last_id: Option<uuid::Uuid> = None;
+    let inflight = Arc::new(Inflight::default());
+    let checkpoint_every = mapper.checkpoint_every().map(|every| max(every, 1));
+    let partitioning = mapper.partitioning();
+    let mut pager = Pager::new(&*mapper, from);
+    let mut queries_since_checkpoint: u32 = 0;
 
     loop {
+        // Wait for the pool to catch up before pulling more vertices, so that
+        // the queue of pending map operations doesn't grow without bound.
+        inflight.wait_below(max_inflight_maps, &last_err);
+
         if last_err.lock().unwrap().is_some() {
             break;
         }
 
-        let q = indradb::RangeVertexQuery {
-            limit: query_limit,
-            t: t_filter.clone(),
-            start_id: last_id,
-        };
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("map_batch", start_id = ?pager.last_id, limit = pager.query_limit).entered();
 
-        let vertices = match datastore.get_vertices(q.into()) {
+        let vertices = match pager.pull(&*datastore) {
             Ok(value) => value,
             Err(err) => {
                 *last_err.lock().unwrap() = Some(err.into());
@@ -60,32 +667,915 @@ pub fn map<M: VertexMapper>(
             }
         };
 
-        let is_last_query = vertices.len() < query_limit as usize;
-        if let Some(last_vertex) = vertices.last() {
-            last_id = Some(last_vertex.id);
-        }
+        let dispatcher = Dispatcher {
+            mapper: &mapper,
+            pool,
+            inflight: &inflight,
+            last_err: &last_err,
+            partitioning,
+        };
 
-        for vertex in vertices {
-            let mapper = mapper.clone();
-            let last_err = last_err.clone();
-            pool.execute(move || {
-                if let Err(err) = mapper.map(vertex) {
-                    *last_err.lock().unwrap() = Some(err);
+        if prefetch_properties.is_empty() {
+            dispatcher.dispatch(vertices, |vertex| vertex.id, |mapper, vertex| mapper.map(vertex));
+        } else {
+            let vertex_properties = match prefetch(&*datastore, vertices, &prefetch_properties) {
+                Ok(value) => value,
+                Err(err) => {
+                    *last_err.lock().unwrap() = Some(err.into());
+                    break;
                 }
-            });
+            };
+
+            dispatcher.dispatch(
+                vertex_properties,
+                |vertex_properties| vertex_properties.vertex.id,
+                |mapper, vertex_properties| mapper.map_with_properties(vertex_properties),
+            );
         }
 
-        if is_last_query {
+        if pager.is_done {
             break;
         }
+
+        queries_since_checkpoint += 1;
+        if let (Some(every), Some(last_id)) = (checkpoint_every, pager.last_id) {
+            if queries_since_checkpoint >= every {
+                queries_since_checkpoint = 0;
+                inflight.wait_idle();
+                if last_err.lock().unwrap().is_none() {
+                    mapper.checkpoint(last_id);
+                }
+            }
+        }
     }
 
-    pool.join();
+    inflight.wait_idle();
 
     let mut last_err = last_err.lock().unwrap();
     if last_err.is_some() {
         Err(last_err.take().unwrap())
     } else {
-        Ok(())
+        mapper.finalize(&*datastore)
+    }
+}
+
+// Keeps the first error, so that the error returned is the one that stopped
+// the operation. Map operations still queued after an error are skipped.
+fn record_error(last_err: &Mutex<Option<Error>>, err: Error) {
+    let mut last_err = last_err.lock().unwrap();
+    if last_err.is_none() {
+        *last_err = Some(err);
+    }
+}
+
+fn keyed_error<M: VertexMapper>(mapper: &M, id: uuid::Uuid, err: Error) -> Error {
+    if mapper.keyed_errors() {
+        Error::Map {
+            id,
+            source: Box::new(err),
+        }
+    } else {
+        err
+    }
+}
+
+fn effective_query_limit(query_limit: u32) -> u32 {
+    query_limit.clamp(1, MAX_QUERY_LIMIT)
+}
+
+// Reads the given properties for a set of vertices, issuing one query per
+// property name rather than one per vertex.
+fn prefetch(
+    datastore: &(dyn indradb::Datastore + Send + Sync + 'static),
+    vertices: Vec<indradb::Vertex>,
+    names: &[indradb::Identifier],
+) -> Result<Vec<indradb::VertexProperties>, indradb::Error> {
+    let ids: Vec<uuid::Uuid> = vertices.iter().map(|vertex| vertex.id).collect();
+    let mut props: HashMap<uuid::Uuid, Vec<indradb::NamedProperty>> = HashMap::new();
+
+    for name in names {
+        let q = indradb::SpecificVertexQuery::new(ids.clone()).property(name.clone());
+        for property in datastore.get_vertex_properties(q)? {
+            props
+                .entry(property.id)
+                .or_default()
+                .push(indradb::NamedProperty::new(name.clone(), property.value));
+        }
+    }
+
+    Ok(vertices
+        .into_iter()
+        .map(|vertex| {
+            let vertex_props = props.remove(&vertex.id).unwrap_or_default();
+            indradb::VertexProperties::new(vertex, vertex_props)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{
+        count, effective_query_limit, export_ndjson, map_from, map_with_pool, DistinctValues, GroupBy, Partitioning,
+        VertexMapper, Weighted, MAX_QUERY_LIMIT,
+    };
+    use crate::errors::Error;
+
+    use indradb::{Datastore, VertexQueryExt};
+    use threadpool::ThreadPool;
+
+    struct QueueObservingMapper {
+        pool: ThreadPool,
+        mapped: Mutex<HashSet<uuid::Uuid>>,
+        max_queued: AtomicUsize,
+    }
+
+    impl VertexMapper for QueueObservingMapper {
+        fn num_threads(&self) -> usize {
+            1
+        }
+
+        fn query_limit(&self) -> u32 {
+            10
+        }
+
+        fn max_inflight_maps(&self) -> usize {
+            20
+        }
+
+        fn map(&self, vertex: indradb::Vertex) -> Result<(), Error> {
+            self.max_queued.fetch_max(self.pool.queued_count(), Ordering::SeqCst);
+            self.mapped.lock().unwrap().insert(vertex.id);
+            thread::sleep(Duration::from_micros(100));
+            Ok(())
+        }
+    }
+
+    struct CappedMapper {
+        mapped: AtomicUsize,
+        finalized: AtomicUsize,
+    }
+
+    impl VertexMapper for CappedMapper {
+        fn query_limit(&self) -> u32 {
+            64
+        }
+
+        fn max_vertices(&self) -> Option<u64> {
+            Some(100)
+        }
+
+        fn map(&self, _vertex: indradb::Vertex) -> Result<(), Error> {
+            self.mapped.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn finalize(&self, _datastore: &(dyn Datastore + Send + Sync + 'static)) -> Result<(), Error> {
+            self.finalized.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    struct PartitionedMapper {
+        partitioning: Partitioning,
+        mapped: Mutex<HashSet<uuid::Uuid>>,
+        out_degrees: AtomicU64,
+    }
+
+    impl VertexMapper for PartitionedMapper {
+        fn query_limit(&self) -> u32 {
+            50
+        }
+
+        fn partitioning(&self) -> Partitioning {
+            self.partitioning
+        }
+
+        fn map(&self, vertex: indradb::Vertex) -> Result<(), Error> {
+            assert!(self.mapped.lock().unwrap().insert(vertex.id));
+            self.out_degrees
+                .fetch_add(vertex.id.as_bytes()[15] as u64, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn should_map_the_same_vertices_with_hashed_partitioning() {
+        let datastore = Arc::new(indradb::MemoryDatastore::default());
+        let t = indradb::Identifier::new("test_vertex_type").unwrap();
+        for _ in 0..500 {
+            datastore.create_vertex_from_type(t.clone()).unwrap();
+        }
+
+        let pool = ThreadPool::new(4);
+        let run = |partitioning| {
+            let mapper = Arc::new(PartitionedMapper {
+                partitioning,
+                mapped: Mutex::new(HashSet::new()),
+                out_degrees: AtomicU64::new(0),
+            });
+            map_with_pool(mapper.clone(), datastore.clone(), &pool).unwrap();
+            let mapped = std::mem::take(&mut *mapper.mapped.lock().unwrap());
+            (mapped, mapper.out_degrees.load(Ordering::SeqCst))
+        };
+
+        let (per_vertex_mapped, per_vertex_total) = run(Partitioning::PerVertex);
+        let (hashed_mapped, hashed_total) = run(Partitioning::Hashed);
+        assert_eq!(per_vertex_mapped.len(), 500);
+        assert_eq!(hashed_mapped, per_vertex_mapped);
+        assert_eq!(hashed_total, per_vertex_total);
+    }
+
+    #[test]
+    fn should_cap_mapped_vertices() {
+        let datastore = indradb::MemoryDatastore::default();
+        let t = indradb::Identifier::new("test_vertex_type").unwrap();
+        for _ in 0..1000 {
+            datastore.create_vertex_from_type(t.clone()).unwrap();
+        }
+
+        let mapper = Arc::new(CappedMapper {
+            mapped: AtomicUsize::new(0),
+            finalized: AtomicUsize::new(0),
+        });
+        let pool = ThreadPool::new(4);
+        map_with_pool(mapper.clone(), Arc::new(datastore), &pool).unwrap();
+        assert_eq!(mapper.mapped.load(Ordering::SeqCst), 100);
+        assert_eq!(mapper.finalized.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn should_bound_inflight_maps() {
+        let datastore = indradb::MemoryDatastore::default();
+        let t = indradb::Identifier::new("test_vertex_type").unwrap();
+        for _ in 0..500 {
+            datastore.create_vertex_from_type(t.clone()).unwrap();
+        }
+
+        let pool = ThreadPool::new(1);
+        let mapper = Arc::new(QueueObservingMapper {
+            pool: pool.clone(),
+            mapped: Mutex::new(HashSet::new()),
+            max_queued: AtomicUsize::new(0),
+        });
+
+        map_with_pool(mapper.clone(), Arc::new(datastore), &pool).unwrap();
+        assert_eq!(mapper.mapped.lock().unwrap().len(), 500);
+        // At most one page can be dispatched on top of a nearly full queue
+        assert!(mapper.max_queued.load(Ordering::SeqCst) < 20 + 10);
+    }
+
+    struct PropertySumMapper {
+        datastore: indradb::MemoryDatastore,
+        names: Vec<indradb::Identifier>,
+        prefetch: bool,
+        sum: AtomicU64,
+    }
+
+    impl PropertySumMapper {
+        fn add(&self, value: &serde_json::Value) {
+            self.sum.fetch_add(value.as_u64().unwrap(), Ordering::SeqCst);
+        }
+    }
+
+    impl VertexMapper for PropertySumMapper {
+        fn query_limit(&self) -> u32 {
+            7
+        }
+
+        fn prefetch_properties(&self) -> Vec<indradb::Identifier> {
+            if self.prefetch {
+                self.names.clone()
+            } else {
+                Vec::new()
+            }
+        }
+
+        fn map(&self, vertex: indradb::Vertex) -> Result<(), Error> {
+            for name in &self.names {
+                let q = indradb::SpecificVertexQuery::single(vertex.id).property(name.clone());
+                for property in self.datastore.get_vertex_properties(q)? {
+                    self.add(&property.value);
+                }
+            }
+            Ok(())
+        }
+
+        fn map_with_properties(&self, vertex_properties: indradb::VertexProperties) -> Result<(), Error> {
+            for property in vertex_properties.props {
+                self.add(&property.value);
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn should_map_with_prefetched_properties() {
+        let datastore = indradb::MemoryDatastore::default();
+        let t = indradb::Identifier::new("test_vertex_type").unwrap();
+        let names: Vec<indradb::Identifier> = ["a", "b", "c"]
+            .iter()
+            .map(|name| indradb::Identifier::new(*name).unwrap())
+            .collect();
+
+        for i in 0..50u64 {
+            let id = datastore.create_vertex_from_type(t.clone()).unwrap();
+            // Leave some properties unset, so that not every vertex has all
+            // of the prefetched properties
+            for (j, name) in names.iter().enumerate() {
+                if (i + j as u64) % 4 != 0 {
+                    let q = indradb::SpecificVertexQuery::single(id).property(name.clone());
+                    datastore
+                        .set_vertex_properties(q, serde_json::json!(i * 10 + j as u64))
+                        .unwrap();
+                }
+            }
+        }
+
+        let run = |prefetch: bool| -> u64 {
+            let mapper = Arc::new(PropertySumMapper {
+                datastore: datastore.clone(),
+                names: names.clone(),
+                prefetch,
+                sum: AtomicU64::new(0),
+            });
+            let pool = ThreadPool::new(4);
+            map_with_pool(mapper.clone(), Arc::new(datastore.clone()), &pool).unwrap();
+            mapper.sum.load(Ordering::SeqCst)
+        };
+
+        let sum = run(false);
+        assert!(sum > 0);
+        assert_eq!(run(true), sum);
+    }
+
+    struct IdCollectingMapper {
+        id_range: (uuid::Uuid, uuid::Uuid),
+        mapped: Mutex<HashSet<uuid::Uuid>>,
+    }
+
+    impl VertexMapper for IdCollectingMapper {
+        fn query_limit(&self) -> u32 {
+            3
+        }
+
+        fn id_range(&self) -> Option<(uuid::Uuid, uuid::Uuid)> {
+            Some(self.id_range)
+        }
+
+        fn map(&self, vertex: indradb::Vertex) -> Result<(), Error> {
+            self.mapped.lock().unwrap().insert(vertex.id);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn should_map_id_range() {
+        let datastores: Vec<Arc<dyn Datastore + Send + Sync + 'static>> = vec![
+            Arc::new(indradb::MemoryDatastore::default()),
+            Arc::new(indradb::RocksdbConfig::default().temporary().open("ignored").unwrap()),
+        ];
+        let t = indradb::Identifier::new("test_vertex_type").unwrap();
+
+        for datastore in datastores {
+            for i in 0..=20 {
+                let vertex = indradb::Vertex::with_id(uuid::Uuid::from_u128(i), t.clone());
+                datastore.create_vertex(&vertex).unwrap();
+            }
+
+            for (start, end) in [(5, 15), (0, 3)] {
+                let mapper = Arc::new(IdCollectingMapper {
+                    id_range: (uuid::Uuid::from_u128(start), uuid::Uuid::from_u128(end)),
+                    mapped: Mutex::new(HashSet::new()),
+                });
+                assert_eq!(count(&*mapper, &*datastore).unwrap(), (end - start) as u64);
+                map_with_pool(mapper.clone(), datastore.clone(), &ThreadPool::new(2)).unwrap();
+
+                let expected: HashSet<uuid::Uuid> = (start..end).map(uuid::Uuid::from_u128).collect();
+                assert_eq!(*mapper.mapped.lock().unwrap(), expected);
+            }
+        }
+    }
+
+    struct FilteredMapper {
+        t_filter: Option<indradb::Identifier>,
+        id_range: Option<(uuid::Uuid, uuid::Uuid)>,
+        max_vertices: Option<u64>,
+        mapped: AtomicU64,
+    }
+
+    impl VertexMapper for FilteredMapper {
+        fn query_limit(&self) -> u32 {
+            7
+        }
+
+        fn t_filter(&self) -> Option<indradb::Identifier> {
+            self.t_filter.clone()
+        }
+
+        fn id_range(&self) -> Option<(uuid::Uuid, uuid::Uuid)> {
+            self.id_range
+        }
+
+        fn max_vertices(&self) -> Option<u64> {
+            self.max_vertices
+        }
+
+        fn map(&self, _vertex: indradb::Vertex) -> Result<(), Error> {
+            self.mapped.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn should_export_vertices_as_ndjson() {
+        let datastore = indradb::MemoryDatastore::default();
+        let t1 = indradb::Identifier::new("test_vertex_type_1").unwrap();
+        let t2 = indradb::Identifier::new("test_vertex_type_2").unwrap();
+        let name = indradb::Identifier::new("test_property").unwrap();
+        for i in 1..=20 {
+            let t = if i % 4 == 0 { t1.clone() } else { t2.clone() };
+            let vertex = indradb::Vertex::with_id(uuid::Uuid::from_u128(i), t);
+            datastore.create_vertex(&vertex).unwrap();
+            if i % 2 == 0 {
+                let q = indradb::SpecificVertexQuery::single(vertex.id).property(name.clone());
+                datastore.set_vertex_properties(q, serde_json::json!(i as u64)).unwrap();
+            }
+        }
+
+        let mut buf = Vec::new();
+        assert_eq!(export_ndjson(&datastore, &mut buf, Some(t1.clone())).unwrap(), 5);
+        let lines: Vec<serde_json::Value> = String::from_utf8(buf)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 5);
+        for (line, i) in lines.iter().zip((4..=20).step_by(4)) {
+            assert_eq!(
+                line,
+                &serde_json::json!({
+                    "id": uuid::Uuid::from_u128(i),
+                    "type": t1.as_str(),
+                    "properties": {"test_property": i as u64},
+                })
+            );
+        }
+
+        let mut buf = Vec::new();
+        assert_eq!(export_ndjson(&datastore, &mut buf, None).unwrap(), 20);
+        let output = String::from_utf8(buf).unwrap();
+        assert_eq!(output.lines().count(), 20);
+        let first: serde_json::Value = serde_json::from_str(output.lines().next().unwrap()).unwrap();
+        assert_eq!(first["properties"], serde_json::json!({}));
+    }
+
+    #[test]
+    fn should_count_the_same_vertices_as_a_full_map() {
+        let datastore = Arc::new(indradb::MemoryDatastore::default());
+        let t1 = indradb::Identifier::new("test_vertex_type_1").unwrap();
+        let t2 = indradb::Identifier::new("test_vertex_type_2").unwrap();
+        for i in 1..=50 {
+            let t = if i % 3 == 0 { t1.clone() } else { t2.clone() };
+            let vertex = indradb::Vertex::with_id(uuid::Uuid::from_u128(i), t);
+            datastore.create_vertex(&vertex).unwrap();
+        }
+
+        let id_range = Some((uuid::Uuid::from_u128(10), uuid::Uuid::from_u128(40)));
+        let filters = vec![
+            (None, None, None),
+            (Some(t1.clone()), None, None),
+            (Some(t2), id_range, None),
+            (None, id_range, Some(12)),
+            (Some(t1), None, Some(100)),
+        ];
+        for (t_filter, id_range, max_vertices) in filters {
+            let mapper = Arc::new(FilteredMapper {
+                t_filter,
+                id_range,
+                max_vertices,
+                mapped: AtomicU64::new(0),
+            });
+            let counted = count(&*mapper, &*datastore).unwrap();
+            map_with_pool(mapper.clone(), datastore.clone(), &ThreadPool::new(2)).unwrap();
+            assert_eq!(counted, mapper.mapped.load(Ordering::SeqCst));
+        }
+    }
+
+    struct CountWritingMapper {
+        count: AtomicU64,
+    }
+
+    impl VertexMapper for CountWritingMapper {
+        fn map(&self, _vertex: indradb::Vertex) -> Result<(), Error> {
+            self.count.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn finalize(&self, datastore: &(dyn indradb::Datastore + Send + Sync + 'static)) -> Result<(), Error> {
+            let name = indradb::Identifier::new("vertex_count").unwrap();
+            let q = indradb::RangeVertexQuery::new().property(name);
+            datastore.set_vertex_properties(q, serde_json::json!(self.count.load(Ordering::SeqCst)))?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn should_finalize() {
+        let datastore = indradb::MemoryDatastore::default();
+        let t = indradb::Identifier::new("test_vertex_type").unwrap();
+        for _ in 0..10 {
+            datastore.create_vertex_from_type(t.clone()).unwrap();
+        }
+
+        let mapper = Arc::new(CountWritingMapper {
+            count: AtomicU64::new(0),
+        });
+        let pool = ThreadPool::new(2);
+        map_with_pool(mapper, Arc::new(datastore.clone()), &pool).unwrap();
+
+        let name = indradb::Identifier::new("vertex_count").unwrap();
+        let properties = datastore
+            .get_vertex_properties(indradb::RangeVertexQuery::new().property(name))
+            .unwrap();
+        assert_eq!(properties.len(), 10);
+        for property in properties {
+            assert_eq!(property.value, serde_json::json!(10));
+        }
+    }
+
+    struct CheckpointingMapper {
+        fail_on: Option<uuid::Uuid>,
+        mapped: Mutex<HashSet<uuid::Uuid>>,
+        checkpoints: Mutex<Vec<(uuid::Uuid, HashSet<uuid::Uuid>)>>,
+    }
+
+    impl CheckpointingMapper {
+        fn new(fail_on: Option<uuid::Uuid>, mapped: HashSet<uuid::Uuid>) -> Self {
+            Self {
+                fail_on,
+                mapped: Mutex::new(mapped),
+                checkpoints: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    impl VertexMapper for CheckpointingMapper {
+        fn query_limit(&self) -> u32 {
+            3
+        }
+
+        fn checkpoint_every(&self) -> Option<u32> {
+            Some(1)
+        }
+
+        fn checkpoint(&self, last_id: uuid::Uuid) {
+            let mapped = self.mapped.lock().unwrap().clone();
+            self.checkpoints.lock().unwrap().push((last_id, mapped));
+        }
+
+        fn map(&self, vertex: indradb::Vertex) -> Result<(), Error> {
+            if Some(vertex.id) == self.fail_on {
+                return Err(Error::Other("simulated crash".into()));
+            }
+            self.mapped.lock().unwrap().insert(vertex.id);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn should_resume_from_checkpoint() {
+        let datastore = indradb::MemoryDatastore::default();
+        let t = indradb::Identifier::new("test_vertex_type").unwrap();
+        for i in 1..=20 {
+            let vertex = indradb::Vertex::with_id(uuid::Uuid::from_u128(i), t.clone());
+            datastore.create_vertex(&vertex).unwrap();
+        }
+        let pool = ThreadPool::new(2);
+
+        let single_run = Arc::new(CheckpointingMapper::new(None, HashSet::new()));
+        map_with_pool(single_run.clone(), Arc::new(datastore.clone()), &pool).unwrap();
+
+        let crashed_run = Arc::new(CheckpointingMapper::new(
+            Some(uuid::Uuid::from_u128(11)),
+            HashSet::new(),
+        ));
+        assert!(map_with_pool(crashed_run.clone(), Arc::new(datastore.clone()), &pool).is_err());
+        let (last_id, partial) = crashed_run.checkpoints.lock().unwrap().pop().unwrap();
+        assert!(partial.len() < 20);
+
+        let resumed_run = Arc::new(CheckpointingMapper::new(None, partial));
+        map_from(resumed_run.clone(), Arc::new(datastore), Some(last_id), &pool).unwrap();
+        assert_eq!(*resumed_run.mapped.lock().unwrap(), *single_run.mapped.lock().unwrap());
+    }
+
+    #[test]
+    fn should_collect_distinct_values() {
+        let datastore = indradb::MemoryDatastore::default();
+        for i in 0..100 {
+            let t = indradb::Identifier::new(format!("test_vertex_type_{}", i % 5)).unwrap();
+            datastore.create_vertex_from_type(t).unwrap();
+        }
+
+        let num_emits = Arc::new(AtomicUsize::new(0));
+        let mapper = {
+            let num_emits = num_emits.clone();
+            Arc::new(DistinctValues::new(move |vertex: indradb::Vertex| {
+                num_emits.fetch_add(1, Ordering::SeqCst);
+                Ok(vec![
+                    serde_json::json!(vertex.t.as_str()),
+                    serde_json::json!({"b": 1, "a": 2}),
+                ])
+            }))
+        };
+        let pool = ThreadPool::new(4);
+        map_with_pool(mapper.clone(), Arc::new(datastore), &pool).unwrap();
+
+        assert_eq!(num_emits.load(Ordering::SeqCst), 100);
+        let mut values = mapper.values();
+        values.sort_by_key(|value| value.to_string());
+        let mut expected: Vec<serde_json::Value> = (0..5)
+            .map(|i| serde_json::json!(format!("test_vertex_type_{}", i)))
+            .collect();
+        expected.push(serde_json::json!({"a": 2, "b": 1}));
+        expected.sort_by_key(|value| value.to_string());
+        assert_eq!(values, expected);
+    }
+
+    #[test]
+    fn should_reduce_groups_by_key() {
+        let datastore = indradb::MemoryDatastore::default();
+        for i in 0..100 {
+            let t = indradb::Identifier::new(format!("test_vertex_type_{}", i % 3)).unwrap();
+            datastore.create_vertex_from_type(t).unwrap();
+        }
+
+        let mapper = Arc::new(GroupBy::new(
+            |vertex: indradb::Vertex| Ok((vertex.t.into_string(), serde_json::json!(1))),
+            |_: &str, values: Vec<serde_json::Value>| {
+                Ok(serde_json::json!(values
+                    .iter()
+                    .filter_map(|value| value.as_u64())
+                    .sum::<u64>()))
+            },
+        ));
+        let pool = ThreadPool::new(4);
+        map_with_pool(mapper.clone(), Arc::new(datastore), &pool).unwrap();
+
+        let reduced = mapper.reduce_json().unwrap();
+        let expected: serde_json::Map<String, serde_json::Value> = vec![
+            ("test_vertex_type_0".to_string(), serde_json::json!(34)),
+            ("test_vertex_type_1".to_string(), serde_json::json!(33)),
+            ("test_vertex_type_2".to_string(), serde_json::json!(33)),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(reduced, expected);
+        assert!(mapper.reduce().unwrap().is_empty());
+    }
+
+    #[test]
+    fn should_combine_groups_while_mapping() {
+        let datastore = Arc::new(indradb::MemoryDatastore::default());
+        for i in 0..100 {
+            let t = indradb::Identifier::new(format!("test_vertex_type_{}", i % 3)).unwrap();
+            datastore.create_vertex_from_type(t).unwrap();
+        }
+
+        let reduced_values = Arc::new(AtomicUsize::new(0));
+        let new_mapper = || {
+            let reduced_values = reduced_values.clone();
+            GroupBy::new(
+                |vertex: indradb::Vertex| Ok((vertex.t.into_string(), 1_u64)),
+                move |_: &str, values: Vec<u64>| {
+                    reduced_values.fetch_add(values.len(), Ordering::SeqCst);
+                    Ok(values.iter().sum())
+                },
+            )
+        };
+        let pool = ThreadPool::new(4);
+
+        let mapper = Arc::new(new_mapper());
+        map_with_pool(mapper.clone(), datastore.clone(), &pool).unwrap();
+        let expected = mapper.reduce().unwrap();
+        assert_eq!(reduced_values.swap(0, Ordering::SeqCst), 100);
+
+        let mapper = Arc::new(new_mapper().combine(|a, b| Ok(a + b)));
+        map_with_pool(mapper.clone(), datastore, &pool).unwrap();
+        assert_eq!(mapper.reduce().unwrap(), expected);
+        assert_eq!(reduced_values.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn should_average_across_uneven_chunks() {
+        let values: Vec<f64> = (1..=20).map(|i| f64::from(i * i)).collect();
+        let expected = values.iter().sum::<f64>() / values.len() as f64;
+
+        let mut chunks = Vec::new();
+        let mut rest = &values[..];
+        for len in [1, 7, 2, 10] {
+            let (chunk, remaining) = rest.split_at(len);
+            chunks.push(Weighted::new(chunk.iter().sum::<f64>() / len as f64, len as f64));
+            rest = remaining;
+        }
+        let mean = Weighted::mean(chunks);
+        assert!((mean.value - expected).abs() < 1e-9);
+        assert_eq!(mean.weight, 20.0);
+        assert_eq!(Weighted::mean(Vec::new()), Weighted::new(0.0, 0.0));
+
+        let datastore = indradb::MemoryDatastore::default();
+        for i in 1..=20 {
+            let t = indradb::Identifier::new(format!("test_vertex_type_{}", i % 2)).unwrap();
+            datastore
+                .create_vertex(&indradb::Vertex::with_id(uuid::Uuid::from_u128(i), t))
+                .unwrap();
+        }
+
+        // Vertices are weighted by their ID, so each type's mean is pulled
+        // towards its later vertices
+        let mapper = Arc::new(GroupBy::weighted_mean(|vertex: indradb::Vertex| {
+            let id = vertex.id.as_u128() as f64;
+            Ok((vertex.t.into_string(), Weighted::new(id * 10.0, id)))
+        }));
+        let pool = ThreadPool::new(4);
+        map_with_pool(mapper.clone(), Arc::new(datastore), &pool).unwrap();
+
+        let reduced = mapper.reduce().unwrap();
+        for (key, ids) in [
+            ("test_vertex_type_0", (2..=20).step_by(2)),
+            ("test_vertex_type_1", (1..=19).step_by(2)),
+        ] {
+            let ids: Vec<f64> = ids.map(f64::from).collect();
+            let weight: f64 = ids.iter().sum();
+            let expected = ids.iter().map(|id| id * id * 10.0).sum::<f64>() / weight;
+            assert!((reduced[key].value - expected).abs() < 1e-9);
+            assert_eq!(reduced[key].weight, weight);
+        }
+    }
+
+    #[test]
+    fn should_reduce_groups_of_any_value_type() {
+        let datastore = indradb::MemoryDatastore::default();
+        for i in 0..10 {
+            let t = indradb::Identifier::new(format!("test_vertex_type_{}", i % 2)).unwrap();
+            datastore.create_vertex_from_type(t).unwrap();
+        }
+
+        let mapper = Arc::new(GroupBy::new(
+            |vertex: indradb::Vertex| Ok((vertex.t.into_string(), 0.5_f64)),
+            |_: &str, values: Vec<f64>| Ok(values.iter().sum()),
+        ));
+        let pool = ThreadPool::new(4);
+        map_with_pool(mapper.clone(), Arc::new(datastore), &pool).unwrap();
+
+        let reduced = mapper.reduce().unwrap();
+        assert_eq!(reduced.len(), 2);
+        assert_eq!(reduced["test_vertex_type_0"], 2.5);
+        assert_eq!(reduced["test_vertex_type_1"], 2.5);
+    }
+
+    #[test]
+    fn should_map_with_a_shared_pool() {
+        let datastore = indradb::MemoryDatastore::default();
+        let t = indradb::Identifier::new("test_vertex_type").unwrap();
+        for i in 1..=20 {
+            let vertex = indradb::Vertex::with_id(uuid::Uuid::from_u128(i), t.clone());
+            datastore.create_vertex(&vertex).unwrap();
+        }
+        let datastore = Arc::new(datastore);
+
+        // Occupy one of the pool's threads with unrelated work that outlives
+        // every map, which would deadlock if the pool were joined
+        let pool = ThreadPool::new(2);
+        let (sender, receiver) = mpsc::channel::<()>();
+        pool.execute(move || {
+            receiver.recv().unwrap();
+        });
+
+        for end in [5, 10, 15] {
+            let mapper = Arc::new(IdCollectingMapper {
+                id_range: (uuid::Uuid::from_u128(1), uuid::Uuid::from_u128(end)),
+                mapped: Mutex::new(HashSet::new()),
+            });
+            map_with_pool(mapper.clone(), datastore.clone(), &pool).unwrap();
+            let expected: HashSet<uuid::Uuid> = (1..end).map(uuid::Uuid::from_u128).collect();
+            assert_eq!(*mapper.mapped.lock().unwrap(), expected);
+        }
+
+        sender.send(()).unwrap();
+        pool.join();
+    }
+
+    struct EnormousPageMapper {
+        mapped: Mutex<HashSet<uuid::Uuid>>,
+    }
+
+    impl VertexMapper for EnormousPageMapper {
+        fn query_limit(&self) -> u32 {
+            u32::MAX
+        }
+
+        fn map(&self, vertex: indradb::Vertex) -> Result<(), Error> {
+            self.mapped.lock().unwrap().insert(vertex.id);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn should_cap_query_limit() {
+        assert_eq!(effective_query_limit(0), 1);
+        assert_eq!(effective_query_limit(100), 100);
+        assert_eq!(effective_query_limit(u32::MAX), MAX_QUERY_LIMIT);
+
+        let datastore = indradb::MemoryDatastore::default();
+        let t = indradb::Identifier::new("test_vertex_type").unwrap();
+        for _ in 0..20 {
+            datastore.create_vertex_from_type(t.clone()).unwrap();
+        }
+
+        let mapper = Arc::new(EnormousPageMapper {
+            mapped: Mutex::new(HashSet::new()),
+        });
+        let pool = ThreadPool::new(2);
+        map_with_pool(mapper.clone(), Arc::new(datastore), &pool).unwrap();
+        assert_eq!(mapper.mapped.lock().unwrap().len(), 20);
+    }
+
+    struct FailingMapper {
+        fail_on: uuid::Uuid,
+    }
+
+    impl VertexMapper for FailingMapper {
+        fn keyed_errors(&self) -> bool {
+            true
+        }
+
+        fn map(&self, vertex: indradb::Vertex) -> Result<(), Error> {
+            if vertex.id == self.fail_on {
+                Err(Error::InvalidArgument("bad vertex".to_string()))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[test]
+    fn should_name_the_vertex_of_a_failed_map() {
+        let datastore = indradb::MemoryDatastore::default();
+        let t = indradb::Identifier::new("test_vertex_type").unwrap();
+        let ids: Vec<_> = (0..10)
+            .map(|_| datastore.create_vertex_from_type(t.clone()).unwrap())
+            .collect();
+
+        let mapper = Arc::new(FailingMapper { fail_on: ids[5] });
+        let pool = ThreadPool::new(2);
+        let err = map_with_pool(mapper, Arc::new(datastore), &pool).unwrap_err();
+        assert!(matches!(err, Error::Map { id, .. } if id == ids[5]));
+        assert!(err.to_string().contains(&ids[5].to_string()));
+        assert!(err.to_string().contains("bad vertex"));
+    }
+
+    struct AlwaysFailingMapper {
+        num_maps: AtomicUsize,
+    }
+
+    impl VertexMapper for AlwaysFailingMapper {
+        fn keyed_errors(&self) -> bool {
+            true
+        }
+
+        fn map(&self, _vertex: indradb::Vertex) -> Result<(), Error> {
+            self.num_maps.fetch_add(1, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(1));
+            Err(Error::InvalidArgument("bad vertex".to_string()))
+        }
+    }
+
+    #[test]
+    fn should_stop_cleanly_after_errors() {
+        let datastore = indradb::MemoryDatastore::default();
+        let t = indradb::Identifier::new("test_vertex_type").unwrap();
+        let ids: HashSet<_> = (0..1000)
+            .map(|_| datastore.create_vertex_from_type(t.clone()).unwrap())
+            .collect();
+        let datastore = Arc::new(datastore);
+        let pool = ThreadPool::new(8);
+
+        for _ in 0..20 {
+            let mapper = Arc::new(AlwaysFailingMapper {
+                num_maps: AtomicUsize::new(0),
+            });
+            let err = map_with_pool(mapper.clone(), datastore.clone(), &pool).unwrap_err();
+            assert!(matches!(err, Error::Map { id, ref source }
+                if ids.contains(&id) && matches!(**source, Error::InvalidArgument(_))));
+            // Queued map operations are skipped once one has failed
+            assert!(mapper.num_maps.load(Ordering::SeqCst) < 100);
+            assert_eq!(pool.queued_count(), 0);
+        }
     }
 }
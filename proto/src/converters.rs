@@ -185,6 +185,7 @@ impl From<indradb::VertexQuery> for crate::VertexQuery {
                     limit: q.limit,
                     t: q.t.map(|t| t.into()),
                     start_id: q.start_id.map(|start_id| start_id.into()),
+                    high_id: q.high_id.map(|high_id| high_id.into()),
                 }),
                 indradb::VertexQuery::Specific(q) => crate::VertexQueryVariant::Specific(crate::SpecificVertexQuery {
                     ids: q.ids.into_iter().map(|id| id.into()).collect(),
@@ -243,6 +244,7 @@ impl TryInto<indradb::VertexQuery> for crate::VertexQuery {
                 limit: q.limit,
                 t: q.t.map(|t| t.try_into()).transpose()?,
                 start_id: q.start_id.map(|start_id| start_id.try_into()).transpose()?,
+                high_id: q.high_id.map(|high_id| high_id.try_into()).transpose()?,
             }),
             crate::VertexQueryVariant::Specific(q) => {
                 let ids: Result<Vec<Uuid>, ConversionError> = q.ids.into_iter().map(|id| id.try_into()).collect();